@@ -1,13 +1,18 @@
 extern crate lalrpop;
+#[cfg(feature = "phl")]
 extern crate cmake;
+#[cfg(feature = "phl")]
 use cmake::Config;
 
 fn main() {
     lalrpop::process_root().unwrap();
 
-    let dst = Config::new("phl").build();       
+    #[cfg(feature = "phl")]
+    {
+        let dst = Config::new("phl").build();
 
-    println!("cargo:rustc-link-search=native={}", dst.display());
-    println!("cargo:rustc-link-lib=static=phl");   
-    println!("cargo:rustc-link-lib=static=stdc++");
-} 
+        println!("cargo:rustc-link-search=native={}", dst.display());
+        println!("cargo:rustc-link-lib=static=phl");
+        println!("cargo:rustc-link-lib=static=stdc++");
+    }
+}
@@ -0,0 +1,143 @@
+// Times surjective_closure on synthetic structures of growing size.
+//
+// Usage: closure-bench [thread_count]
+
+#include <closure.hpp>
+#include <cwf.hpp>
+#include <partial_structure.hpp>
+
+#include <chrono>
+#include <cstdio>
+#include <cstdlib>
+#include <functional>
+#include <string>
+#include <vector>
+
+using std::size_t;
+using std::vector;
+
+namespace {
+
+// Adds op(args) as a new element, like define_operation does.
+size_t define(partial_structure& pstruct, const operation& op, vector<size_t> args) {
+    for (size_t& arg : args) {
+        arg = get_representative(pstruct.equality, arg);
+    }
+    size_t el = add_carrier_element(pstruct, op.cod);
+    args.push_back(el);
+    insert_row(pstruct, op, std::move(args));
+    return el;
+}
+
+struct scenario {
+    std::string name;
+    vector<size_t> sizes;
+    std::function<partial_structure(size_t)> build;
+    vector<sequent> sequents;
+};
+
+sort s{"s"};
+predicate p{"p", {s, s}};
+operation c{"c", {}, s};
+operation f{"f", {s}, s};
+
+term x = "x", y = "y", z = "z";
+
+// A cycle of p of length n, which antisymmetry and transitivity collapse into
+// a single element.
+partial_structure cycle(size_t n) {
+    partial_structure pstruct;
+    for (size_t i = 0; i != n; ++i) {
+        add_carrier_element(pstruct, s);
+    }
+    for (size_t i = 0; i != n; ++i) {
+        insert_row(pstruct, p, {i, (i + 1) % n});
+    }
+    return pstruct;
+}
+
+// Two chains c, f(c), f(f(c)), ... of length n, which functionality merges
+// one pair per round.
+partial_structure chains(size_t n) {
+    partial_structure pstruct;
+    pstruct.relations[c];
+    pstruct.relations[f];
+    for (size_t chain = 0; chain != 2; ++chain) {
+        size_t el = add_carrier_element(pstruct, s);
+        insert_row(pstruct, c, {el});
+        for (size_t i = 0; i != n; ++i) {
+            size_t next = add_carrier_element(pstruct, s);
+            insert_row(pstruct, f, {el, next});
+            el = next;
+        }
+    }
+    return pstruct;
+}
+
+// The contexts bool, bool.bool, ... up to length n with the weakenings
+// between them, their composition and bool substituted along it, defined like
+// the cwf model defines them.
+partial_structure telescope(size_t n) {
+    using namespace cwf;
+    partial_structure pstruct(cwf_signature);
+    auto morph = [&](const operation& op, vector<size_t> args) {
+        size_t el = define(pstruct, op, std::move(args));
+        define(pstruct, dom, {el});
+        define(pstruct, cod, {el});
+        return el;
+    };
+    auto type = [&](const operation& op, vector<size_t> args) {
+        size_t el = define(pstruct, op, std::move(args));
+        define(pstruct, ty_ctx, {el});
+        return el;
+    };
+
+    size_t empty = define(pstruct, empty_ctx, {});
+    size_t ctx = empty;
+    size_t composite = morph(id, {empty});
+    for (size_t i = 0; i != n; ++i) {
+        size_t bool_ty = type(bool_, {ctx});
+        size_t weakening = morph(wkn, {bool_ty});
+        ctx = define(pstruct, ctx_ext, {bool_ty});
+        composite = morph(comp, {weakening, composite});
+    }
+    type(subst_ty, {composite, type(bool_, {empty})});
+    return pstruct;
+}
+
+}
+
+int main(int argc, char** argv) {
+    size_t thread_count = argc > 1 ? std::strtoul(argv[1], nullptr, 10) : 1;
+
+    vector<scenario> scenarios = {
+        {
+            "cycle", {16, 32, 64}, cycle,
+            {p(x, y) && p(y, x) |= x % y, p(x, y) && p(y, z) |= p(x, z)},
+        },
+        {"chains", {64, 128, 256}, chains, {}},
+        {"telescope", {16, 64, 256}, telescope, cwf::cwf.surjective_axioms},
+    };
+
+    std::printf("%-10s %6s %10s %7s %12s %8s\n",
+                "scenario", "n", "time (ms)", "rounds", "joined rows", "merges");
+    for (const scenario& sc : scenarios) {
+        for (size_t n : sc.sizes) {
+            // the fastest of a few runs, to reduce noise
+            double best = 0;
+            closure_stats stats;
+            for (int run = 0; run != 3; ++run) {
+                partial_structure pstruct = sc.build(n);
+                auto start = std::chrono::steady_clock::now();
+                stats = surjective_closure(sc.sequents, pstruct, thread_count);
+                std::chrono::duration<double, std::milli> elapsed =
+                    std::chrono::steady_clock::now() - start;
+                if (run == 0 || elapsed.count() < best) {
+                    best = elapsed.count();
+                }
+            }
+            std::printf("%-10s %6zu %10.2f %7zu %12zu %8zu\n",
+                        sc.name.c_str(), n, best, stats.rounds, stats.joined_rows, stats.merges);
+        }
+    }
+}
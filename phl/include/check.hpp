@@ -0,0 +1,11 @@
+#pragma once
+
+#include <phl.hpp>
+
+// Checks that theory only uses the sorts, predicates and operations of its
+// signature and applies them to as many arguments of the right sorts as they
+// take, and that the sort of every variable of a sequent or conflict can be
+// inferred from where it occurs. The conclusions of surjective axioms and the
+// witnesses of conflicts may only mention terms that their premise binds.
+// Throws malformed_sequent naming the sequent and the problem otherwise.
+void check_theory(const phl_theory& theory);
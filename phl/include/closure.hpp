@@ -2,8 +2,96 @@
 
 #include <phl.hpp>
 #include <partial_structure.hpp>
+#include <chrono>
+#include <optional>
+#include <stdexcept>
+#include <string>
 
-void surjective_closure(
+// Thrown when a sequent can not be evaluated against a partial structure, for
+// example because its conclusion mentions a term not bound by its premise.
+struct malformed_sequent : std::invalid_argument {
+    using std::invalid_argument::invalid_argument;
+};
+
+std::string term_to_string(const term& t);
+std::string sequent_to_string(const sequent& seq);
+std::string conflict_to_string(const conflict& c);
+
+// Counters of a sequent over all rounds of a closure.
+struct sequent_stats {
+    // the sequent, or the functionality of an operation
+    std::string name;
+    // rows of the relations in the premise that were tried
+    std::size_t visited_rows = 0;
+    // rows of the join of the premise, i.e. its matches
+    std::size_t joined_rows = 0;
+    // equivalence classes merged and predicate rows added by the conclusion
+    std::size_t merges = 0;
+    std::size_t facts = 0;
+    // spent evaluating the premise and conclusion, but not merging
+    std::chrono::nanoseconds time{0};
+};
+
+// Counters of one or more surjective_closures.
+struct closure_stats {
+    std::size_t closures = 0;
+    // rounds of evaluating all sequents, including the last one of each
+    // closure, which derives nothing new
+    std::size_t rounds = 0;
+    // rows of the joins of the premises
+    std::size_t joined_rows = 0;
+    // equivalence classes that were merged
+    std::size_t merges = 0;
+    std::vector<sequent_stats> sequents;
+};
+
+// Bounds on a surjective_closure, each unbounded if empty.
+struct closure_limits {
+    // checked after every round that derived something, so a closure that
+    // reaches its fixpoint in max_rounds rounds does not fail
+    std::optional<std::size_t> max_rounds;
+    // checked before the first round, since the closure adds no elements;
+    // merged elements count as well
+    std::optional<std::size_t> max_carrier_size;
+    // checked like max_rounds
+    std::optional<std::chrono::steady_clock::time_point> deadline;
+};
+
+// Thrown when a closure exceeds one of its limits. The structure is left
+// canonical and everything in it follows from the axioms, but it is not
+// closed under them.
+struct limit_exceeded : std::runtime_error {
+    limit_exceeded(const std::string& what, closure_stats stats)
+        : std::runtime_error(what), stats(std::move(stats)) {}
+
+    // of the rounds up to the one after which the limit was exceeded
+    closure_stats stats;
+};
+
+// Adds the counters of from to into. Sequents are matched by name, sequents
+// new to into are added at the end.
+void accumulate(closure_stats& into, const closure_stats& from);
+
+// A match of conflicts[conflict], in which its witness is bound to witness.
+struct conflict_match {
+    std::size_t conflict;
+    std::size_t witness;
+};
+
+// The matches of the conflicts in pstruct, ordered by conflict and then by
+// witness, with one match per witness. Since the sequents never remove
+// anything, the matches only grow under surjective_closure.
+std::vector<conflict_match> find_conflicts(
+    const std::vector<conflict>& conflicts,
+    const partial_structure& pstruct
+);
+
+// Each round evaluates the sequents on up to thread_count threads. The result
+// does not depend on the number of threads. Throws limit_exceeded if the
+// fixpoint is not reached within limits.
+closure_stats surjective_closure(
     const std::vector<sequent>& surjective_sequents,
-    partial_structure& pstruct
+    partial_structure& pstruct,
+    std::size_t thread_count = 1,
+    const closure_limits& limits = {}
 );
@@ -38,9 +38,25 @@ const operation
     // G |- af : bar(false(G))(A)
     bool_elim = {"bool_elim", {ctx, ty, tm, tm}, tm};
 
+// Judgements about types that are not expressible as equalities.
+const predicate
+    // any two terms of the type are equal
+    is_prop = {"is_prop", {ty}},
+    // the type is a proposition that has a term
+    is_contractible = {"is_contractible", {ty}},
+    // the type is a proposition or its terms have decidable equality
+    is_decidable = {"is_decidable", {ty}},
+    // the context has a closed substitution into it, so that no equation
+    // between closed terms can be assumed in it
+    is_consistent = {"is_consistent", {ctx}},
+    // the two types or terms can not be equal, because they are built from
+    // different constructors
+    ty_distinct = {"ty_distinct", {ty, ty}},
+    tm_distinct = {"tm_distinct", {tm, tm}};
+
 const phl_signature cwf_signature = {
     {ctx, mor, ty, tm},
-    {},
+    {is_prop, is_contractible, is_decidable, is_consistent, ty_distinct, tm_distinct},
     {
         dom, cod,
         id, comp,
@@ -97,6 +113,7 @@ const term
     aff0 = "aff0",
     b = "b",
     b0 = "b0",
+    b1 = "b1",
     c = "c",
     d = "d",
     r = "r",
@@ -115,7 +132,7 @@ const phl_theory cwf = {
         truth |= !ty_ctx(A),
         truth |= !tm_ty(a),
         ty_ctx(A) == dom(f) |= !subst_ty(f, A),
-        ty_ctx(a) == dom(f) |= !subst_tm(f, A),
+        ty_ctx(tm_ty(a)) == dom(f) |= !subst_tm(f, a),
         truth |= !empty_ctx(),
         truth |= !ctx_ext(A),
         truth |= !wkn(A),
@@ -172,14 +189,54 @@ const phl_theory cwf = {
         subst_tm(mor_ext(f, bool_(G), true_(D)), bool_elim(G, A, at, af)) -= subst_tm(f, at),
         subst_tm(mor_ext(f, bool_(G), false_(D)), bool_elim(G, A, at, af)) -= subst_tm(f, af),
         a == bool_elim(G, A, at, af) &&
-            subst_tm(mor_ext(id(G), A, true_(G)), a0) == at &&
-            subst_tm(mor_ext(id(G), A, false_(G)), a0) == af |=
+            subst_tm(mor_ext(id(G), bool_(G), true_(G)), a0) == at &&
+            subst_tm(mor_ext(id(G), bool_(G), false_(G)), a0) == af |=
             a == a0,
         subst_ty(f, bool_(G)) -= bool_(cod(f)),
         subst_tm(f, true_(G)) -= true_(cod(f)),
         subst_tm(f, false_(G)) -= false_(cod(f)),
-        subst_tm(mor_ext(f, A, b), bool_elim(G, A, at, af)) -=
-            subst_tm(mor_ext(id(cod(f)), b), bool_elim(cod(f), subst_ty(f, A), subst_tm(at), subst_tm(af)))
+        // <f, b> = <id, b> . f+ for the lifting f+ of f to G.bool
+        subst_tm(mor_ext(f, bool_(G), b), bool_elim(G, A, at, af)) -=
+            subst_tm(
+                mor_ext(id(cod(f)), bool_(cod(f)), b),
+                bool_elim(
+                    cod(f),
+                    subst_ty(mor_ext(comp(wkn(bool_(cod(f))), f), bool_(G), var(bool_(cod(f)))), A),
+                    subst_tm(f, at),
+                    subst_tm(f, af))),
+        // judgements are stable under substitution
+        is_prop(A) && B == subst_ty(f, A) |= is_prop(B),
+        is_contractible(A) && B == subst_ty(f, A) |= is_contractible(B),
+        is_decidable(A) && B == subst_ty(f, A) |= is_decidable(B),
+        // proof irrelevance
+        is_prop(A) && tm_ty(a) == A && tm_ty(b) == A |= a == b,
+        A == Eq(a, b) |= is_prop(A),
+        is_contractible(A) |= is_prop(A),
+        is_prop(A) && tm_ty(a) == A |= is_contractible(A),
+        is_prop(A) |= is_decidable(A),
+        A == bool_(G) |= is_decidable(A),
+        B == tm_ty(a) && is_decidable(B) && A == Eq(a, b) |= is_decidable(A),
+        // consistency of contexts built from inhabited types
+        G == empty_ctx() |= is_consistent(G),
+        is_consistent(G) && A == bool_(G) && D == ctx_ext(A) |= is_consistent(D),
+        is_consistent(G) && ty_ctx(A) == G && is_contractible(A) && D == ctx_ext(A) |= is_consistent(D),
+        // disjointness of constructors; terms are only distinct in consistent
+        // contexts, since equality reflection identifies them otherwise
+        is_consistent(G) && a == true_(G) && b == false_(G) |= tm_distinct(a, b),
+        B == Eq(a, b) && ty_ctx(B) == G && A == bool_(G) |= ty_distinct(A, B),
+        tm_distinct(a0, b0) && A == Eq(a0, a1) && B == Eq(b0, b1) |= ty_distinct(A, B),
+        tm_distinct(a1, b1) && A == Eq(a0, a1) && B == Eq(b0, b1) |= ty_distinct(A, B),
+        ty_distinct(A, B) |= ty_distinct(B, A),
+        tm_distinct(a, b) |= tm_distinct(b, a),
+        // an extension of a context in which true and false are equal is
+        // inconsistent as well
+        a == true_(G) && a == false_(G) && ty_ctx(A) == G && D == ctx_ext(A) &&
+            b0 == true_(D) && b1 == false_(D) |= b0 == b1
+    },
+    // conflicts, witnessed by the context that is inconsistent:
+    {
+        {a == true_(G) && a == false_(G), G},
+        {ty_distinct(A, A) && G == ty_ctx(A), G}
     }
 };
 
@@ -0,0 +1,163 @@
+#pragma once
+
+// C interface to the cwf partial structure, used by the Rust bindings.
+//
+// Functions never abort on bad input. Functions returning a phl_status
+// report failures through it, and a description of the most recent failure
+// on the calling thread can be retrieved with get_last_error.
+
+#include <cstddef>
+#include <cstdint>
+#include <partial_structure.hpp>
+#include <phl.hpp>
+
+enum phl_status : int {
+    PHL_OK = 0,
+    // an argument refers to an element that is not in the carrier
+    PHL_INVALID_ELEMENT = 1,
+    // an argument has a different sort than the operation expects
+    PHL_SORT_MISMATCH = 2,
+    // a required pointer argument was null
+    PHL_INVALID_ARGUMENT = 3,
+    // an axiom of the theory can not be evaluated
+    PHL_MALFORMED_SEQUENT = 4,
+    // any other failure, e.g. running out of memory
+    PHL_INTERNAL_ERROR = 5,
+    // rollback or commit without an active checkpoint
+    PHL_NO_CHECKPOINT = 6,
+    // compute_fixpoint gave up because of the limits set on the structure
+    PHL_LIMIT_EXCEEDED = 7,
+};
+
+extern "C" {
+
+const char* get_last_error();
+
+// Returns nullptr on failure, e.g. if the cwf axioms are malformed.
+partial_structure* create_cwf();
+void destroy_cwf(partial_structure* pstruct);
+
+// Lookups return nullptr if there is no sort/operation/predicate of that name
+// in the signature. Sort pointers returned by these functions are unique per
+// sort and can be compared.
+const sort* get_sort(const char* name);
+const char* get_sort_name(const sort* s, std::size_t* len);
+const operation* get_operation(const char* name);
+const char* get_operation_name(const operation* op, std::size_t* len);
+std::size_t get_operation_arity(const operation* op);
+const sort* get_operation_domain(const operation* op, std::size_t index);
+const sort* get_operation_codomain(const operation* op);
+const predicate* get_predicate(const char* name);
+const char* get_predicate_name(const predicate* pred, std::size_t* len);
+std::size_t get_predicate_arity(const predicate* pred);
+const sort* get_predicate_domain(const predicate* pred, std::size_t index);
+
+std::size_t get_carrier_size(const partial_structure* pstruct);
+phl_status are_equal(partial_structure* pstruct, std::size_t l, std::size_t r, bool* result);
+// On success stores the new element in result. On failure stores the index of
+// the offending argument in result instead.
+phl_status define_operation(
+    partial_structure* pstruct,
+    const operation* op,
+    const std::size_t* args,
+    std::size_t* result
+);
+phl_status define_predicate(
+    partial_structure* pstruct,
+    const predicate* pred,
+    const std::size_t* args
+);
+// Stores in result whether pred holds for args. Only reflects consequences of
+// the axioms after compute_fixpoint.
+phl_status predicate_holds(
+    partial_structure* pstruct,
+    const predicate* pred,
+    const std::size_t* args,
+    bool* result
+);
+// Evaluates the axioms on up to thread_count threads, 0 meaning as many as
+// there are cores. The result is the same for any number of threads.
+// Returns PHL_LIMIT_EXCEEDED if the fixpoint is not reached within the limits
+// of the structure. The structure then only lacks some consequences of the
+// axioms, and a later call continues from where this one stopped.
+phl_status compute_fixpoint(partial_structure* pstruct, std::size_t thread_count);
+
+// Limits of every later compute_fixpoint on a structure, 0 meaning no limit.
+// There are none initially.
+struct phl_closure_limits {
+    std::size_t max_rounds;
+    std::size_t max_carrier_size;
+    // measured from the start of each compute_fixpoint
+    std::uint64_t timeout_nanoseconds;
+};
+phl_status set_closure_limits(partial_structure* pstruct, const phl_closure_limits* limits);
+
+// Statistics summed over the compute_fixpoint calls on a structure since it
+// was created or they were reset. There is an entry for each axiom and for
+// the functionality of each operation.
+struct phl_closure_stats {
+    std::size_t closures;
+    std::size_t rounds;
+    std::size_t sequent_count;
+};
+struct phl_sequent_stats {
+    // valid until the next call of compute_fixpoint or reset_closure_stats
+    const char* name;
+    std::size_t name_len;
+    // rows of the relations in the premise that were tried
+    std::size_t visited_rows;
+    // matches of the premise
+    std::size_t joined_rows;
+    // equivalence classes merged and predicate rows added by the conclusion
+    std::size_t merges;
+    std::size_t facts;
+    // spent evaluating the sequent, not counting merging
+    std::uint64_t nanoseconds;
+};
+phl_status get_closure_stats(partial_structure* pstruct, phl_closure_stats* result);
+phl_status get_sequent_stats(
+    partial_structure* pstruct,
+    std::size_t index,
+    phl_sequent_stats* result
+);
+phl_status reset_closure_stats(partial_structure* pstruct);
+
+// Finds the matches of the conflicts of the theory, e.g. of true and false
+// being equal in a context, and stores their number in count. Like
+// predicate_holds, only reflects consequences of the axioms after
+// compute_fixpoint. There is one match per conflict and witness.
+phl_status find_conflicts(partial_structure* pstruct, std::size_t* count);
+struct phl_conflict {
+    // valid as long as the library is loaded
+    const char* name;
+    std::size_t name_len;
+    // the element where the structure is inconsistent, a context for cwf
+    std::size_t witness;
+    const sort* witness_sort;
+};
+// Returns the match of the most recent find_conflicts at index.
+phl_status get_conflict(partial_structure* pstruct, std::size_t index, phl_conflict* result);
+
+// Checkpoints nest. Rolling back undoes every change since the most recent
+// active checkpoint, including elements defined and equalities derived by
+// compute_fixpoint, and deactivates it. Committing deactivates it and keeps
+// the changes.
+phl_status push_checkpoint(partial_structure* pstruct);
+phl_status rollback_checkpoint(partial_structure* pstruct);
+phl_status commit_checkpoint(partial_structure* pstruct);
+
+// Removes the elements that are not needed for the root_count elements in
+// roots and renumbers the others, keeping their order. Needed are the roots
+// and the elements they are defined from, together with their dom, cod,
+// ty_ctx and tm_ty. On success new_ids, which must have room for
+// get_carrier_size elements, receives the new id of every element, or
+// PHL_REMOVED_ELEMENT if it was removed. Fails while a checkpoint is active.
+static const std::size_t PHL_REMOVED_ELEMENT = removed_element;
+phl_status collect_garbage(
+    partial_structure* pstruct,
+    const std::size_t* roots,
+    std::size_t root_count,
+    std::size_t* new_ids
+);
+
+}
@@ -12,6 +12,22 @@
 
 using relation = std::variant<predicate, operation>;
 
+// Changes to a partial structure, recorded while a checkpoint is active so
+// that they can be undone. Changes to equality are recorded by the union find
+// itself.
+struct carrier_addition {
+    std::size_t element;
+};
+struct relation_insert {
+    relation rel;
+    std::vector<std::size_t> row;
+};
+struct relation_erase {
+    relation rel;
+    std::vector<std::size_t> row;
+};
+using trail_entry = std::variant<carrier_addition, relation_insert, relation_erase>;
+
 struct partial_structure {
     partial_structure() {}
     explicit partial_structure(const struct phl_signature& sig);
@@ -24,6 +40,40 @@ struct partial_structure {
         relation,
         std::unordered_set<std::vector<std::size_t>>
     > relations;
+
+    std::vector<trail_entry> trail;
+    // sizes of trail and equality.links when the active checkpoints were
+    // taken, the most recent one last
+    std::vector<std::pair<std::size_t, std::size_t>> checkpoints;
 };
 
+// All changes to a partial structure outside of tests go through these, so
+// that they are recorded while a checkpoint is active.
+std::size_t add_carrier_element(partial_structure&, sort);
+// Returns whether row was not in the relation before.
+bool insert_row(partial_structure&, const relation&, std::vector<std::size_t> row);
+
 void compact_relations(partial_structure&);
+
+// Checkpoints nest. rollback undoes all changes since the most recent active
+// checkpoint and commit keeps them, both deactivate the checkpoint. They
+// return false if there is no active checkpoint.
+void checkpoint(partial_structure&);
+bool rollback(partial_structure&);
+bool commit(partial_structure&);
+
+// The new id of an element that was removed by compact_structure.
+constexpr std::size_t removed_element = static_cast<std::size_t>(-1);
+
+// Removes the elements that are not needed anymore and renumbers the others,
+// keeping their order. Needed are the roots, the arguments of the operations
+// that define needed elements and the values of the attributes, unary
+// operations like dom that are defined for every element, of needed
+// elements. Elements that are equal to a needed element are only kept if they
+// are roots. Returns the new id of every element. There must not be an active
+// checkpoint.
+std::vector<std::size_t> compact_structure(
+    partial_structure&,
+    const std::vector<std::size_t>& roots,
+    const std::vector<operation>& attributes
+);
@@ -142,10 +142,19 @@ struct phl_signature {
     std::vector<operation> operations;
 };
 
+// A premise that no structure satisfying the theory matches, as if it were a
+// sequent with falsity as its conclusion. The element that witness is bound
+// to in a match tells where the structure is inconsistent.
+struct conflict {
+    formula premise;
+    term witness;
+};
+
 struct phl_theory {
     phl_signature signature;
     std::vector<sequent> injective_axioms;
     std::vector<sequent> surjective_axioms;
+    std::vector<conflict> conflicts;
 };
 
 inline bool operator==(const predicate& pred1, const predicate& pred2) {
@@ -1,10 +1,20 @@
 #pragma once
 
+#include <utility>
 #include <vector>
 
-using union_find = std::vector<std::size_t>;
+// Parent pointers of the elements. While recording, every change of a parent
+// pointer, including those made by path compression, is appended to links as
+// the element and its previous parent so that it can be undone.
+struct union_find : std::vector<std::size_t> {
+    using std::vector<std::size_t>::vector;
+
+    bool recording = false;
+    std::vector<std::pair<std::size_t, std::size_t>> links;
+};
 
 std::size_t add_element(union_find&);
 std::size_t get_representative(union_find&, std::size_t);
 void merge_into(union_find&, std::size_t, std::size_t);
-
+// Undoes the recorded changes after the first links_size ones.
+void undo_links(union_find&, std::size_t links_size);
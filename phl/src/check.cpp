@@ -0,0 +1,233 @@
+#include "closure_impl.hpp"
+#include <check.hpp>
+#include <closure.hpp>
+#include <phl.hpp>
+#include <util.hpp>
+#include <algorithm>
+#include <optional>
+#include <string>
+#include <unordered_map>
+#include <vector>
+
+using std::vector;
+using std::unordered_map;
+using std::optional;
+using std::nullopt;
+using std::size_t;
+using std::string;
+using std::visit;
+using std::get_if;
+using std::find;
+
+static string arguments(size_t count) {
+    return std::to_string(count) + (count == 1 ? " argument" : " arguments");
+}
+
+static void check_sort(const phl_signature& signature, sort s, const string& of) {
+    if (find(signature.sorts.begin(), signature.sorts.end(), s) == signature.sorts.end()) {
+        throw malformed_sequent(
+            "sort " + string(s) + " of " + of + " is not part of the signature");
+    }
+}
+
+static void check_signature(const phl_signature& signature) {
+    for (const predicate& pred : signature.predicates) {
+        for (sort s : pred.arity) {
+            check_sort(signature, s, "predicate " + string(pred.name));
+        }
+    }
+    for (const operation& op : signature.operations) {
+        for (sort s : op.dom) {
+            check_sort(signature, s, "operation " + string(op.name));
+        }
+        check_sort(signature, op.cod, "operation " + string(op.name));
+    }
+}
+
+namespace {
+
+// Infers the sorts of the variables of one sequent or conflict from the
+// arguments they are passed as, checking every application against the
+// signature on the way. Variables that are only equated to other terms get
+// the sort of those by finish.
+class sort_inference {
+public:
+    explicit sort_inference(const phl_signature& signature) : signature(signature) {}
+
+    void add(const formula& f) {
+        for (const atomic_formula& atom : f) {
+            visit(overloaded{
+                [&](const equality& eq) -> void {
+                    infer(eq.first);
+                    infer(eq.second);
+                    equalities.push_back(&eq);
+                },
+                [&](const applied_predicate& app_pred) -> void {
+                    check_predicate(app_pred);
+                },
+                [&](const defined_term& t) -> void {
+                    infer(t.value);
+                }
+            }, atom);
+        }
+    }
+
+    void add(const term& t) {
+        infer(t);
+    }
+
+    // Propagates sorts along the equalities until nothing changes, then
+    // checks that the sides of every equality have the same sort and that
+    // every variable has one.
+    void finish() {
+        bool change;
+        do {
+            change = false;
+            for (const equality* eq : equalities) {
+                optional<sort> lhs = sort_of(eq->first);
+                optional<sort> rhs = sort_of(eq->second);
+                if (lhs && !rhs) {
+                    sorts.insert({std::get<variable>(eq->second), *lhs});
+                    change = true;
+                } else if (!lhs && rhs) {
+                    sorts.insert({std::get<variable>(eq->first), *rhs});
+                    change = true;
+                }
+            }
+        } while (change);
+
+        for (const equality* eq : equalities) {
+            optional<sort> lhs = sort_of(eq->first);
+            optional<sort> rhs = sort_of(eq->second);
+            if (lhs && rhs && *lhs != *rhs) {
+                throw malformed_sequent(
+                    term_to_string(eq->first) + " is a " + string(*lhs) + ", but " +
+                    term_to_string(eq->second) + " is a " + string(*rhs));
+            }
+        }
+        for (variable v : variables) {
+            if (!sorts.count(v)) {
+                throw malformed_sequent("the sort of " + string(v) + " can not be inferred");
+            }
+        }
+    }
+
+private:
+    const phl_signature& signature;
+    unordered_map<variable, sort> sorts;
+    // in the order they occur, so that errors do not depend on hashing
+    vector<variable> variables;
+    vector<const equality*> equalities;
+
+    optional<sort> sort_of(const term& t) const {
+        if (const variable* v = get_if<variable>(&t)) {
+            auto it = sorts.find(*v);
+            if (it == sorts.end()) {
+                return nullopt;
+            }
+            return it->second;
+        }
+        return std::get<applied_operation>(t).op.cod;
+    }
+
+    optional<sort> infer(const term& t) {
+        if (const variable* v = get_if<variable>(&t)) {
+            if (find(variables.begin(), variables.end(), *v) == variables.end()) {
+                variables.push_back(*v);
+            }
+            return sort_of(t);
+        }
+
+        const applied_operation& app_op = std::get<applied_operation>(t);
+        const vector<operation>& ops = signature.operations;
+        if (find(ops.begin(), ops.end(), app_op.op) == ops.end()) {
+            throw malformed_sequent(
+                "operation " + string(app_op.op.name) + " is not part of the signature");
+        }
+        check_arguments(app_op.op.name, app_op.op.dom, app_op.args);
+        return app_op.op.cod;
+    }
+
+    void check_predicate(const applied_predicate& app_pred) {
+        const vector<predicate>& preds = signature.predicates;
+        if (find(preds.begin(), preds.end(), app_pred.pred) == preds.end()) {
+            throw malformed_sequent(
+                "predicate " + string(app_pred.pred.name) + " is not part of the signature");
+        }
+        check_arguments(app_pred.pred.name, app_pred.pred.arity, app_pred.args);
+    }
+
+    void check_arguments(std::string_view name, const vector<sort>& dom, const vector<term>& args) {
+        string application(name);
+        application += "(";
+        for (size_t i = 0; i != args.size(); ++i) {
+            if (i > 0) {
+                application += ", ";
+            }
+            application += term_to_string(args[i]);
+        }
+        application += ")";
+
+        if (args.size() != dom.size()) {
+            throw malformed_sequent(
+                application + " applies " + string(name) + " to " +
+                arguments(args.size()) + ", but it takes " + std::to_string(dom.size()));
+        }
+        for (size_t i = 0; i != args.size(); ++i) {
+            optional<sort> arg_sort = infer(args[i]);
+            if (!arg_sort) {
+                sorts.insert({std::get<variable>(args[i]), dom[i]});
+            } else if (*arg_sort != dom[i]) {
+                throw malformed_sequent(
+                    string(name) + " takes a " + string(dom[i]) + " as argument " +
+                    std::to_string(i + 1) + " of " + application + ", but " +
+                    term_to_string(args[i]) + " is a " + string(*arg_sort));
+            }
+        }
+    }
+};
+
+}
+
+template<class F>
+static void with_context(const string& context, F&& f) {
+    try {
+        f();
+    } catch (const malformed_sequent& e) {
+        throw malformed_sequent(context + ": " + e.what());
+    }
+}
+
+void check_theory(const phl_theory& theory) {
+    check_signature(theory.signature);
+
+    for (const sequent& seq : theory.injective_axioms) {
+        with_context("injective axiom " + sequent_to_string(seq), [&] {
+            sort_inference inference(theory.signature);
+            inference.add(seq.premise);
+            inference.add(seq.conclusion);
+            inference.finish();
+        });
+    }
+    for (const sequent& seq : theory.surjective_axioms) {
+        with_context("surjective axiom " + sequent_to_string(seq), [&] {
+            sort_inference inference(theory.signature);
+            inference.add(seq.premise);
+            inference.add(seq.conclusion);
+            inference.finish();
+            plan_surjective_conclusion(formula_join_plan(seq.premise), seq.conclusion);
+        });
+    }
+    for (const conflict& c : theory.conflicts) {
+        with_context("conflict " + conflict_to_string(c), [&] {
+            sort_inference inference(theory.signature);
+            inference.add(c.premise);
+            inference.add(c.witness);
+            inference.finish();
+            if (!lookup(formula_join_plan(c.premise).term_indices, c.witness)) {
+                throw malformed_sequent(
+                    "witness " + term_to_string(c.witness) + " is not bound by the premise");
+            }
+        });
+    }
+}
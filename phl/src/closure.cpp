@@ -2,7 +2,11 @@
 #include <util.hpp>
 #include <phl.hpp>
 #include <partial_structure.hpp>
+#include <atomic>
 #include <cassert>
+#include <exception>
+#include <set>
+#include <thread>
 
 using std::vector;
 using std::unordered_map;
@@ -19,6 +23,95 @@ using std::nullopt;
 using std::max;
 using std::variant;
 
+std::string term_to_string(const term& t) {
+    return visit(overloaded{
+        [](const variable& v) -> std::string {
+            return std::string(v);
+        },
+        [](const applied_operation& app_op) -> std::string {
+            std::string s(app_op.op.name);
+            s += "(";
+            for (size_t i = 0; i != app_op.args.size(); ++i) {
+                if (i > 0) {
+                    s += ", ";
+                }
+                s += term_to_string(app_op.args[i]);
+            }
+            s += ")";
+            return s;
+        }
+    }, t);
+}
+
+static std::string formula_to_string(const formula& f) {
+    std::string s;
+    for (const atomic_formula& atom : f) {
+        if (!s.empty()) {
+            s += " && ";
+        }
+        s += visit(overloaded{
+            [](const equality& eq) -> std::string {
+                return term_to_string(eq.first) + " = " + term_to_string(eq.second);
+            },
+            [](const applied_predicate& app_pred) -> std::string {
+                std::string s(app_pred.pred.name);
+                s += "(";
+                for (size_t i = 0; i != app_pred.args.size(); ++i) {
+                    if (i > 0) {
+                        s += ", ";
+                    }
+                    s += term_to_string(app_pred.args[i]);
+                }
+                return s + ")";
+            },
+            [](const defined_term& t) -> std::string {
+                return "!" + term_to_string(t.value);
+            }
+        }, atom);
+    }
+    return s.empty() ? "truth" : s;
+}
+
+std::string sequent_to_string(const sequent& seq) {
+    // t1 -= t2 is sugar for !t1 && !t2 |= t1 = t2
+    if (seq.premise.size() == 2 && seq.conclusion.size() == 1) {
+        const defined_term* lhs = get_if<defined_term>(&seq.premise[0]);
+        const defined_term* rhs = get_if<defined_term>(&seq.premise[1]);
+        const equality* eq = get_if<equality>(&seq.conclusion[0]);
+        if (lhs && rhs && eq && eq->first == lhs->value && eq->second == rhs->value) {
+            return term_to_string(lhs->value) + " -= " + term_to_string(rhs->value);
+        }
+    }
+    return formula_to_string(seq.premise) + " |= " + formula_to_string(seq.conclusion);
+}
+
+std::string conflict_to_string(const conflict& c) {
+    return formula_to_string(c.premise) + " |= falsity";
+}
+
+void accumulate(closure_stats& into, const closure_stats& from) {
+    into.closures += from.closures;
+    into.rounds += from.rounds;
+    into.joined_rows += from.joined_rows;
+    into.merges += from.merges;
+    unordered_map<std::string, size_t> indices;
+    for (size_t i = 0; i != into.sequents.size(); ++i) {
+        indices.insert({into.sequents[i].name, i});
+    }
+    for (const sequent_stats& seq : from.sequents) {
+        auto [it, fresh] = indices.insert({seq.name, into.sequents.size()});
+        if (fresh) {
+            into.sequents.push_back({seq.name});
+        }
+        sequent_stats& sum = into.sequents[it->second];
+        sum.visited_rows += seq.visited_rows;
+        sum.joined_rows += seq.joined_rows;
+        sum.merges += seq.merges;
+        sum.facts += seq.facts;
+        sum.time += seq.time;
+    }
+}
+
 optional<size_t> lookup(const unordered_map<term, size_t>& indices, const term& t) {
     auto it = indices.find(t);
     if (it == indices.end()) {
@@ -111,7 +204,11 @@ void add_equality_to_plan(join_plan& plan, const equality& eq) {
     add_subterm_to_plan(plan, rhs);
     optional<size_t> lhs_index = lookup(plan.term_indices, lhs);
     optional<size_t> rhs_index = lookup(plan.term_indices, rhs);
-    assert(lhs_index || rhs_index); // TODO: proper error
+    if (!lhs_index && !rhs_index) {
+        throw malformed_sequent(
+            "neither side of " + term_to_string(lhs) + " = " + term_to_string(rhs) +
+            " is bound elsewhere in the premise");
+    }
 
     if (lhs_index && rhs_index) {
         plan.equalities.push_back({*lhs_index, *rhs_index});
@@ -184,6 +281,7 @@ std::string relation_name(const variant<predicate, operation>& rel) {
 template<class F>
 void visit_join_impl(
     F&& f,
+    size_t& visited_rows,
     vector<size_t>& joined_row,
     vector<const unordered_set<vector<size_t>>*>::iterator rels_it,
     vector<vector<pair<size_t, size_t>>>::iterator eqs_it,
@@ -195,6 +293,7 @@ void visit_join_impl(
     } else {
         size_t before_size = joined_row.size();
         for (const vector<size_t>& row : **rels_it) {
+            ++visited_rows;
             joined_row.insert(joined_row.end(), row.begin(), row.end());
             bool is_good_row = true;
             for (auto [lhs, rhs] : *eqs_it) {
@@ -206,6 +305,7 @@ void visit_join_impl(
             if (is_good_row) {
                 visit_join_impl(
                     forward<F>(f),
+                    visited_rows,
                     joined_row,
                     rels_it + 1,
                     eqs_it + 1,
@@ -217,15 +317,25 @@ void visit_join_impl(
     }
 }
 
+// Calls f with every row of the join and adds the number of rows of the
+// relations that were tried to visited_rows.
 template<class F>
-void visit_join(F&& f, const join_plan& plan, const partial_structure& pstruct) {
+void visit_join(
+    F&& f,
+    size_t& visited_rows,
+    const join_plan& plan,
+    const partial_structure& pstruct
+) {
     vector<const unordered_set<vector<size_t>>*> rels;
     vector<vector<pair<size_t, size_t>>> partitioned_eqs;
     vector<pair<size_t, size_t>>::const_iterator eq_it = plan.equalities.begin();
     size_t current_join_size = 0;
     for (const relation& rel_sym : plan.relations) {
         auto it = pstruct.relations.find(rel_sym);
-        assert(it != pstruct.relations.end());
+        if (it == pstruct.relations.end()) {
+            throw malformed_sequent(
+                "relation " + relation_name(rel_sym) + " is not part of the structure");
+        }
         const unordered_set<vector<size_t>>& rel = it->second;
         rels.push_back(&rel);
         current_join_size += relation_arity(rel_sym);
@@ -249,6 +359,7 @@ void visit_join(F&& f, const join_plan& plan, const partial_structure& pstruct)
 
     visit_join_impl(
         forward<F>(f),
+        visited_rows,
         joined_row,
         rels.begin(),
         partitioned_eqs.begin(),
@@ -261,12 +372,18 @@ unordered_set<vector<size_t>> compute_join(
     const partial_structure& pstruct
 ) {
     unordered_set<vector<size_t>> join;
+    size_t visited_rows = 0;
     visit_join([&](const vector<size_t>& row) {
         join.insert(row);
-    }, plan, pstruct);
+    }, visited_rows, plan, pstruct);
     return join;
 }
 
+[[noreturn]] static void throw_unbound_in_conclusion(const term& t) {
+    throw malformed_sequent(
+        "conclusion mentions " + term_to_string(t) + " which is not bound by the premise");
+}
+
 surjective_conclusion_plan plan_surjective_conclusion(
     const join_plan& premise_plan,
     const formula& conclusion
@@ -279,23 +396,33 @@ surjective_conclusion_plan plan_surjective_conclusion(
                 const auto& [lhs, rhs] = eq;
                 optional<size_t> lhs_index = lookup(premise_plan.term_indices, lhs);
                 optional<size_t> rhs_index = lookup(premise_plan.term_indices, rhs);
-                assert(lhs_index && rhs_index); // otherwise it's not a surjective sequent
+                // otherwise it's not a surjective sequent
+                if (!lhs_index) {
+                    throw_unbound_in_conclusion(lhs);
+                }
+                if (!rhs_index) {
+                    throw_unbound_in_conclusion(rhs);
+                }
                 concl_plan.concluded_equalities.push_back({*lhs_index, *rhs_index});
             },
             [&](const applied_predicate& app_pred) -> void {
                 vector<size_t> arg_indices;
                 for (const term& arg : app_pred.args) {
                     optional<size_t> arg_index = lookup(premise_plan.term_indices, arg);
-                    assert(arg_index); // otherwise it's not a surjective sequent
+                    if (!arg_index) {
+                        // otherwise it's not a surjective sequent
+                        throw_unbound_in_conclusion(arg);
+                    }
                     arg_indices.push_back(*arg_index);
                 }
                 concl_plan.concluded_predicates.push_back({app_pred.pred, move(arg_indices)});
             },
             [&](const defined_term& term) -> void {
-                (void)term;
                 // it is somewhat nonsensical to have a defined_term in the
                 // conclusion of a surjective sequent
-                assert(lookup(premise_plan.term_indices, term.value));
+                if (!lookup(premise_plan.term_indices, term.value)) {
+                    throw_unbound_in_conclusion(term.value);
+                }
             }
         }, af);
     }
@@ -315,6 +442,7 @@ void surjective_closure_step(
     }
 
     visit_join([&](const vector<size_t>& row) {
+        ++delta.joined_rows;
         // take care of new equalities
         for (pair<size_t, size_t> eq : conclusion_plan.concluded_equalities) {
             delta.equalities.push_back({row[eq.first], row[eq.second]});
@@ -329,38 +457,113 @@ void surjective_closure_step(
             (**it).insert(move(substituted_args));
             ++it;
         }
-    }, premise_plan, pstruct);
+    }, delta.visited_rows, premise_plan, pstruct);
 }
 
-bool merge_into(const surjective_delta& delta, partial_structure& pstruct) {
-    bool change = false;
-    bool equality_change = false;
+vector<surjective_delta> parallel_closure_step(
+    const vector<pair<join_plan, surjective_conclusion_plan>>& plans,
+    const partial_structure& pstruct,
+    size_t thread_count
+) {
+    vector<surjective_delta> deltas(plans.size());
+    vector<std::exception_ptr> errors(plans.size());
+    // Plans differ a lot in cost, so threads take the next plan when they are
+    // done with one rather than a fixed share.
+    std::atomic<size_t> next_plan{0};
+    auto work = [&] {
+        for (size_t i = next_plan++; i < plans.size(); i = next_plan++) {
+            try {
+                auto start = std::chrono::steady_clock::now();
+                surjective_closure_step(plans[i].first, plans[i].second, pstruct, deltas[i]);
+                deltas[i].time = std::chrono::steady_clock::now() - start;
+            } catch (...) {
+                errors[i] = std::current_exception();
+            }
+        }
+    };
+
+    size_t row_count = 0;
+    for (const auto& [_, rows] : pstruct.relations) {
+        row_count += rows.size();
+    }
+    vector<std::thread> threads;
+    size_t helper_count = row_count < min_parallel_rows ? 1 : std::min(thread_count, plans.size());
+    for (size_t i = 1; i < helper_count; ++i) {
+        threads.emplace_back(work);
+    }
+    work();
+    for (std::thread& thread : threads) {
+        thread.join();
+    }
+
+    for (const std::exception_ptr& error : errors) {
+        if (error) {
+            std::rethrow_exception(error);
+        }
+    }
+    return deltas;
+}
 
+static size_t merge_equalities(const surjective_delta& delta, partial_structure& pstruct) {
+    size_t merges = 0;
     for (auto [lhs, rhs] : delta.equalities) {
         size_t lhs_repr = get_representative(pstruct.equality, lhs);
         size_t rhs_repr = get_representative(pstruct.equality, rhs);
         if (lhs_repr != rhs_repr) {
             merge_into(pstruct.equality, lhs_repr, rhs_repr);
-            change = true;
-            equality_change = true;
+            ++merges;
         }
     }
+    return merges;
+}
 
+// Rows are inserted with the current representatives, so that a row that
+// only differs from an existing one by equalities merged so far does not
+// count as new.
+static size_t insert_rows(const surjective_delta& delta, partial_structure& pstruct) {
+    size_t facts = 0;
     for (const auto& [pred, delta_rows] : delta.relations) {
-        unordered_set<vector<size_t>>& pstruct_rows = pstruct.relations[pred];
-        size_t before_size = pstruct_rows.size();
-        pstruct_rows.insert(delta_rows.begin(), delta_rows.end());
-        if (before_size != pstruct_rows.size()) {
-            change = true;
+        for (vector<size_t> row : delta_rows) {
+            for (size_t& arg : row) {
+                arg = get_representative(pstruct.equality, arg);
+            }
+            if (insert_row(pstruct, pred, move(row))) {
+                ++facts;
+            }
         }
     }
+    return facts;
+}
 
-    if (equality_change) {
-        // can't do this earlier because delta.relations might contain rows
-        // with non-canonical representatives
+bool merge_into(const surjective_delta& delta, partial_structure& pstruct) {
+    size_t merges = merge_equalities(delta, pstruct);
+    size_t facts = insert_rows(delta, pstruct);
+    if (merges > 0) {
+        // rows inserted before a later merge may have become uncanonical
         compact_relations(pstruct);
     }
+    return merges > 0 || facts > 0;
+}
 
+bool merge_into(
+    const vector<surjective_delta>& deltas,
+    partial_structure& pstruct,
+    vector<sequent_stats>& stats
+) {
+    assert(deltas.size() == stats.size());
+    bool equality_change = false;
+    bool change = false;
+    for (size_t i = 0; i != deltas.size(); ++i) {
+        size_t merges = merge_equalities(deltas[i], pstruct);
+        size_t facts = insert_rows(deltas[i], pstruct);
+        stats[i].merges += merges;
+        stats[i].facts += facts;
+        equality_change = equality_change || merges > 0;
+        change = change || merges > 0 || facts > 0;
+    }
+    if (equality_change) {
+        compact_relations(pstruct);
+    }
     return change;
 }
 
@@ -381,20 +584,46 @@ void verify_canonicity(partial_structure& pstruct) {
 #endif
 }
 
-void surjective_closure(
+static void add_totals(closure_stats& stats) {
+    for (const sequent_stats& seq : stats.sequents) {
+        stats.joined_rows += seq.joined_rows;
+        stats.merges += seq.merges;
+    }
+}
+
+// Called after a round that derived something, so another round is needed.
+static void check_round_limits(const closure_limits& limits, closure_stats& stats) {
+    std::string exceeded;
+    if (limits.max_rounds && stats.rounds >= *limits.max_rounds) {
+        exceeded = "no fixpoint after " + std::to_string(stats.rounds) + " rounds";
+    } else if (limits.deadline && std::chrono::steady_clock::now() >= *limits.deadline) {
+        exceeded = "no fixpoint before the deadline, after " + std::to_string(stats.rounds) + " rounds";
+    } else {
+        return;
+    }
+    add_totals(stats);
+    throw limit_exceeded(exceeded, std::move(stats));
+}
+
+closure_stats surjective_closure(
     const std::vector<sequent>& surjections,
-    partial_structure& pstruct
+    partial_structure& pstruct,
+    size_t thread_count,
+    const closure_limits& limits
 ) {
     // We maintain the invariant that data in the partial structure
     // is always canonical except for during joins.
     verify_canonicity(pstruct);
 
+    closure_stats stats;
+    stats.closures = 1;
     vector<pair<join_plan, surjective_conclusion_plan>> plans;
     for (const sequent& seq : surjections) {
         join_plan premise_plan = formula_join_plan(seq.premise);
         surjective_conclusion_plan conclusion_plan =
             plan_surjective_conclusion(premise_plan, seq.conclusion);
         plans.push_back({premise_plan, conclusion_plan});
+        stats.sequents.push_back({sequent_to_string(seq)});
     }
     for (const auto& [rel, _] : pstruct.relations) {
         if (const operation* op = get_if<operation>(&rel)) {
@@ -416,19 +645,64 @@ void surjective_closure(
                 {}
             };
             plans.push_back({premise_plan, conclusion_plan});
+            stats.sequents.push_back({"functionality of " + std::string(op->name)});
         }
     }
 
-    surjective_delta delta;
+    // Surjective sequents never add elements, so the carrier only needs to be
+    // checked once.
+    if (limits.max_carrier_size && pstruct.carrier.size() > *limits.max_carrier_size) {
+        throw limit_exceeded(
+            "the carrier has " + std::to_string(pstruct.carrier.size()) +
+            " elements, more than the limit of " + std::to_string(*limits.max_carrier_size),
+            std::move(stats));
+    }
+
+    bool change;
     do {
-        for (auto& [_, rows] : delta.relations) {
-            rows.clear();
+        ++stats.rounds;
+        // Every plan gets its own delta even without helper threads, and the
+        // deltas are merged in the order of the plans, so the result does not
+        // depend on the number of threads or on their scheduling.
+        vector<surjective_delta> deltas = parallel_closure_step(plans, pstruct, thread_count);
+        for (size_t i = 0; i != deltas.size(); ++i) {
+            stats.sequents[i].visited_rows += deltas[i].visited_rows;
+            stats.sequents[i].joined_rows += deltas[i].joined_rows;
+            stats.sequents[i].time += deltas[i].time;
         }
-        delta.equalities.clear();
-        for (const auto& [premise_plan, conclusion_plan] : plans) {
-            surjective_closure_step(premise_plan, conclusion_plan, pstruct, delta);
+        change = merge_into(deltas, pstruct, stats.sequents);
+        if (change) {
+            check_round_limits(limits, stats);
         }
-    } while (merge_into(delta, pstruct));
+    } while (change);
 
+    add_totals(stats);
     verify_canonicity(pstruct);
+    return stats;
+}
+
+vector<conflict_match> find_conflicts(
+    const vector<conflict>& conflicts,
+    const partial_structure& pstruct
+) {
+    vector<conflict_match> matches;
+    for (size_t i = 0; i != conflicts.size(); ++i) {
+        join_plan plan = formula_join_plan(conflicts[i].premise);
+        optional<size_t> witness_index = lookup(plan.term_indices, conflicts[i].witness);
+        if (!witness_index) {
+            throw malformed_sequent(
+                "witness " + term_to_string(conflicts[i].witness) +
+                " of a conflict is not bound by its premise");
+        }
+
+        std::set<size_t> witnesses;
+        size_t visited_rows = 0;
+        visit_join([&](const vector<size_t>& row) {
+            witnesses.insert(row[*witness_index]);
+        }, visited_rows, plan, pstruct);
+        for (size_t witness : witnesses) {
+            matches.push_back({i, witness});
+        }
+    }
+    return matches;
 }
@@ -2,6 +2,7 @@
 
 #include <closure.hpp>
 #include <phl.hpp>
+#include <optional>
 #include <vector>
 #include <tuple>
 #include <unordered_map>
@@ -16,6 +17,8 @@ struct join_plan {
 
 join_plan formula_join_plan(const formula& f);
 
+std::optional<std::size_t> lookup(const std::unordered_map<term, std::size_t>& indices, const term& t);
+
 std::unordered_set<std::vector<size_t>> compute_join(
     const join_plan& plan,
     const partial_structure& pstruct
@@ -34,13 +37,40 @@ surjective_conclusion_plan plan_surjective_conclusion(
 struct surjective_delta {
     std::vector<std::pair<std::size_t, std::size_t>> equalities; // use union find?
     std::unordered_map<predicate, std::unordered_set<std::vector<std::size_t>>> relations;
+    // counters of the evaluation that computed the delta
+    std::size_t visited_rows = 0;
+    std::size_t joined_rows = 0;
+    std::chrono::nanoseconds time{0};
 };
 
 bool merge_into(const surjective_delta& delta, partial_structure& pstruct);
 
+// Merges the deltas in order, which gives the same structure as merging the
+// concatenation of them. Adds the classes merged and rows added by deltas[i]
+// to stats[i].
+bool merge_into(
+    const std::vector<surjective_delta>& deltas,
+    partial_structure& pstruct,
+    std::vector<sequent_stats>& stats
+);
+
 void surjective_closure_step(
     const join_plan& premise_plan,
     const surjective_conclusion_plan& conclusion_plan,
     const partial_structure& pstruct,
     surjective_delta& delta
 );
+
+// Rounds over structures with fewer rows than this are evaluated on the
+// calling thread only, since starting the other threads would take longer
+// than the round itself.
+constexpr std::size_t min_parallel_rows = 256;
+
+// Evaluates each plan into its own delta, on up to thread_count threads. The
+// structure is only read. If some plans throw, the exception of the first of
+// them is rethrown.
+std::vector<surjective_delta> parallel_closure_step(
+    const std::vector<std::pair<join_plan, surjective_conclusion_plan>>& plans,
+    const partial_structure& pstruct,
+    std::size_t thread_count
+);
@@ -1,96 +1,512 @@
+#include <algorithm>
 #include <cassert>
+#include <chrono>
+#include <check.hpp>
 #include <closure.hpp>
+#include <cstdint>
 #include <cwf.hpp>
+#include <ffi.hpp>
+#include <limits>
 #include <partial_structure.hpp>
 #include <phl.hpp>
+#include <string>
+#include <thread>
 #include <util.hpp>
 
+static thread_local std::string last_error;
+
+static phl_status fail(phl_status status, std::string message) {
+    last_error = std::move(message);
+    return status;
+}
+
+// Runs f, translating exceptions into a status so that they never cross the
+// C interface.
+template<class F>
+static phl_status guarded(F&& f) {
+    try {
+        return f();
+    } catch (const malformed_sequent& e) {
+        return fail(PHL_MALFORMED_SEQUENT, e.what());
+    } catch (const std::exception& e) {
+        return fail(PHL_INTERNAL_ERROR, e.what());
+    }
+}
+
+extern "C" const char* get_last_error() {
+    return last_error.c_str();
+}
+
+// The structures handed out by create_cwf, which also keep the statistics and
+// limits of their fixpoint computations and the conflicts found last.
+struct cwf_structure : partial_structure {
+    using partial_structure::partial_structure;
+
+    closure_stats stats;
+    phl_closure_limits limits{};
+    std::vector<conflict_match> conflicts;
+};
+
+static closure_stats& stats_of(partial_structure* pstruct) {
+    return static_cast<cwf_structure*>(pstruct)->stats;
+}
+
+static phl_closure_limits& limits_of(partial_structure* pstruct) {
+    return static_cast<cwf_structure*>(pstruct)->limits;
+}
+
+static std::vector<conflict_match>& conflicts_of(partial_structure* pstruct) {
+    return static_cast<cwf_structure*>(pstruct)->conflicts;
+}
+
 extern "C" partial_structure* create_cwf() {
-    return new partial_structure(cwf::cwf_signature);
+    partial_structure* pstruct = nullptr;
+    guarded([&] {
+        // catches mistakes in the axioms before any closure runs into them
+        check_theory(cwf::cwf);
+        pstruct = new cwf_structure(cwf::cwf_signature);
+        return PHL_OK;
+    });
+    return pstruct;
 }
 
 extern "C" void destroy_cwf(partial_structure* pstruct) {
-    delete pstruct;
+    delete static_cast<cwf_structure*>(pstruct);
 }
 
-extern "C" const sort* get_sort(const char* name) {
-    return &*std::find_if(
+static const sort* find_sort(std::string_view name) {
+    auto it = std::find(
         cwf::cwf_signature.sorts.begin(),
         cwf::cwf_signature.sorts.end(),
-        [&](auto& sort) {
-            return sort == name;
-        });
+        name);
+    return it == cwf::cwf_signature.sorts.end() ? nullptr : &*it;
+}
+
+extern "C" const sort* get_sort(const char* name) {
+    return find_sort(name);
+}
+
+extern "C" const char* get_sort_name(const sort* s, size_t* len) {
+    *len = s->size();
+    return s->data();
 }
 
 extern "C" const operation* get_operation(const char* name) {
-    return &*std::find_if(
+    auto it = std::find_if(
         cwf::cwf_signature.operations.begin(),
         cwf::cwf_signature.operations.end(),
         [&](auto& op) { return op.name == name; }
     );
+    return it == cwf::cwf_signature.operations.end() ? nullptr : &*it;
+}
+
+extern "C" const char* get_operation_name(const operation* op, size_t* len) {
+    *len = op->name.size();
+    return op->name.data();
 }
 
 extern "C" size_t get_operation_arity(const operation* op) {
     return op->dom.size();
 }
 
+extern "C" const sort* get_operation_domain(const operation* op, size_t index) {
+    if (index >= op->dom.size()) {
+        return nullptr;
+    }
+    return find_sort(op->dom[index]);
+}
+
+extern "C" const sort* get_operation_codomain(const operation* op) {
+    return find_sort(op->cod);
+}
+
 extern "C" const predicate* get_predicate(const char* name) {
-    return &*std::find_if(
+    auto it = std::find_if(
         cwf::cwf_signature.predicates.begin(),
         cwf::cwf_signature.predicates.end(),
         [&](auto& pred) { return pred.name == name; }
     );
+    return it == cwf::cwf_signature.predicates.end() ? nullptr : &*it;
 }
 
-extern "C" bool are_equal(partial_structure* pstruct, size_t l, size_t r) {
+extern "C" const char* get_predicate_name(const predicate* pred, size_t* len) {
+    *len = pred->name.size();
+    return pred->name.data();
+}
+
+extern "C" size_t get_predicate_arity(const predicate* pred) {
+    return pred->arity.size();
+}
+
+extern "C" const sort* get_predicate_domain(const predicate* pred, size_t index) {
+    if (index >= pred->arity.size()) {
+        return nullptr;
+    }
+    return find_sort(pred->arity[index]);
+}
+
+extern "C" size_t get_carrier_size(const partial_structure* pstruct) {
+    return pstruct ? pstruct->carrier.size() : 0;
+}
+
+static phl_status check_element(const partial_structure& pstruct, size_t el, sort s) {
+    auto it = pstruct.carrier.find(el);
+    if (it == pstruct.carrier.end()) {
+        return fail(PHL_INVALID_ELEMENT, std::to_string(el) + " is not in the carrier");
+    }
+    if (it->second != s) {
+        return fail(
+            PHL_SORT_MISMATCH,
+            std::to_string(el) + " has sort " + std::string(it->second) +
+            " but " + std::string(s) + " was expected");
+    }
+    return PHL_OK;
+}
+
+extern "C" phl_status are_equal(partial_structure* pstruct, size_t l, size_t r, bool* result) {
+    if (!pstruct || !result) {
+        return fail(PHL_INVALID_ARGUMENT, "are_equal: null argument");
+    }
+    for (size_t el : {l, r}) {
+        if (el >= pstruct->equality.size()) {
+            return fail(PHL_INVALID_ELEMENT, std::to_string(el) + " is not in the carrier");
+        }
+    }
     size_t lr = get_representative(pstruct->equality, l);
     size_t rr = get_representative(pstruct->equality, r);
 #ifndef NDEBUG
     //printf("[%zu] == [%zu] => %s\n", l, r, lr == rr ? "true" : "false");
 #endif
-    return lr == rr;
+    *result = lr == rr;
+    return PHL_OK;
 }
 
-extern "C" size_t define_operation(partial_structure* pstruct, const operation* op, const size_t* args) {
-    size_t new_id = pstruct->carrier.size();
+extern "C" phl_status define_operation(
+    partial_structure* pstruct,
+    const operation* op,
+    const size_t* args,
+    size_t* result
+) {
+    if (!pstruct || !op || !result || (!args && !op->dom.empty())) {
+        return fail(PHL_INVALID_ARGUMENT, "define_operation: null argument");
+    }
+
+    for (size_t i = 0; i < op->dom.size(); i++) {
+        phl_status status = check_element(*pstruct, args[i], op->dom[i]);
+        if (status != PHL_OK) {
+            *result = i;
+            return status;
+        }
+    }
+
+    return guarded([&] {
+        size_t new_id = pstruct->carrier.size();
 
 #ifndef NDEBUG
-    //printf("%s(", std::string(op->name).c_str());
-    //for (size_t i = 0; i < op->dom.size(); i++) {
-    //    if (i > 0)
-    //        printf(", ");
+        //printf("%s(", std::string(op->name).c_str());
+        //for (size_t i = 0; i < op->dom.size(); i++) {
+        //    if (i > 0)
+        //        printf(", ");
 
-    //    printf("%zu", args[i]);
-    //}
+        //    printf("%zu", args[i]);
+        //}
 
-    //printf(") = %zu\n", new_id);
+        //printf(") = %zu\n", new_id);
 #endif
 
-    pstruct->carrier[new_id] = op->cod;
-    size_t uf_id = add_element(pstruct->equality);
-    assert(uf_id == new_id);
+        size_t added = add_carrier_element(*pstruct, op->cod);
+        assert(added == new_id);
+
+        std::vector<size_t> vec;
+        vec.reserve(op->dom.size() + 1);
+        for (size_t i = 0; i < op->dom.size(); i++) {
+            // Ensure that we keep up the invariant that the tables always
+            // contain canonical representatives except during joins.
+            size_t rep = get_representative(pstruct->equality, args[i]);
+            vec.push_back(rep);
+        }
+
+        vec.push_back(new_id);
+        insert_row(*pstruct, *op, std::move(vec));
+        *result = new_id;
+        return PHL_OK;
+    });
+}
+
+static phl_status check_predicate_args(
+    const partial_structure& pstruct,
+    const predicate& pred,
+    const size_t* args
+) {
+    for (size_t i = 0; i < pred.arity.size(); i++) {
+        phl_status status = check_element(pstruct, args[i], pred.arity[i]);
+        if (status != PHL_OK) {
+            return status;
+        }
+    }
+    return PHL_OK;
+}
 
+// Rows of relations only contain canonical representatives outside of joins,
+// so arguments need to be canonicalized before they are inserted or looked up.
+static std::vector<size_t> canonical_args(
+    partial_structure& pstruct,
+    const predicate& pred,
+    const size_t* args
+) {
     std::vector<size_t> vec;
-    vec.reserve(op->dom.size() + 1);
-    for (size_t i = 0; i < op->dom.size(); i++) {
-        assert(args[i] < new_id);
-        assert(pstruct->carrier[args[i]] == op->dom[i]);
-        // Ensure that we keep up the invariant that the tables always
-        // contain canonical representatives except during joins.
-        size_t rep = get_representative(pstruct->equality, args[i]);
-        vec.push_back(rep);
+    vec.reserve(pred.arity.size());
+    for (size_t i = 0; i < pred.arity.size(); i++) {
+        vec.push_back(get_representative(pstruct.equality, args[i]));
+    }
+    return vec;
+}
+
+extern "C" phl_status define_predicate(
+    partial_structure* pstruct,
+    const predicate* pred,
+    const size_t* args
+) {
+    if (!pstruct || !pred || (!args && !pred->arity.empty())) {
+        return fail(PHL_INVALID_ARGUMENT, "define_predicate: null argument");
     }
 
-    vec.push_back(new_id);
-    pstruct->relations[*op].insert(std::move(vec));
-    return new_id;
+    phl_status status = check_predicate_args(*pstruct, *pred, args);
+    if (status != PHL_OK) {
+        return status;
+    }
+
+    return guarded([&] {
+        insert_row(*pstruct, *pred, canonical_args(*pstruct, *pred, args));
+        return PHL_OK;
+    });
+}
+
+extern "C" phl_status predicate_holds(
+    partial_structure* pstruct,
+    const predicate* pred,
+    const size_t* args,
+    bool* result
+) {
+    if (!pstruct || !pred || !result || (!args && !pred->arity.empty())) {
+        return fail(PHL_INVALID_ARGUMENT, "predicate_holds: null argument");
+    }
+
+    phl_status status = check_predicate_args(*pstruct, *pred, args);
+    if (status != PHL_OK) {
+        return status;
+    }
+
+    return guarded([&] {
+        const auto& rows = pstruct->relations[*pred];
+        *result = rows.find(canonical_args(*pstruct, *pred, args)) != rows.end();
+        return PHL_OK;
+    });
+}
+
+extern "C" phl_status compute_fixpoint(partial_structure* pstruct, size_t thread_count) {
+    if (!pstruct) {
+        return fail(PHL_INVALID_ARGUMENT, "compute_fixpoint: null argument");
+    }
+    if (thread_count == 0) {
+        // hardware_concurrency is 0 if it is not known
+        thread_count = std::max(std::thread::hardware_concurrency(), 1u);
+    }
+
+    // The deadline is relative to the start of every call.
+    const phl_closure_limits& raw_limits = limits_of(pstruct);
+    closure_limits limits;
+    if (raw_limits.max_rounds != 0) {
+        limits.max_rounds = raw_limits.max_rounds;
+    }
+    if (raw_limits.max_carrier_size != 0) {
+        limits.max_carrier_size = raw_limits.max_carrier_size;
+    }
+    if (raw_limits.timeout_nanoseconds != 0) {
+        // Deadlines beyond the range of the clock saturate rather than
+        // overflow into the past.
+        auto now = std::chrono::steady_clock::now();
+        std::chrono::nanoseconds timeout(std::min<std::uint64_t>(
+            raw_limits.timeout_nanoseconds, std::numeric_limits<std::int64_t>::max()));
+        if (timeout >= std::chrono::steady_clock::time_point::max() - now) {
+            limits.deadline = std::chrono::steady_clock::time_point::max();
+        } else {
+            limits.deadline = now + timeout;
+        }
+    }
+
+    return guarded([&] {
+        try {
+            closure_stats stats =
+                surjective_closure(cwf::cwf.surjective_axioms, *pstruct, thread_count, limits);
+            accumulate(stats_of(pstruct), stats);
+            return PHL_OK;
+        } catch (const limit_exceeded& e) {
+            accumulate(stats_of(pstruct), e.stats);
+            return fail(PHL_LIMIT_EXCEEDED, e.what());
+        }
+    });
+}
+
+extern "C" phl_status set_closure_limits(
+    partial_structure* pstruct,
+    const phl_closure_limits* limits
+) {
+    if (!pstruct || !limits) {
+        return fail(PHL_INVALID_ARGUMENT, "set_closure_limits: null argument");
+    }
+
+    limits_of(pstruct) = *limits;
+    return PHL_OK;
 }
 
-extern "C" void define_predicate(partial_structure* pstruct, const predicate* pred, const size_t* args) {
-    std::vector<size_t> vec(args, args + pred->arity.size());
-    pstruct->relations[*pred].insert(vec);
+extern "C" phl_status get_closure_stats(partial_structure* pstruct, phl_closure_stats* result) {
+    if (!pstruct || !result) {
+        return fail(PHL_INVALID_ARGUMENT, "get_closure_stats: null argument");
+    }
+
+    const closure_stats& stats = stats_of(pstruct);
+    *result = {stats.closures, stats.rounds, stats.sequents.size()};
+    return PHL_OK;
 }
 
-extern "C" void compute_fixpoint(partial_structure* pstruct) {
-    surjective_closure(cwf::cwf.surjective_axioms, *pstruct);
-}
\ No newline at end of file
+extern "C" phl_status get_sequent_stats(
+    partial_structure* pstruct,
+    size_t index,
+    phl_sequent_stats* result
+) {
+    if (!pstruct || !result) {
+        return fail(PHL_INVALID_ARGUMENT, "get_sequent_stats: null argument");
+    }
+    const closure_stats& stats = stats_of(pstruct);
+    if (index >= stats.sequents.size()) {
+        return fail(
+            PHL_INVALID_ARGUMENT,
+            "get_sequent_stats: there are only " + std::to_string(stats.sequents.size()) +
+            " sequents");
+    }
+
+    const sequent_stats& seq = stats.sequents[index];
+    *result = {
+        seq.name.data(),
+        seq.name.size(),
+        seq.visited_rows,
+        seq.joined_rows,
+        seq.merges,
+        seq.facts,
+        static_cast<std::uint64_t>(seq.time.count()),
+    };
+    return PHL_OK;
+}
+
+extern "C" phl_status reset_closure_stats(partial_structure* pstruct) {
+    if (!pstruct) {
+        return fail(PHL_INVALID_ARGUMENT, "reset_closure_stats: null argument");
+    }
+
+    stats_of(pstruct) = {};
+    return PHL_OK;
+}
+
+extern "C" phl_status find_conflicts(partial_structure* pstruct, size_t* count) {
+    if (!pstruct || !count) {
+        return fail(PHL_INVALID_ARGUMENT, "find_conflicts: null argument");
+    }
+
+    return guarded([&] {
+        conflicts_of(pstruct) = find_conflicts(cwf::cwf.conflicts, *pstruct);
+        *count = conflicts_of(pstruct).size();
+        return PHL_OK;
+    });
+}
+
+extern "C" phl_status get_conflict(
+    partial_structure* pstruct,
+    size_t index,
+    phl_conflict* result
+) {
+    if (!pstruct || !result) {
+        return fail(PHL_INVALID_ARGUMENT, "get_conflict: null argument");
+    }
+    const std::vector<conflict_match>& conflicts = conflicts_of(pstruct);
+    if (index >= conflicts.size()) {
+        return fail(
+            PHL_INVALID_ARGUMENT,
+            "get_conflict: there are only " + std::to_string(conflicts.size()) + " conflicts");
+    }
+
+    static const std::vector<std::string> names = [] {
+        std::vector<std::string> names;
+        for (const conflict& c : cwf::cwf.conflicts) {
+            names.push_back(conflict_to_string(c));
+        }
+        return names;
+    }();
+    const std::string& name = names[conflicts[index].conflict];
+    size_t witness = conflicts[index].witness;
+    *result = {name.data(), name.size(), witness, find_sort(pstruct->carrier.at(witness))};
+    return PHL_OK;
+}
+
+extern "C" phl_status push_checkpoint(partial_structure* pstruct) {
+    if (!pstruct) {
+        return fail(PHL_INVALID_ARGUMENT, "push_checkpoint: null argument");
+    }
+
+    return guarded([&] {
+        checkpoint(*pstruct);
+        return PHL_OK;
+    });
+}
+
+extern "C" phl_status rollback_checkpoint(partial_structure* pstruct) {
+    if (!pstruct) {
+        return fail(PHL_INVALID_ARGUMENT, "rollback_checkpoint: null argument");
+    }
+
+    return guarded([&] {
+        if (!rollback(*pstruct)) {
+            return fail(PHL_NO_CHECKPOINT, "rollback_checkpoint: no active checkpoint");
+        }
+        return PHL_OK;
+    });
+}
+
+extern "C" phl_status commit_checkpoint(partial_structure* pstruct) {
+    if (!pstruct) {
+        return fail(PHL_INVALID_ARGUMENT, "commit_checkpoint: null argument");
+    }
+
+    if (!commit(*pstruct)) {
+        return fail(PHL_NO_CHECKPOINT, "commit_checkpoint: no active checkpoint");
+    }
+    return PHL_OK;
+}
+
+extern "C" phl_status collect_garbage(
+    partial_structure* pstruct,
+    const size_t* roots,
+    size_t root_count,
+    size_t* new_ids
+) {
+    if (!pstruct || !new_ids || (!roots && root_count > 0)) {
+        return fail(PHL_INVALID_ARGUMENT, "collect_garbage: null argument");
+    }
+    if (!pstruct->checkpoints.empty()) {
+        return fail(PHL_INVALID_ARGUMENT, "collect_garbage: a checkpoint is active");
+    }
+    for (size_t i = 0; i < root_count; i++) {
+        if (pstruct->carrier.find(roots[i]) == pstruct->carrier.end()) {
+            return fail(PHL_INVALID_ELEMENT, std::to_string(roots[i]) + " is not in the carrier");
+        }
+    }
+
+    return guarded([&] {
+        std::vector<size_t> root_ids(roots, roots + root_count);
+        // The model defines these for every element it adds.
+        std::vector<size_t> ids = compact_structure(
+            *pstruct, root_ids, {cwf::dom, cwf::cod, cwf::ty_ctx, cwf::tm_ty});
+        std::copy(ids.begin(), ids.end(), new_ids);
+        return PHL_OK;
+    });
+}
@@ -1,8 +1,10 @@
+#include <cassert>
 #include <partial_structure.hpp>
 #include <phl.hpp>
 
 using std::vector;
 using std::size_t;
+using std::unordered_map;
 using std::find;
 using std::move;
 
@@ -16,9 +18,34 @@ partial_structure::partial_structure(const phl_signature& sig) {
     }
 }
 
+size_t add_carrier_element(partial_structure& pstruct, sort s) {
+    size_t new_id = pstruct.carrier.size();
+    pstruct.carrier[new_id] = s;
+    size_t uf_id = add_element(pstruct.equality);
+    assert(uf_id == new_id);
+    (void)uf_id;
+    if (!pstruct.checkpoints.empty()) {
+        pstruct.trail.push_back(carrier_addition{new_id});
+    }
+    return new_id;
+}
+
+bool insert_row(partial_structure& pstruct, const relation& rel, vector<size_t> row) {
+    auto& rows = pstruct.relations[rel];
+    if (pstruct.checkpoints.empty()) {
+        return rows.insert(move(row)).second;
+    }
+    if (!rows.insert(row).second) {
+        return false;
+    }
+    pstruct.trail.push_back(relation_insert{rel, move(row)});
+    return true;
+}
+
 void compact_relations(partial_structure& pstruct) {
+    bool recording = !pstruct.checkpoints.empty();
     vector<vector<size_t>> changed_rows;
-    for (auto& [_, rows] : pstruct.relations) {
+    for (auto& [rel, rows] : pstruct.relations) {
         changed_rows.clear();
         auto it = rows.begin();
         while (it != rows.end()) {
@@ -33,6 +60,9 @@ void compact_relations(partial_structure& pstruct) {
                     changed_row.push_back(get_representative(pstruct.equality, arg));
                 }
                 changed_rows.push_back(move(changed_row));
+                if (recording) {
+                    pstruct.trail.push_back(relation_erase{rel, *it});
+                }
                 it = rows.erase(it);
             } else {
                 ++it;
@@ -40,7 +70,151 @@ void compact_relations(partial_structure& pstruct) {
         }
 
         for (vector<size_t>& changed_row : changed_rows) {
-            rows.insert(move(changed_row));
+            if (rows.insert(changed_row).second && recording) {
+                pstruct.trail.push_back(relation_insert{rel, move(changed_row)});
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+void checkpoint(partial_structure& pstruct) {
+    pstruct.checkpoints.push_back({pstruct.trail.size(), pstruct.equality.links.size()});
+    pstruct.equality.recording = true;
+}
+
+// Deactivates the most recent checkpoint. Nothing needs to be recorded
+// anymore once there are no active checkpoints left.
+static void pop_checkpoint(partial_structure& pstruct) {
+    pstruct.checkpoints.pop_back();
+    if (pstruct.checkpoints.empty()) {
+        pstruct.trail.clear();
+        pstruct.equality.links.clear();
+        pstruct.equality.recording = false;
+    }
+}
+
+bool rollback(partial_structure& pstruct) {
+    if (pstruct.checkpoints.empty()) {
+        return false;
+    }
+    auto [trail_size, links_size] = pstruct.checkpoints.back();
+    // Links of elements added since the checkpoint are undone before the
+    // elements are removed.
+    undo_links(pstruct.equality, links_size);
+    while (pstruct.trail.size() > trail_size) {
+        std::visit(overloaded {
+            [&](const carrier_addition& addition) {
+                pstruct.carrier.erase(addition.element);
+                assert(pstruct.equality.size() == addition.element + 1);
+                pstruct.equality.pop_back();
+            },
+            [&](const relation_insert& insert) {
+                pstruct.relations[insert.rel].erase(insert.row);
+            },
+            [&](const relation_erase& erase) {
+                pstruct.relations[erase.rel].insert(erase.row);
+            },
+        }, pstruct.trail.back());
+        pstruct.trail.pop_back();
+    }
+    pop_checkpoint(pstruct);
+    return true;
+}
+
+bool commit(partial_structure& pstruct) {
+    if (pstruct.checkpoints.empty()) {
+        return false;
+    }
+    pop_checkpoint(pstruct);
+    return true;
+}
+
+vector<size_t> compact_structure(
+    partial_structure& pstruct,
+    const vector<size_t>& roots,
+    const vector<operation>& attributes
+) {
+    assert(pstruct.checkpoints.empty());
+    size_t size = pstruct.equality.size();
+
+    // Rows only contain representatives, so marking works on those. Needed
+    // representatives are marked, and their rows are visited through these
+    // indices.
+    unordered_map<size_t, vector<const vector<size_t>*>> defining_rows;
+    unordered_map<size_t, vector<size_t>> attribute_values;
+    for (const auto& [rel, rows] : pstruct.relations) {
+        const operation* op = std::get_if<operation>(&rel);
+        if (!op) {
+            continue;
+        }
+        bool is_attribute = find(attributes.begin(), attributes.end(), *op) != attributes.end();
+        for (const vector<size_t>& row : rows) {
+            defining_rows[row.back()].push_back(&row);
+            if (is_attribute) {
+                attribute_values[row[0]].push_back(row.back());
+            }
+        }
+    }
+
+    vector<bool> marked(size, false);
+    vector<size_t> worklist;
+    auto mark = [&](size_t el) {
+        if (!marked[el]) {
+            marked[el] = true;
+            worklist.push_back(el);
+        }
+    };
+    for (size_t root : roots) {
+        mark(get_representative(pstruct.equality, root));
+    }
+    while (!worklist.empty()) {
+        size_t el = worklist.back();
+        worklist.pop_back();
+        for (const vector<size_t>* row : defining_rows[el]) {
+            for (size_t i = 0; i + 1 < row->size(); i++) {
+                mark((*row)[i]);
+            }
+        }
+        for (size_t value : attribute_values[el]) {
+            mark(value);
+        }
+    }
+
+    vector<bool> kept = marked;
+    for (size_t root : roots) {
+        kept[root] = true;
+    }
+    vector<size_t> new_ids(size, removed_element);
+    size_t next_id = 0;
+    for (size_t el = 0; el < size; el++) {
+        if (kept[el]) {
+            new_ids[el] = next_id++;
+        }
+    }
+
+    std::unordered_map<size_t, sort> carrier;
+    union_find equality(next_id);
+    for (size_t el = 0; el < size; el++) {
+        if (kept[el]) {
+            carrier[new_ids[el]] = pstruct.carrier.at(el);
+            equality[new_ids[el]] = new_ids[get_representative(pstruct.equality, el)];
+        }
+    }
+    for (auto& [_, rows] : pstruct.relations) {
+        std::unordered_set<vector<size_t>> new_rows;
+        for (const vector<size_t>& row : rows) {
+            if (all_of(row.begin(), row.end(), [&](size_t el) { return marked[el]; })) {
+                vector<size_t> new_row;
+                new_row.reserve(row.size());
+                for (size_t el : row) {
+                    new_row.push_back(new_ids[el]);
+                }
+                new_rows.insert(move(new_row));
+            }
+        }
+        rows = move(new_rows);
+    }
+    pstruct.carrier = move(carrier);
+    pstruct.equality = move(equality);
+    return new_ids;
+}
@@ -3,6 +3,13 @@
 using std::vector;
 using std::size_t;
 
+static void set_parent(union_find& uf, size_t el, size_t parent) {
+    if (uf.recording) {
+        uf.links.push_back({el, uf[el]});
+    }
+    uf[el] = parent;
+}
+
 size_t add_element(union_find& uf) {
     size_t new_element = uf.size();
     uf.push_back(new_element);
@@ -15,10 +22,19 @@ size_t get_representative(union_find& uf, size_t el) {
 
     // poor man's path compression via recursion
     size_t repr = get_representative(uf, uf[el]);
-    uf[el] = repr;
+    if (uf[el] != repr) {
+        set_parent(uf, el, repr);
+    }
     return repr;
 }
 void merge_into(union_find& uf, size_t a, size_t b) {
     size_t repr_a = get_representative(uf, a);
-    uf[repr_a] = b;
+    set_parent(uf, repr_a, b);
+}
+void undo_links(union_find& uf, size_t links_size) {
+    while (uf.links.size() > links_size) {
+        auto [el, parent] = uf.links.back();
+        uf[el] = parent;
+        uf.links.pop_back();
+    }
 }
@@ -0,0 +1,120 @@
+#define DOCTEST_CONFIG_IMPLEMENT_WITH_MAIN
+#include <doctest/doctest.h>
+
+#include <check.hpp>
+#include <closure.hpp>
+#include <cwf.hpp>
+
+using std::vector;
+
+// two sorts, so that sort errors can happen
+static const sort s{"s"}, t{"t"};
+static const predicate p{"p", {s}};
+static const operation
+    op{"op", {s}, t},
+    op2{"op2", {t, s}, s},
+    c{"c", {}, s};
+static const phl_signature signature{{s, t}, {p}, {op, op2, c}};
+
+static phl_theory injective(vector<sequent> axioms) {
+    return {signature, std::move(axioms), {}, {}};
+}
+
+static phl_theory surjective(vector<sequent> axioms) {
+    return {signature, {}, std::move(axioms), {}};
+}
+
+static const term x = "x", y = "y";
+
+TEST_CASE("check_theory should accept the cwf theory") {
+    REQUIRE_NOTHROW(check_theory(cwf::cwf));
+}
+
+TEST_CASE("check_theory should accept well-sorted theories") {
+    REQUIRE_NOTHROW(check_theory(injective({p(x) |= !op(x), truth |= !op2(op(c()), c())})));
+    // y only gets its sort from x
+    REQUIRE_NOTHROW(check_theory(surjective({p(x) && x % y |= p(y), op(x) % op(y) |= x % y})));
+}
+
+TEST_CASE("check_theory should reject operations applied to the wrong number of arguments") {
+    REQUIRE_THROWS_WITH(
+        check_theory(injective({truth |= !op2(op(x))})),
+        "injective axiom truth |= !op2(op(x)): op2(op(x)) applies op2 to 1 argument, but it takes 2");
+    REQUIRE_THROWS_WITH(
+        check_theory(surjective({p(x, y) |= p(x)})),
+        "surjective axiom p(x, y) |= p(x): p(x, y) applies p to 2 arguments, but it takes 1");
+}
+
+TEST_CASE("check_theory should reject arguments of the wrong sort") {
+    REQUIRE_THROWS_WITH(
+        check_theory(injective({truth |= !op(op(x))})),
+        "injective axiom truth |= !op(op(x)): op takes a s as argument 1 of op(op(x)), "
+        "but op(x) is a t");
+    // x is an s from p, so it is not a t
+    REQUIRE_THROWS_WITH(
+        check_theory(injective({p(x) |= !op2(x, x)})),
+        "injective axiom p(x) |= !op2(x, x): op2 takes a t as argument 1 of op2(x, x), "
+        "but x is a s");
+}
+
+TEST_CASE("check_theory should reject equalities between terms of different sorts") {
+    REQUIRE_THROWS_WITH(
+        check_theory(surjective({op(x) % y && p(y) |= p(x)})),
+        "surjective axiom op(x) = y && p(y) |= p(x): op(x) is a t, but y is a s");
+}
+
+TEST_CASE("check_theory should reject variables of unknown sort") {
+    REQUIRE_THROWS_WITH(
+        check_theory(injective({x % y |= !c()})),
+        "injective axiom x = y |= !c(): the sort of x can not be inferred");
+}
+
+TEST_CASE("check_theory should reject what is not part of the signature") {
+    operation other{"other", {s}, s};
+    predicate q{"q", {s}};
+    REQUIRE_THROWS_WITH(
+        check_theory(injective({p(x) |= !other(x)})),
+        "injective axiom p(x) |= !other(x): operation other is not part of the signature");
+    REQUIRE_THROWS_WITH(
+        check_theory(surjective({p(x) |= q(x)})),
+        "surjective axiom p(x) |= q(x): predicate q is not part of the signature");
+
+    sort u{"u"};
+    phl_theory theory = injective({});
+    theory.signature.operations.push_back({"d", {}, u});
+    REQUIRE_THROWS_WITH(
+        check_theory(theory),
+        "sort u of operation d is not part of the signature");
+}
+
+TEST_CASE("check_theory should reject surjective conclusions with unbound terms") {
+    REQUIRE_THROWS_WITH(
+        check_theory(surjective({p(x) |= p(op2(op(x), x))})),
+        "surjective axiom p(x) |= p(op2(op(x), x)): "
+        "conclusion mentions op2(op(x), x) which is not bound by the premise");
+    // the same sequent is fine as an injective axiom
+    REQUIRE_NOTHROW(check_theory(injective({p(x) |= p(op2(op(x), x))})));
+}
+
+TEST_CASE("check_theory should reject conflicts with unbound witnesses") {
+    phl_theory theory = injective({});
+    theory.conflicts.push_back({p(x), y});
+    REQUIRE_THROWS_WITH(
+        check_theory(theory),
+        "conflict p(x) |= falsity: the sort of y can not be inferred");
+    theory.conflicts.back().witness = c();
+    REQUIRE_THROWS_WITH(
+        check_theory(theory),
+        "conflict p(x) |= falsity: witness c() is not bound by the premise");
+}
+
+TEST_CASE("check_theory should reject malformed cwf axioms") {
+    using namespace cwf;
+    // a is used both as a type and as a term
+    REQUIRE_THROWS_AS(
+        check_theory({cwf_signature, {ty_ctx(a) == dom(f) |= !subst_tm(f, a)}, {}, {}}),
+        malformed_sequent);
+    REQUIRE_THROWS_AS(
+        check_theory({cwf_signature, {}, {subst_tm(mor_ext(id(G), b), a) -= b}, {}}),
+        malformed_sequent);
+}
@@ -300,6 +300,23 @@ TEST_CASE("plan_surjective_conclusion of p1(x, y) && p2(x, z) |= x % y && p1(x,
     });
 }
 
+TEST_CASE("formula_join_plan rejects equalities between unbound variables") {
+    term x = "x", y = "y";
+    REQUIRE_THROWS_AS(formula_join_plan(x % y), malformed_sequent);
+}
+
+TEST_CASE("plan_surjective_conclusion rejects unbound variables") {
+    sort s{"s"};
+    predicate p{"p", {s, s}};
+    operation op{"op", {s}, s};
+    term x = "x", y = "y", z = "z";
+
+    join_plan premise_plan = formula_join_plan(p(x, y));
+    REQUIRE_THROWS_AS(plan_surjective_conclusion(premise_plan, p(x, z)), malformed_sequent);
+    REQUIRE_THROWS_AS(plan_surjective_conclusion(premise_plan, x % z), malformed_sequent);
+    REQUIRE_THROWS_AS(plan_surjective_conclusion(premise_plan, !op(z)), malformed_sequent);
+}
+
 TEST_CASE("surjective_closure_step should work for transitivity") {
     sort s{"s"};
     predicate p{"p", {s, s}};
@@ -433,7 +450,7 @@ TEST_CASE("surjective_closure should work for antisymmetry and two elements") {
         {1, 0},
     };
 
-    surjective_closure({antisymmetry}, pstruct);
+    closure_stats stats = surjective_closure({antisymmetry}, pstruct);
     auto repr = [&](size_t i) {
         return get_representative(pstruct.equality, i);
     };
@@ -442,6 +459,44 @@ TEST_CASE("surjective_closure should work for antisymmetry and two elements") {
     REQUIRE(pstruct.relations[p] == rows{
         {repr(0), repr(0)}
     });
+    // both orders of the two rows, then the single row left
+    REQUIRE(stats.rounds == 2);
+    REQUIRE(stats.joined_rows == 3);
+    REQUIRE(stats.merges == 1);
+    REQUIRE(stats.sequents.size() == 1);
+    REQUIRE(stats.sequents[0].name == "p(x, y) && p(y, x) |= x = y");
+    // 2 + 2 * 2 rows tried in the first round, 1 + 1 in the second
+    REQUIRE(stats.sequents[0].visited_rows == 8);
+    REQUIRE(stats.sequents[0].joined_rows == 3);
+    REQUIRE(stats.sequents[0].merges == 1);
+    REQUIRE(stats.sequents[0].facts == 0);
+}
+
+TEST_CASE("sequent_to_string") {
+    sort s{"s"};
+    predicate p{"p", {s}};
+    operation o{"o", {s}, s};
+    operation c{"c", {}, s};
+    term x = "x", y = "y";
+    REQUIRE(sequent_to_string(o(x) -= x) == "o(x) -= x");
+    REQUIRE(sequent_to_string(p(x) && y % o(x) |= p(y)) == "p(x) && y = o(x) |= p(y)");
+    REQUIRE(sequent_to_string(truth |= !c()) == "truth |= !c()");
+}
+
+TEST_CASE("accumulate should add the counters of sequents with the same name") {
+    closure_stats into{1, 2, 3, 1, {{"a", 1, 1, 1, 0}, {"b", 5, 2, 0, 0}}};
+    closure_stats from{1, 3, 1, 0, {{"c", 4, 1, 0, 1}, {"a", 2, 0, 0, 0}}};
+    accumulate(into, from);
+    REQUIRE(into.closures == 2);
+    REQUIRE(into.rounds == 5);
+    REQUIRE(into.joined_rows == 4);
+    REQUIRE(into.merges == 1);
+    REQUIRE(into.sequents.size() == 3);
+    REQUIRE(into.sequents[0].name == "a");
+    REQUIRE(into.sequents[0].visited_rows == 3);
+    REQUIRE(into.sequents[1].visited_rows == 5);
+    REQUIRE(into.sequents[2].name == "c");
+    REQUIRE(into.sequents[2].facts == 1);
 }
 
 TEST_CASE("surjective_closure should work for antisymmetry requiring most likely 2 iterrations") {
@@ -550,3 +605,290 @@ TEST_CASE("surjective_closure should make operations functional") {
         {repr(2), 1, repr(4)}
     });
 }
+
+// A cycle of p of length n, which antisymmetry and transitivity take a few
+// rounds to collapse.
+static partial_structure cycle(const sort& s, const predicate& p, size_t n) {
+    partial_structure pstruct;
+    for (size_t i = 0; i != n; ++i) {
+        pstruct.equality.push_back(i);
+        pstruct.carrier.insert({i, s});
+    }
+    for (size_t i = 0; i != n; ++i) {
+        pstruct.relations[p].insert({i, (i + 1) % n});
+    }
+    return pstruct;
+}
+
+TEST_CASE("surjective_closure should stop after the maximal number of rounds") {
+    sort s{"s"};
+    predicate p{"p", {s, s}};
+    term x = "x", y = "y", z = "z";
+    vector<sequent> sequents = {p(x, y) && p(y, x) |= x % y, p(x, y) && p(y, z) |= p(x, z)};
+    partial_structure pstruct = cycle(s, p, 10);
+
+    closure_limits limits;
+    limits.max_rounds = 2;
+    try {
+        surjective_closure(sequents, pstruct, 1, limits);
+        FAIL("the closure should need more than 2 rounds");
+    } catch (const limit_exceeded& e) {
+        REQUIRE(std::string(e.what()) == "no fixpoint after 2 rounds");
+        REQUIRE(e.stats.closures == 1);
+        REQUIRE(e.stats.rounds == 2);
+        REQUIRE(e.stats.sequents[1].facts > 0);
+    }
+    // what was derived is kept, but the cycle is not collapsed yet
+    REQUIRE(pstruct.relations[p].size() > 10);
+    REQUIRE(get_representative(pstruct.equality, 0) != get_representative(pstruct.equality, 1));
+
+    // a later closure continues from there
+    surjective_closure(sequents, pstruct);
+    REQUIRE(get_representative(pstruct.equality, 0) == get_representative(pstruct.equality, 1));
+}
+
+TEST_CASE("surjective_closure should not start on a carrier that is too large") {
+    sort s{"s"};
+    predicate p{"p", {s, s}};
+    term x = "x", y = "y";
+    partial_structure pstruct = cycle(s, p, 10);
+
+    closure_limits limits;
+    limits.max_carrier_size = 9;
+    REQUIRE_THROWS_AS(
+        surjective_closure({p(x, y) && p(y, x) |= x % y}, pstruct, 1, limits),
+        limit_exceeded);
+    REQUIRE(pstruct.relations[p].size() == 10);
+}
+
+TEST_CASE("surjective_closure should stop at its deadline") {
+    sort s{"s"};
+    predicate p{"p", {s, s}};
+    term x = "x", y = "y", z = "z";
+    vector<sequent> sequents = {p(x, y) && p(y, x) |= x % y, p(x, y) && p(y, z) |= p(x, z)};
+    partial_structure pstruct = cycle(s, p, 10);
+
+    closure_limits limits;
+    limits.deadline = std::chrono::steady_clock::now();
+    try {
+        surjective_closure(sequents, pstruct, 1, limits);
+        FAIL("the deadline has passed after the first round");
+    } catch (const limit_exceeded& e) {
+        REQUIRE(e.stats.rounds == 1);
+    }
+}
+
+TEST_CASE("surjective_closure should not fail if it reaches its fixpoint within the limits") {
+    sort s{"s"};
+    predicate p{"p", {s, s}};
+    term x = "x", y = "y";
+    sequent antisymmetry = p(x, y) && p(y, x) |= x % y;
+
+    partial_structure pstruct;
+    pstruct.equality = {0, 1};
+    pstruct.carrier = {
+        {0, s},
+        {1, s},
+    };
+    pstruct.relations[p] = {
+        {0, 1},
+        {1, 0},
+    };
+
+    closure_limits limits;
+    // the second round derives nothing
+    limits.max_rounds = 2;
+    limits.max_carrier_size = 2;
+    closure_stats stats = surjective_closure({antisymmetry}, pstruct, 1, limits);
+    REQUIRE(stats.rounds == 2);
+    REQUIRE(get_representative(pstruct.equality, 0) == get_representative(pstruct.equality, 1));
+}
+
+TEST_CASE("find_conflicts should find the witnesses of derived conflicts") {
+    sort s{"s"};
+    predicate p{"p", {s, s}};
+    predicate distinct{"distinct", {s, s}};
+    term x = "x", y = "y";
+    // distinct elements that the antisymmetry of p identifies
+    vector<conflict> conflicts = {{distinct(x, x), x}, {p(x, y) && distinct(y, y), x}};
+    partial_structure pstruct = cycle(s, p, 3);
+    pstruct.relations[distinct] = {{0, 1}};
+
+    REQUIRE(find_conflicts(conflicts, pstruct).empty());
+    surjective_closure(
+        {p(x, y) && p(y, x) |= x % y, p(x, y) && p("z"_v, x) |= p("z"_v, y)},
+        pstruct);
+    size_t repr = get_representative(pstruct.equality, 0);
+    vector<conflict_match> matches = find_conflicts(conflicts, pstruct);
+    REQUIRE(matches.size() == 2);
+    REQUIRE(matches[0].conflict == 0);
+    REQUIRE(matches[0].witness == repr);
+    REQUIRE(matches[1].conflict == 1);
+    REQUIRE(matches[1].witness == repr);
+}
+
+TEST_CASE("find_conflicts should reject a witness that is not bound by the premise") {
+    sort s{"s"};
+    predicate p{"p", {s, s}};
+    partial_structure pstruct = cycle(s, p, 2);
+    REQUIRE_THROWS_AS(
+        find_conflicts({{p("x"_v, "y"_v), "z"_v}}, pstruct),
+        malformed_sequent);
+}
+
+TEST_CASE("surjective_closure should not depend on the number of threads") {
+    sort s{"s"};
+    predicate p{"p", {s, s}};
+    predicate q{"q", {s}};
+    operation op{"op", {s}, s};
+    term x = "x", y = "y", z = "z", u = "u", v = "v";
+    sequent antisymmetry = p(x, y) && p(y, x) |= x % y;
+    sequent transitivity = p(x, y) && p(y, z) |= p(x, z);
+    sequent congruence = p(x, y) && op(x) % u && op(y) % v |= p(u, v);
+    sequent marked = q(x) && p(x, y) |= q(y);
+
+    // two cycles of p, joined by op
+    partial_structure pstruct;
+    size_t n = 12;
+    for (size_t i = 0; i != 2 * n; ++i) {
+        add_carrier_element(pstruct, s);
+        pstruct.relations[op];
+        pstruct.relations[q];
+    }
+    for (size_t i = 0; i != n; ++i) {
+        insert_row(pstruct, p, {i, (i + 1) % n});
+        insert_row(pstruct, op, {i, n + i});
+        insert_row(pstruct, p, {n + (i + 1) % n, n + i});
+    }
+    insert_row(pstruct, q, {0});
+
+    // Path compression may differ, so parent pointers are not compared.
+    auto representatives = [&](partial_structure& result) {
+        vector<size_t> reprs;
+        for (size_t i = 0; i != 2 * n; ++i) {
+            reprs.push_back(get_representative(result.equality, i));
+        }
+        return reprs;
+    };
+    std::vector<sequent> sequents = {antisymmetry, transitivity, congruence, marked};
+    partial_structure sequential = pstruct;
+    surjective_closure(sequents, sequential);
+    for (size_t thread_count : {2, 3, 8}) {
+        partial_structure parallel = pstruct;
+        surjective_closure(sequents, parallel, thread_count);
+        REQUIRE(representatives(parallel) == representatives(sequential));
+        REQUIRE(parallel.relations == sequential.relations);
+    }
+    vector<size_t> reprs = representatives(sequential);
+    REQUIRE(reprs[n - 1] == reprs[0]);
+    REQUIRE(reprs[2 * n - 1] == reprs[n]);
+    REQUIRE(reprs[0] != reprs[n]);
+    REQUIRE(sequential.relations[op] == rows{{reprs[0], reprs[n]}});
+}
+
+TEST_CASE("parallel_closure_step should rethrow failures of the first plan") {
+    sort s{"s"};
+    predicate p{"p", {s}};
+    predicate missing{"missing", {s}};
+    term x = "x";
+
+    partial_structure pstruct;
+    pstruct.relations[p];
+    vector<pair<join_plan, surjective_conclusion_plan>> plans;
+    for (const sequent& seq : {p(x) |= p(x), missing(x) |= p(x), p(x) |= p(x)}) {
+        join_plan premise_plan = formula_join_plan(seq.premise);
+        plans.push_back({premise_plan, plan_surjective_conclusion(premise_plan, seq.conclusion)});
+    }
+    REQUIRE_THROWS_AS(parallel_closure_step(plans, pstruct, 3), malformed_sequent);
+    plans.erase(plans.begin() + 1);
+    REQUIRE(parallel_closure_step(plans, pstruct, 3).size() == 2);
+}
+
+TEST_CASE("rollback should undo additions, closure and compaction") {
+    sort s{"s"};
+    operation op{"op", {s, s}, s};
+    predicate p{"p", {s, s}};
+    term x = "x", y = "y";
+    sequent antisymmetry = p(x, y) && p(y, x) |= x % y;
+
+    partial_structure pstruct;
+    for (size_t i = 0; i < 3; i++) {
+        add_carrier_element(pstruct, s);
+    }
+    insert_row(pstruct, op, {0, 1, 2});
+    insert_row(pstruct, p, {0, 1});
+    partial_structure before = pstruct;
+
+    checkpoint(pstruct);
+    size_t added = add_carrier_element(pstruct, s);
+    insert_row(pstruct, op, {0, 1, added});
+    insert_row(pstruct, p, {1, 0});
+    surjective_closure({antisymmetry}, pstruct);
+    REQUIRE(get_representative(pstruct.equality, 0) == get_representative(pstruct.equality, 1));
+    REQUIRE(get_representative(pstruct.equality, 2) == get_representative(pstruct.equality, 3));
+
+    REQUIRE(rollback(pstruct));
+    REQUIRE(pstruct.equality == before.equality);
+    REQUIRE(pstruct.carrier == before.carrier);
+    REQUIRE(pstruct.relations == before.relations);
+    REQUIRE(pstruct.trail.empty());
+    REQUIRE(!rollback(pstruct));
+}
+
+TEST_CASE("checkpoints should nest") {
+    sort s{"s"};
+    predicate p{"p", {s}};
+
+    partial_structure pstruct;
+    add_carrier_element(pstruct, s);
+    checkpoint(pstruct);
+    add_carrier_element(pstruct, s);
+    insert_row(pstruct, p, {1});
+    checkpoint(pstruct);
+    add_carrier_element(pstruct, s);
+    insert_row(pstruct, p, {2});
+
+    REQUIRE(rollback(pstruct));
+    REQUIRE(pstruct.carrier.size() == 2);
+    REQUIRE(pstruct.relations[p] == rows{{1}});
+
+    checkpoint(pstruct);
+    insert_row(pstruct, p, {0});
+    REQUIRE(commit(pstruct));
+    REQUIRE(rollback(pstruct));
+    REQUIRE(pstruct.carrier.size() == 1);
+    REQUIRE(pstruct.equality.size() == 1);
+    REQUIRE(pstruct.relations[p].empty());
+    REQUIRE(!commit(pstruct));
+}
+
+TEST_CASE("compact_structure should keep what roots are defined from") {
+    sort s{"s"};
+    operation c{"c", {}, s};
+    operation f{"f", {s}, s};
+    operation attr{"attr", {s}, s};
+    predicate p{"p", {s}};
+
+    // 0 = c, 1 = f(0), 2 = f(1), 3 = attr(1), 4 = f(3), 5 = c with 5 == 0
+    partial_structure pstruct;
+    for (size_t i = 0; i < 6; i++) {
+        add_carrier_element(pstruct, s);
+    }
+    insert_row(pstruct, c, {0});
+    insert_row(pstruct, f, {0, 1});
+    insert_row(pstruct, f, {1, 2});
+    insert_row(pstruct, attr, {1, 3});
+    insert_row(pstruct, f, {3, 4});
+    insert_row(pstruct, p, {1});
+    insert_row(pstruct, p, {2});
+    merge_into(pstruct.equality, 5, 0);
+
+    vector<size_t> new_ids = compact_structure(pstruct, {1, 5}, {attr});
+    REQUIRE(new_ids == vector<size_t>{0, 1, removed_element, 2, removed_element, 3});
+    REQUIRE(pstruct.carrier.size() == 4);
+    REQUIRE(pstruct.equality == union_find{0, 1, 2, 0});
+    REQUIRE(pstruct.relations[c] == rows{{0}});
+    REQUIRE(pstruct.relations[f] == rows{{0, 1}});
+    REQUIRE(pstruct.relations[attr] == rows{{1, 2}});
+    REQUIRE(pstruct.relations[p] == rows{{1}});
+}
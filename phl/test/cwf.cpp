@@ -0,0 +1,776 @@
+#define DOCTEST_CONFIG_IMPLEMENT_WITH_MAIN
+#include <doctest/doctest.h>
+
+#include <cwf.hpp>
+#include <ffi.hpp>
+#include <set>
+#include <string>
+#include <vector>
+
+using std::size_t;
+using std::vector;
+
+// A cwf structure built through the C interface, like the Rust side does.
+// Every test defines the sides of one axiom, and whatever they are made of,
+// and checks what the closure derives.
+struct cwf_test {
+    partial_structure* pstruct;
+
+    cwf_test() : pstruct(create_cwf()) {
+        REQUIRE(pstruct != nullptr);
+    }
+    cwf_test(const cwf_test&) = delete;
+    cwf_test& operator=(const cwf_test&) = delete;
+    ~cwf_test() {
+        destroy_cwf(pstruct);
+    }
+
+    size_t op(const char* name, vector<size_t> args = {}) {
+        const operation* o = get_operation(name);
+        REQUIRE(o != nullptr);
+        size_t result = 0;
+        REQUIRE(define_operation(pstruct, o, args.data(), &result) == PHL_OK);
+        return result;
+    }
+
+    void assume(const char* name, vector<size_t> args) {
+        const predicate* p = get_predicate(name);
+        REQUIRE(p != nullptr);
+        REQUIRE(define_predicate(pstruct, p, args.data()) == PHL_OK);
+    }
+
+    bool eq(size_t l, size_t r) {
+        REQUIRE(compute_fixpoint(pstruct, 1) == PHL_OK);
+        bool result = false;
+        REQUIRE(are_equal(pstruct, l, r, &result) == PHL_OK);
+        return result;
+    }
+
+    bool holds(const char* name, vector<size_t> args) {
+        const predicate* p = get_predicate(name);
+        REQUIRE(p != nullptr);
+        REQUIRE(compute_fixpoint(pstruct, 1) == PHL_OK);
+        bool result = false;
+        REQUIRE(predicate_holds(pstruct, p, args.data(), &result) == PHL_OK);
+        return result;
+    }
+
+    size_t conflicts() {
+        REQUIRE(compute_fixpoint(pstruct, 1) == PHL_OK);
+        size_t count = 0;
+        REQUIRE(find_conflicts(pstruct, &count) == PHL_OK);
+        return count;
+    }
+
+    std::multiset<std::string> conflict_names() {
+        std::multiset<std::string> names;
+        size_t count = conflicts();
+        for (size_t i = 0; i < count; ++i) {
+            phl_conflict conflict;
+            REQUIRE(get_conflict(pstruct, i, &conflict) == PHL_OK);
+            names.emplace(conflict.name, conflict.name_len);
+        }
+        return names;
+    }
+
+    // shorthands for what most tests start from: the empty context, bool in
+    // it, and the context extended by bool
+    size_t empty() { return op("empty_ctx"); }
+    size_t bool_ty() { return op("bool", {empty()}); }
+    size_t bool_ctx() { return op("ctx_ext", {bool_ty()}); }
+};
+
+// Guards that every axiom has a test below, where the injective ones are
+// covered by a test of the operation they define. Update it together with the
+// tests when adding or removing axioms.
+TEST_CASE("the cwf theory should have a test for each axiom") {
+    REQUIRE(cwf::cwf.injective_axioms.size() == 19);
+    REQUIRE(cwf::cwf.surjective_axioms.size() == 58);
+    REQUIRE(cwf::cwf.conflicts.size() == 2);
+}
+
+// The injective axioms say which operations are defined. The closure does
+// not evaluate them, the model defines the operations itself, so whether an
+// operation is defined only when their premise holds can not be tested here.
+// The tests are named after the operation instead and check that the
+// surjective axioms type what it defines.
+
+TEST_CASE("dom(f) and cod(f) of a weakening should be its contexts") {
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    t.op("ty_ctx", {t.bool_ty()});
+    REQUIRE(t.eq(t.op("dom", {w}), t.empty()));
+    REQUIRE(t.eq(t.op("cod", {w}), t.bool_ctx()));
+}
+
+TEST_CASE("id(G) should go from G to G") {
+    cwf_test t;
+    size_t i = t.op("id", {t.bool_ctx()});
+    REQUIRE(t.eq(t.op("dom", {i}), t.bool_ctx()));
+    REQUIRE(t.eq(t.op("cod", {i}), t.bool_ctx()));
+}
+
+TEST_CASE("comp(g, f) of composable morphisms should obey the identity laws") {
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    size_t i = t.op("id", {t.bool_ctx()});
+    size_t c = t.op("comp", {i, w});
+    REQUIRE(t.eq(c, w));
+}
+
+TEST_CASE("ty_ctx(A) of bool should be the context of bool") {
+    cwf_test t;
+    REQUIRE(t.eq(t.op("ty_ctx", {t.bool_ty()}), t.empty()));
+}
+
+TEST_CASE("tm_ty(a) of true should be bool") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    REQUIRE(t.eq(t.op("tm_ty", {tt}), t.bool_ty()));
+}
+
+TEST_CASE("subst_ty(f, A) should live in the codomain of f") {
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    size_t s = t.op("subst_ty", {w, t.bool_ty()});
+    t.op("cod", {w});
+    REQUIRE(t.eq(t.op("ty_ctx", {s}), t.bool_ctx()));
+}
+
+TEST_CASE("subst_tm(f, a) should move true along f") {
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    size_t s = t.op("subst_tm", {w, t.op("true", {t.empty()})});
+    t.op("cod", {w});
+    REQUIRE(t.eq(s, t.op("true", {t.bool_ctx()})));
+}
+
+TEST_CASE("empty_ctx() should be unique") {
+    cwf_test t;
+    REQUIRE(t.eq(t.op("empty_ctx"), t.op("empty_ctx")));
+}
+
+TEST_CASE("ctx_ext(A) should not be identified with the context of A") {
+    cwf_test t;
+    REQUIRE(!t.eq(t.bool_ctx(), t.empty()));
+}
+
+TEST_CASE("var(A) should have type A weakened by wkn(A)") {
+    cwf_test t;
+    size_t v = t.op("var", {t.bool_ty()});
+    size_t w = t.op("wkn", {t.bool_ty()});
+    REQUIRE(t.eq(t.op("tm_ty", {v}), t.op("subst_ty", {w, t.bool_ty()})));
+}
+
+TEST_CASE("mor_ext(f, A, b) should go from ctx_ext(A) to cod(f)") {
+    cwf_test t;
+    size_t m = t.op("mor_ext", {t.op("id", {t.empty()}), t.bool_ty(), t.op("true", {t.empty()})});
+    t.op("cod", {t.op("id", {t.empty()})});
+    REQUIRE(t.eq(t.op("dom", {m}), t.bool_ctx()));
+    REQUIRE(t.eq(t.op("cod", {m}), t.empty()));
+}
+
+TEST_CASE("Eq(a0, a1) should live in the context of a0 and a1") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t e = t.op("Eq", {tt, t.op("false", {t.empty()})});
+    t.op("ty_ctx", {t.op("tm_ty", {tt})});
+    t.bool_ty();
+    REQUIRE(t.eq(t.op("ty_ctx", {e}), t.empty()));
+}
+
+TEST_CASE("refl(a) should have type Eq(a, a)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    REQUIRE(t.eq(t.op("tm_ty", {t.op("refl", {tt})}), t.op("Eq", {tt, tt})));
+}
+
+TEST_CASE("true(G) and false(G) should have type bool(G)") {
+    cwf_test t;
+    REQUIRE(t.eq(t.op("tm_ty", {t.op("true", {t.bool_ctx()})}), t.op("bool", {t.bool_ctx()})));
+    REQUIRE(t.eq(t.op("tm_ty", {t.op("false", {t.bool_ctx()})}), t.op("bool", {t.bool_ctx()})));
+}
+
+TEST_CASE("bool_elim(G, A, at, af) should have type A over ctx_ext(bool(G))") {
+    cwf_test t;
+    size_t motive = t.op("bool", {t.bool_ctx()});
+    size_t at = t.op("false", {t.empty()});
+    size_t af = t.op("true", {t.empty()});
+    size_t elim = t.op("bool_elim", {t.empty(), motive, at, af});
+    REQUIRE(t.eq(t.op("tm_ty", {elim}), motive));
+    REQUIRE(t.eq(t.op("ty_ctx", {t.op("tm_ty", {elim})}), t.bool_ctx()));
+}
+
+// surjective axioms
+
+TEST_CASE("dom(id(G)) -= G") {
+    cwf_test t;
+    REQUIRE(t.eq(t.op("dom", {t.op("id", {t.empty()})}), t.empty()));
+}
+
+TEST_CASE("cod(id(G)) -= G") {
+    cwf_test t;
+    REQUIRE(t.eq(t.op("cod", {t.op("id", {t.empty()})}), t.empty()));
+}
+
+TEST_CASE("comp(id(G), f) -= f") {
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    REQUIRE(t.eq(t.op("comp", {t.op("id", {t.bool_ctx()}), w}), w));
+}
+
+TEST_CASE("comp(f, id(G)) -= f") {
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    REQUIRE(t.eq(t.op("comp", {w, t.op("id", {t.empty()})}), w));
+}
+
+TEST_CASE("comp(h, comp(g, f)) -= comp(comp(h, g), f)") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t g = t.op("wkn", {t.op("bool", {t.bool_ctx()})});
+    size_t h = t.op("wkn", {t.op("bool", {t.op("ctx_ext", {t.op("bool", {t.bool_ctx()})})})});
+    size_t lhs = t.op("comp", {h, t.op("comp", {g, f})});
+    size_t rhs = t.op("comp", {t.op("comp", {h, g}), f});
+    REQUIRE(t.eq(lhs, rhs));
+    REQUIRE(!t.eq(lhs, t.op("comp", {g, f})));
+}
+
+TEST_CASE("ty_ctx(subst_ty(f, A)) -= cod(f)") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t s = t.op("subst_ty", {f, t.bool_ty()});
+    REQUIRE(t.eq(t.op("ty_ctx", {s}), t.op("cod", {f})));
+}
+
+TEST_CASE("tm_ty(subst_tm(f, a)) -= subst_ty(f, tm_ty(a))") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t a = t.op("var", {t.bool_ty()});
+    size_t lhs = t.op("tm_ty", {t.op("subst_tm", {f, a})});
+    size_t rhs = t.op("subst_ty", {f, t.op("tm_ty", {a})});
+    REQUIRE(t.eq(lhs, rhs));
+}
+
+TEST_CASE("subst_ty(id(G), A) -= A") {
+    cwf_test t;
+    size_t a = t.op("Eq", {t.op("true", {t.empty()}), t.op("false", {t.empty()})});
+    REQUIRE(t.eq(t.op("subst_ty", {t.op("id", {t.empty()}), a}), a));
+}
+
+TEST_CASE("subst_tm(id(G), a) -= a") {
+    cwf_test t;
+    size_t v = t.op("var", {t.bool_ty()});
+    REQUIRE(t.eq(t.op("subst_tm", {t.op("id", {t.bool_ctx()}), v}), v));
+}
+
+TEST_CASE("subst_ty(g, subst_ty(f, A)) -= subst_ty(comp(g, f), A)") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t g = t.op("wkn", {t.op("bool", {t.bool_ctx()})});
+    size_t a = t.op("Eq", {t.op("true", {t.empty()}), t.op("false", {t.empty()})});
+    size_t lhs = t.op("subst_ty", {g, t.op("subst_ty", {f, a})});
+    size_t rhs = t.op("subst_ty", {t.op("comp", {g, f}), a});
+    REQUIRE(t.eq(lhs, rhs));
+}
+
+TEST_CASE("subst_tm(g, subst_tm(f, a)) -= subst_tm(comp(g, f), a)") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t g = t.op("wkn", {t.op("bool", {t.bool_ctx()})});
+    size_t v = t.op("var", {t.bool_ty()});
+    size_t lhs = t.op("subst_tm", {g, t.op("subst_tm", {f, v})});
+    size_t rhs = t.op("subst_tm", {t.op("comp", {g, f}), v});
+    REQUIRE(t.eq(lhs, rhs));
+}
+
+TEST_CASE("dom(wkn(A)) -= ty_ctx(A)") {
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    REQUIRE(t.eq(t.op("dom", {w}), t.op("ty_ctx", {t.bool_ty()})));
+}
+
+TEST_CASE("cod(wkn(A)) -= ctx_ext(A)") {
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    REQUIRE(t.eq(t.op("cod", {w}), t.bool_ctx()));
+}
+
+TEST_CASE("tm_ty(var(A)) -= subst_ty(wkn(A), A)") {
+    cwf_test t;
+    size_t v = t.op("var", {t.bool_ty()});
+    size_t w = t.op("wkn", {t.bool_ty()});
+    size_t s = t.op("subst_ty", {w, t.bool_ty()});
+    t.op("cod", {w});
+    REQUIRE(t.eq(t.op("tm_ty", {v}), s));
+    REQUIRE(t.eq(s, t.op("bool", {t.bool_ctx()})));
+}
+
+TEST_CASE("dom(mor_ext(f, A, b)) -= ctx_ext(A)") {
+    cwf_test t;
+    size_t m = t.op("mor_ext", {t.op("id", {t.empty()}), t.bool_ty(), t.op("true", {t.empty()})});
+    REQUIRE(t.eq(t.op("dom", {m}), t.bool_ctx()));
+}
+
+TEST_CASE("cod(mor_ext(f, A, b)) -= cod(f)") {
+    cwf_test t;
+    size_t f = t.op("id", {t.empty()});
+    size_t m = t.op("mor_ext", {f, t.bool_ty(), t.op("true", {t.empty()})});
+    REQUIRE(t.eq(t.op("cod", {m}), t.op("cod", {f})));
+}
+
+TEST_CASE("comp(mor_ext(f, A, b), wkn(A)) -= f") {
+    cwf_test t;
+    size_t f = t.op("id", {t.empty()});
+    size_t m = t.op("mor_ext", {f, t.bool_ty(), t.op("true", {t.empty()})});
+    REQUIRE(t.eq(t.op("comp", {m, t.op("wkn", {t.bool_ty()})}), f));
+}
+
+TEST_CASE("subst_tm(mor_ext(f, A, b), var(A)) -= b") {
+    cwf_test t;
+    size_t b = t.op("false", {t.empty()});
+    size_t m = t.op("mor_ext", {t.op("id", {t.empty()}), t.bool_ty(), b});
+    REQUIRE(t.eq(t.op("subst_tm", {m, t.op("var", {t.bool_ty()})}), b));
+}
+
+TEST_CASE("mor_ext uniqueness") {
+    // <wkn(A), A, var(A)> is the identity
+    cwf_test t;
+    size_t w = t.op("wkn", {t.bool_ty()});
+    size_t v = t.op("var", {t.bool_ty()});
+    size_t i = t.op("id", {t.bool_ctx()});
+    t.op("comp", {i, w});
+    t.op("subst_tm", {i, v});
+    size_t m = t.op("mor_ext", {w, t.bool_ty(), v});
+    REQUIRE(t.eq(m, i));
+}
+
+TEST_CASE("comp(g, mor_ext(f, A, b)) -= mor_ext(comp(g, f), A, subst_tm(g, b))") {
+    cwf_test t;
+    size_t f = t.op("id", {t.empty()});
+    size_t b = t.op("true", {t.empty()});
+    size_t g = t.op("wkn", {t.bool_ty()});
+    size_t lhs = t.op("comp", {g, t.op("mor_ext", {f, t.bool_ty(), b})});
+    size_t rhs = t.op("mor_ext", {t.op("comp", {g, f}), t.bool_ty(), t.op("subst_tm", {g, b})});
+    REQUIRE(t.eq(lhs, rhs));
+    // the substitution of var by true, which is not the identity
+    REQUIRE(!t.eq(lhs, t.op("id", {t.bool_ctx()})));
+}
+
+TEST_CASE("!Eq(a, b) && A == tm_ty(a) && A0 == tm_ty(b) |= A == A0") {
+    cwf_test t;
+    size_t a = t.op("var", {t.bool_ty()});
+    size_t b = t.op("var", {t.op("bool", {t.bool_ctx()})});
+    size_t a_ty = t.op("tm_ty", {a});
+    size_t b_ty = t.op("tm_ty", {b});
+    REQUIRE(!t.eq(a_ty, b_ty));
+    t.op("Eq", {a, b});
+    REQUIRE(t.eq(a_ty, b_ty));
+}
+
+TEST_CASE("ty_ctx(Eq(a, b)) -= ty_ctx(tm_ty(a))") {
+    cwf_test t;
+    size_t v = t.op("var", {t.bool_ty()});
+    size_t e = t.op("Eq", {v, v});
+    REQUIRE(t.eq(t.op("ty_ctx", {e}), t.op("ty_ctx", {t.op("tm_ty", {v})})));
+}
+
+// Builds G.(p : a = b) with the weakenings of a and b, which the variable
+// identifies, and returns them.
+static std::pair<size_t, size_t> assume_equal(cwf_test& t, size_t a, size_t b) {
+    size_t e = t.op("Eq", {a, b});
+    size_t w = t.op("wkn", {e});
+    t.op("cod", {w});
+    size_t wa = t.op("subst_tm", {w, a});
+    size_t wb = t.op("subst_tm", {w, b});
+    t.op("subst_ty", {w, e});
+    t.op("Eq", {wa, wb});
+    t.op("tm_ty", {t.op("var", {e})});
+    return {wa, wb};
+}
+
+TEST_CASE("tm_ty(c) == Eq(a, b) |= a == b") {
+    cwf_test t;
+    size_t v = t.op("var", {t.bool_ty()});
+    size_t tt = t.op("true", {t.bool_ctx()});
+    REQUIRE(!t.eq(v, tt));
+    auto [wv, wt] = assume_equal(t, v, tt);
+    REQUIRE(t.eq(wv, wt));
+    REQUIRE(!t.eq(v, tt));
+}
+
+TEST_CASE("Eq(a, b) == A && tm_ty(c) == A && tm_ty(d) == A |= c == d") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t e = t.op("Eq", {tt, tt});
+    size_t w = t.op("wkn", {e});
+    size_t wt = t.op("subst_tm", {w, tt});
+    t.op("subst_ty", {w, e});
+    t.op("Eq", {wt, wt});
+    size_t p = t.op("var", {e});
+    size_t r = t.op("refl", {wt});
+    t.op("tm_ty", {p});
+    t.op("tm_ty", {r});
+    REQUIRE(t.eq(p, r));
+}
+
+TEST_CASE("tm_ty(refl(a)) -= Eq(a, a)") {
+    cwf_test t;
+    size_t v = t.op("var", {t.bool_ty()});
+    REQUIRE(t.eq(t.op("tm_ty", {t.op("refl", {v})}), t.op("Eq", {v, v})));
+}
+
+TEST_CASE("subst_ty(f, Eq(a, b)) -= Eq(subst_tm(f, a), subst_tm(f, b))") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t a = t.op("true", {t.empty()});
+    size_t b = t.op("false", {t.empty()});
+    size_t lhs = t.op("subst_ty", {f, t.op("Eq", {a, b})});
+    size_t rhs = t.op("Eq", {t.op("subst_tm", {f, a}), t.op("subst_tm", {f, b})});
+    REQUIRE(t.eq(lhs, rhs));
+}
+
+TEST_CASE("subst_tm(f, refl(a)) -= refl(subst_tm(f, a))") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t a = t.op("true", {t.empty()});
+    size_t lhs = t.op("subst_tm", {f, t.op("refl", {a})});
+    size_t rhs = t.op("refl", {t.op("subst_tm", {f, a})});
+    REQUIRE(t.eq(lhs, rhs));
+}
+
+TEST_CASE("ty_ctx(bool(G)) -= G") {
+    cwf_test t;
+    REQUIRE(t.eq(t.op("ty_ctx", {t.op("bool", {t.bool_ctx()})}), t.bool_ctx()));
+}
+
+TEST_CASE("tm_ty(true(G)) -= bool(G)") {
+    cwf_test t;
+    REQUIRE(t.eq(t.op("tm_ty", {t.op("true", {t.empty()})}), t.bool_ty()));
+}
+
+TEST_CASE("tm_ty(false(G)) -= bool(G)") {
+    cwf_test t;
+    REQUIRE(t.eq(t.op("tm_ty", {t.op("false", {t.empty()})}), t.bool_ty()));
+}
+
+TEST_CASE("tm_ty(bool_elim(G, A, at, af)) -= A") {
+    cwf_test t;
+    size_t motive = t.op("bool", {t.bool_ctx()});
+    size_t elim = t.op(
+        "bool_elim", {t.empty(), motive, t.op("true", {t.empty()}), t.op("false", {t.empty()})});
+    REQUIRE(t.eq(t.op("tm_ty", {elim}), motive));
+}
+
+// negb as an elimination into the constant motive bool
+static size_t negb(cwf_test& t) {
+    size_t motive = t.op("bool", {t.bool_ctx()});
+    size_t at = t.op("false", {t.empty()});
+    size_t af = t.op("true", {t.empty()});
+    return t.op("bool_elim", {t.empty(), motive, at, af});
+}
+
+TEST_CASE("subst_tm(mor_ext(f, bool(G), true(D)), bool_elim(G, A, at, af)) -= subst_tm(f, at)") {
+    cwf_test t;
+    size_t f = t.op("id", {t.empty()});
+    size_t bar = t.op("mor_ext", {f, t.bool_ty(), t.op("true", {t.empty()})});
+    size_t at = t.op("false", {t.empty()});
+    t.op("subst_tm", {f, at});
+    REQUIRE(t.eq(t.op("subst_tm", {bar, negb(t)}), at));
+}
+
+TEST_CASE("subst_tm(mor_ext(f, bool(G), false(D)), bool_elim(G, A, at, af)) -= subst_tm(f, af)") {
+    cwf_test t;
+    size_t f = t.op("id", {t.empty()});
+    size_t bar = t.op("mor_ext", {f, t.bool_ty(), t.op("false", {t.empty()})});
+    size_t af = t.op("true", {t.empty()});
+    t.op("subst_tm", {f, af});
+    REQUIRE(t.eq(t.op("subst_tm", {bar, negb(t)}), af));
+}
+
+TEST_CASE("bool_elim eta") {
+    // the variable is the elimination into its own cases
+    cwf_test t;
+    size_t v = t.op("var", {t.bool_ty()});
+    size_t id = t.op("id", {t.empty()});
+    size_t true_bar = t.op("mor_ext", {id, t.bool_ty(), t.op("true", {t.empty()})});
+    size_t false_bar = t.op("mor_ext", {id, t.bool_ty(), t.op("false", {t.empty()})});
+    size_t at = t.op("subst_tm", {true_bar, v});
+    size_t af = t.op("subst_tm", {false_bar, v});
+    size_t elim = t.op("bool_elim", {t.empty(), t.op("bool", {t.bool_ctx()}), at, af});
+    REQUIRE(t.eq(elim, v));
+    REQUIRE(!t.eq(negb(t), v));
+}
+
+TEST_CASE("subst_ty(f, bool(G)) -= bool(cod(f))") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    t.op("cod", {f});
+    REQUIRE(t.eq(t.op("subst_ty", {f, t.bool_ty()}), t.op("bool", {t.bool_ctx()})));
+}
+
+TEST_CASE("subst_tm(f, true(G)) -= true(cod(f))") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t tt = t.op("true", {t.empty()});
+    t.op("cod", {f});
+    REQUIRE(t.eq(t.op("subst_tm", {f, tt}), t.op("true", {t.bool_ctx()})));
+}
+
+TEST_CASE("subst_tm(f, false(G)) -= false(cod(f))") {
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t ff = t.op("false", {t.empty()});
+    t.op("cod", {f});
+    REQUIRE(t.eq(t.op("subst_tm", {f, ff}), t.op("false", {t.bool_ctx()})));
+}
+
+TEST_CASE("subst_tm(mor_ext(f, bool(G), b), bool_elim(G, A, at, af)) -= ...") {
+    // negb of the variable of G.bool, from which G is the empty context and
+    // f the weakening into D = G.bool
+    cwf_test t;
+    size_t f = t.op("wkn", {t.bool_ty()});
+    size_t d = t.bool_ctx();
+    t.op("cod", {f});
+    size_t d_bool = t.op("bool", {d});
+    size_t b = t.op("var", {t.bool_ty()});
+    size_t at = t.op("false", {t.empty()});
+    size_t af = t.op("true", {t.empty()});
+    size_t motive = t.op("bool", {t.bool_ctx()});
+    size_t lhs = t.op("subst_tm", {t.op("mor_ext", {f, t.bool_ty(), b}), negb(t)});
+
+    size_t lift = t.op("mor_ext", {
+        t.op("comp", {t.op("wkn", {d_bool}), f}),
+        t.bool_ty(),
+        t.op("var", {d_bool})});
+    size_t lifted = t.op("bool_elim", {
+        d,
+        t.op("subst_ty", {lift, motive}),
+        t.op("subst_tm", {f, at}),
+        t.op("subst_tm", {f, af})});
+    size_t rhs = t.op("subst_tm", {t.op("mor_ext", {t.op("id", {d}), d_bool, b}), lifted});
+    REQUIRE(t.eq(lhs, rhs));
+    REQUIRE(!t.eq(lhs, b));
+}
+
+TEST_CASE("is_prop(A) && B == subst_ty(f, A) |= is_prop(B)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t a = t.op("Eq", {tt, tt});
+    size_t b = t.op("subst_ty", {t.op("wkn", {t.bool_ty()}), a});
+    t.assume("is_prop", {t.bool_ty()});
+    size_t bool_b = t.op("subst_ty", {t.op("wkn", {t.bool_ty()}), t.bool_ty()});
+    REQUIRE(t.holds("is_prop", {b}));
+    REQUIRE(t.holds("is_prop", {bool_b}));
+}
+
+TEST_CASE("is_contractible(A) && B == subst_ty(f, A) |= is_contractible(B)") {
+    cwf_test t;
+    size_t b = t.op("subst_ty", {t.op("wkn", {t.bool_ty()}), t.bool_ty()});
+    REQUIRE(!t.holds("is_contractible", {b}));
+    t.assume("is_contractible", {t.bool_ty()});
+    REQUIRE(t.holds("is_contractible", {b}));
+}
+
+TEST_CASE("is_decidable(A) && B == subst_ty(f, A) |= is_decidable(B)") {
+    cwf_test t;
+    // bool is decidable without being a proposition
+    size_t b = t.op("subst_ty", {t.op("wkn", {t.bool_ty()}), t.bool_ty()});
+    REQUIRE(t.holds("is_decidable", {b}));
+    REQUIRE(!t.holds("is_prop", {b}));
+}
+
+TEST_CASE("is_prop(A) && tm_ty(a) == A && tm_ty(b) == A |= a == b") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t ff = t.op("false", {t.empty()});
+    t.op("tm_ty", {tt});
+    t.op("tm_ty", {ff});
+    REQUIRE(!t.eq(tt, ff));
+    t.assume("is_prop", {t.bool_ty()});
+    REQUIRE(t.eq(tt, ff));
+}
+
+TEST_CASE("A == Eq(a, b) |= is_prop(A)") {
+    cwf_test t;
+    size_t a = t.op("Eq", {t.op("true", {t.empty()}), t.op("false", {t.empty()})});
+    REQUIRE(t.holds("is_prop", {a}));
+    REQUIRE(!t.holds("is_prop", {t.bool_ty()}));
+}
+
+TEST_CASE("is_contractible(A) |= is_prop(A)") {
+    cwf_test t;
+    t.assume("is_contractible", {t.bool_ty()});
+    REQUIRE(t.holds("is_prop", {t.bool_ty()}));
+}
+
+TEST_CASE("is_prop(A) && tm_ty(a) == A |= is_contractible(A)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t ff = t.op("false", {t.empty()});
+    size_t inhabited = t.op("Eq", {tt, tt});
+    size_t uninhabited = t.op("Eq", {tt, ff});
+    t.op("tm_ty", {t.op("refl", {tt})});
+    REQUIRE(t.holds("is_contractible", {inhabited}));
+    REQUIRE(!t.holds("is_contractible", {uninhabited}));
+}
+
+TEST_CASE("is_prop(A) |= is_decidable(A)") {
+    cwf_test t;
+    size_t a = t.op("Eq", {t.op("true", {t.empty()}), t.op("false", {t.empty()})});
+    REQUIRE(t.holds("is_decidable", {a}));
+}
+
+TEST_CASE("A == bool(G) |= is_decidable(A)") {
+    cwf_test t;
+    REQUIRE(t.holds("is_decidable", {t.bool_ty()}));
+}
+
+TEST_CASE("B == tm_ty(a) && is_decidable(B) && A == Eq(a, b) |= is_decidable(A)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t r = t.op("refl", {tt});
+    size_t a = t.op("Eq", {r, r});
+    REQUIRE(t.holds("is_decidable", {a}));
+}
+
+TEST_CASE("G == empty_ctx() |= is_consistent(G)") {
+    cwf_test t;
+    REQUIRE(t.holds("is_consistent", {t.empty()}));
+}
+
+TEST_CASE("is_consistent(G) && A == bool(G) && D == ctx_ext(A) |= is_consistent(D)") {
+    cwf_test t;
+    REQUIRE(t.holds("is_consistent", {t.bool_ctx()}));
+}
+
+TEST_CASE("is_consistent(G) && ty_ctx(A) == G && is_contractible(A) && D == ctx_ext(A) |= is_consistent(D)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t ff = t.op("false", {t.empty()});
+    size_t e = t.op("Eq", {tt, tt});
+    size_t inhabited = t.op("ctx_ext", {e});
+    size_t uninhabited = t.op("ctx_ext", {t.op("Eq", {tt, ff})});
+    t.op("tm_ty", {t.op("refl", {tt})});
+    t.op("ty_ctx", {e});
+    t.op("ty_ctx", {t.op("tm_ty", {tt})});
+    t.bool_ty();
+    REQUIRE(t.holds("is_consistent", {inhabited}));
+    REQUIRE(!t.holds("is_consistent", {uninhabited}));
+}
+
+TEST_CASE("is_consistent(G) && a == true(G) && b == false(G) |= tm_distinct(a, b)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.bool_ctx()});
+    size_t ff = t.op("false", {t.bool_ctx()});
+    REQUIRE(t.holds("tm_distinct", {tt, ff}));
+    REQUIRE(!t.holds("tm_distinct", {tt, tt}));
+}
+
+TEST_CASE("B == Eq(a, b) && ty_ctx(B) == G && A == bool(G) |= ty_distinct(A, B)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t e = t.op("Eq", {tt, tt});
+    t.op("ty_ctx", {e});
+    t.op("ty_ctx", {t.op("tm_ty", {tt})});
+    REQUIRE(t.holds("ty_distinct", {t.bool_ty(), e}));
+    REQUIRE(!t.holds("ty_distinct", {t.op("bool", {t.bool_ctx()}), e}));
+}
+
+TEST_CASE("tm_distinct(a0, b0) && A == Eq(a0, a1) && B == Eq(b0, b1) |= ty_distinct(A, B)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t ff = t.op("false", {t.empty()});
+    REQUIRE(t.holds("ty_distinct", {t.op("Eq", {tt, tt}), t.op("Eq", {ff, tt})}));
+}
+
+TEST_CASE("tm_distinct(a1, b1) && A == Eq(a0, a1) && B == Eq(b0, b1) |= ty_distinct(A, B)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t ff = t.op("false", {t.empty()});
+    REQUIRE(t.holds("ty_distinct", {t.op("Eq", {tt, tt}), t.op("Eq", {tt, ff})}));
+}
+
+TEST_CASE("ty_distinct(A, B) |= ty_distinct(B, A)") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t e = t.op("Eq", {tt, tt});
+    t.op("ty_ctx", {e});
+    t.op("ty_ctx", {t.op("tm_ty", {tt})});
+    REQUIRE(t.holds("ty_distinct", {e, t.bool_ty()}));
+}
+
+TEST_CASE("tm_distinct(a, b) |= tm_distinct(b, a)") {
+    cwf_test t;
+    REQUIRE(t.holds("tm_distinct", {t.op("false", {t.empty()}), t.op("true", {t.empty()})}));
+}
+
+TEST_CASE("true and false stay equal in extensions of a context in which they are") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t ff = t.op("false", {t.empty()});
+    assume_equal(t, tt, ff);
+    size_t absurd = t.op("ctx_ext", {t.op("Eq", {tt, ff})});
+    t.op("true", {absurd});
+    t.op("false", {absurd});
+    size_t absurd_bool = t.op("bool", {absurd});
+    t.op("ty_ctx", {absurd_bool});
+    size_t later = t.op("ctx_ext", {absurd_bool});
+    REQUIRE(t.eq(t.op("true", {later}), t.op("false", {later})));
+}
+
+// conflicts
+
+TEST_CASE("a == true(G) && a == false(G) |= falsity") {
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t ff = t.op("false", {t.empty()});
+    size_t absurd = t.op("ctx_ext", {t.op("Eq", {tt, ff})});
+    t.op("true", {absurd});
+    t.op("false", {absurd});
+    REQUIRE(t.conflicts() == 0);
+    assume_equal(t, tt, ff);
+    REQUIRE(t.conflicts() == 1);
+    phl_conflict conflict;
+    REQUIRE(get_conflict(t.pstruct, 0, &conflict) == PHL_OK);
+    REQUIRE(t.eq(conflict.witness, absurd));
+    REQUIRE(get_conflict(t.pstruct, 1, &conflict) == PHL_INVALID_ARGUMENT);
+}
+
+TEST_CASE("ty_distinct(A, A) && G == ty_ctx(A) |= falsity") {
+    // Eq(true, refl(true)) identifies bool with true = true, which are
+    // distinct types
+    cwf_test t;
+    size_t tt = t.op("true", {t.empty()});
+    size_t r = t.op("refl", {tt});
+    size_t e = t.op("Eq", {tt, tt});
+    t.op("tm_ty", {r});
+    t.op("ty_ctx", {e});
+    t.op("ty_ctx", {t.op("tm_ty", {tt})});
+    REQUIRE(t.conflicts() == 0);
+    t.op("Eq", {tt, r});
+    REQUIRE(t.eq(e, t.bool_ty()));
+    REQUIRE(t.conflict_names().count("ty_distinct(A, A) && G = ty_ctx(A) |= falsity") == 1);
+}
+
+// Terms that no axiom identifies stay apart.
+TEST_CASE("the closure should not identify unrelated syntax") {
+    cwf_test t;
+    size_t g = t.bool_ctx();
+    size_t tt = t.op("true", {t.empty()});
+    size_t ff = t.op("false", {t.empty()});
+    size_t v = t.op("var", {t.bool_ty()});
+    size_t w = t.op("wkn", {t.bool_ty()});
+    size_t bar = t.op("mor_ext", {t.op("id", {t.empty()}), t.bool_ty(), tt});
+    size_t negb_v = t.op("subst_tm", {t.op("mor_ext", {w, t.bool_ty(), v}), negb(t)});
+
+    REQUIRE(!t.eq(t.empty(), g));
+    REQUIRE(!t.eq(t.bool_ty(), t.op("bool", {g})));
+    REQUIRE(!t.eq(tt, ff));
+    REQUIRE(!t.eq(v, t.op("true", {g})));
+    REQUIRE(!t.eq(v, t.op("false", {g})));
+    REQUIRE(!t.eq(v, negb_v));
+    REQUIRE(!t.eq(t.op("comp", {w, bar}), t.op("id", {g})));
+    REQUIRE(!t.eq(t.op("Eq", {tt, ff}), t.op("Eq", {tt, tt})));
+    REQUIRE(t.conflicts() == 0);
+}
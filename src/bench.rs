@@ -0,0 +1,100 @@
+// Times the checking of generated defs of growing size, with the phl model
+// and with the reference model. The closure on its own is timed by phl's
+// closure-bench.
+use std::time::{Duration, Instant};
+use super::cwf_model::Cwf;
+use super::lang;
+use super::model::Model;
+use super::nbe_model::NbeModel;
+use super::type_checker::TypeChecker;
+
+// Every def is checked this often with a fresh checker, and the fastest run
+// is reported.
+const RUNS: usize = 3;
+
+struct Family {
+    name: &'static str,
+    sizes: &'static [usize],
+    def: fn(usize) -> String,
+}
+
+const FAMILIES: &[Family] = &[
+    Family { name: "nested_elim", sizes: &[2, 4, 8, 16], def: nested_elim },
+    Family { name: "telescope", sizes: &[2, 4, 6, 8], def: telescope },
+    Family { name: "lets", sizes: &[8, 32, 128, 512], def: lets },
+];
+
+// n elims of the same bool nested in the true cases.
+fn nested_elim(n: usize) -> String {
+    let mut body = "true".to_owned();
+    for _ in 0 .. n {
+        body = format!("elim b | => {} | => false end", body);
+    }
+    format!("def nested_elim (b : bool) : bool := {}.", body)
+}
+
+// trans for a chain of n equalities.
+fn telescope(n: usize) -> String {
+    let vars: Vec<String> = (0 ..= n).map(|i| format!("x{}", i)).collect();
+    let proofs: Vec<String> =
+        (1 ..= n).map(|i| format!("(p{} : x{} = x{})", i, i - 1, i)).collect();
+    format!("def telescope ({} : bool) {} : x0 = x{} := refl x0.",
+            vars.join(" "), proofs.join(" "), n)
+}
+
+// n lets, each bound to the previous one.
+fn lets(n: usize) -> String {
+    let mut body = format!("refl x{}", n);
+    for i in (1 ..= n).rev() {
+        body = format!("let x{} : bool := x{} in {}", i, i - 1, body);
+    }
+    format!("def lets (x0 : bool) : x0 = x0 := {}.", body)
+}
+
+fn time_check<M: Model>(new_model: &dyn Fn() -> M, src: &str) -> Duration {
+    let def = lang::parse_def(src).expect("generated def does not parse");
+    (0 .. RUNS).map(|_| {
+        let mut tc = TypeChecker::new(new_model());
+        let start = Instant::now();
+        if let Err(err) = tc.check_def(&def) {
+            panic!("generated def does not check:\n{}\n{}", src, err)
+        }
+        start.elapsed()
+    }).min().unwrap()
+}
+
+// phl computes its fixpoints on thread_count threads, 0 meaning one per core.
+pub fn run(thread_count: usize) {
+    println!("{:<12} {:>4} {:>12} {:>12}", "family", "n", "phl", "nbe");
+    for family in FAMILIES {
+        for &n in family.sizes {
+            let src = (family.def)(n);
+            let phl = time_check(&|| Cwf::threaded(thread_count), &src);
+            let nbe = time_check(&NbeModel::new, &src);
+            println!("{:<12} {:>4} {:>12?} {:>12?}", family.name, n, phl, nbe);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang;
+    use super::FAMILIES;
+
+    #[test]
+    fn families() {
+        assert_eq!((FAMILIES[0].def)(2),
+                   "def nested_elim (b : bool) : bool := \
+                    elim b | => elim b | => true | => false end | => false end.");
+        assert_eq!((FAMILIES[1].def)(2),
+                   "def telescope (x0 x1 x2 : bool) (p1 : x0 = x1) (p2 : x1 = x2) : x0 = x2 := \
+                    refl x0.");
+        assert_eq!((FAMILIES[2].def)(2),
+                   "def lets (x0 : bool) : x0 = x0 := \
+                    let x1 : bool := x0 in let x2 : bool := x1 in refl x2.");
+        for family in FAMILIES {
+            let src = (family.def)(family.sizes[0]);
+            assert!(lang::parse_def(&src).is_ok(), "{}", src);
+        }
+    }
+}
@@ -1,36 +1,431 @@
 #![allow(dead_code)]
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+// Handles to hash-consed syntax nodes. Two handles of the same kind obtained
+// from the same Syntax are equal if and only if the nodes are structurally
+// equal, so comparing and hashing them is O(1).
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct CtxId(Handle);
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct MorphId(Handle);
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct TyId(Handle);
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct TmId(Handle);
+
+impl CtxId { pub fn index(self) -> usize { self.0.index as usize } }
+impl MorphId { pub fn index(self) -> usize { self.0.index as usize } }
+impl TyId { pub fn index(self) -> usize { self.0.index as usize } }
+impl TmId { pub fn index(self) -> usize { self.0.index as usize } }
+
+// Truncating a Syntax frees the indices of the removed nodes for the nodes
+// interned next. Debug builds also store the number of truncations before a
+// node was interned in its handle, so that accessing a removed node through
+// its old handle panics rather than returning the node that took its place.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+struct Handle {
+    index: u32,
+    #[cfg(debug_assertions)]
+    generation: u32,
+}
+
+impl Handle {
+    #[cfg(debug_assertions)]
+    fn new(index: u32, generation: u32) -> Self {
+        Handle { index, generation }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn new(index: u32, _generation: u32) -> Self {
+        Handle { index }
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.index)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Ctx {
     Empty,
-    Comprehension(Box<Ty>),
+    Comprehension(TyId),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Morph {
-    Identity(Box<Ctx>),
-    Weakening(Box<Ty>), // G -> G.A
-    Composition(Box<Morph>, Box<Morph>), // g . f
+    Identity(CtxId),
+    Weakening(TyId), // G -> G.A
+    Composition(MorphId, MorphId), // g . f
     // f : G -> D
     // s \in Ty(G)
     // M \in Tm(fs)
     // <f, s, M> : G.s -> D
-    Extension(Box<Morph>, Box<Ty>, Box<Tm>),
+    Extension(MorphId, TyId, TmId),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Ty {
-    Subst(Box<Morph>, Box<Ty>),
-    Bool(Box<Ctx>),
-    Eq(Box<Tm>, Box<Tm>),
+    Subst(MorphId, TyId),
+    Bool(CtxId),
+    Eq(TmId, TmId),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Tm {
-    Subst(Box<Morph>, Box<Tm>),
-    Var(Box<Ty>),
-    Refl(Box<Tm>),
-    True(Box<Ctx>),
-    False(Box<Ctx>),
-    ElimBool(Box<Ctx>, Box<Ty>, Box<Tm>, Box<Tm>),
+    Subst(MorphId, TmId),
+    Var(TyId),
+    Refl(TmId),
+    True(CtxId),
+    False(CtxId),
+    ElimBool(CtxId, TyId, TmId, TmId),
+}
+
+struct Interner<N> {
+    nodes: Vec<(N, Handle)>,
+    ids: HashMap<N, Handle>,
+}
+
+impl<N: Copy + Eq + Hash> Interner<N> {
+    fn new() -> Self {
+        Interner { nodes: vec![], ids: HashMap::new() }
+    }
+
+    // Returns the handle of node and whether it was newly added, in which case
+    // the handle gets generation.
+    fn intern(&mut self, node: N, generation: u32) -> (Handle, bool) {
+        if let Some(&id) = self.ids.get(&node) {
+            return (id, false)
+        }
+        let id = Handle::new(self.nodes.len() as u32, generation);
+        self.nodes.push((node, id));
+        self.ids.insert(node, id);
+        (id, true)
+    }
+
+    fn find(&self, node: &N) -> Option<Handle> {
+        self.ids.get(node).copied()
+    }
+
+    fn get(&self, id: Handle) -> N {
+        let (node, current) = self.nodes[id.index as usize];
+        debug_assert!(current == id, "stale handle {:?}: its node was removed by a truncation", id);
+        node
+    }
+
+    fn truncate(&mut self, len: usize) {
+        for (node, _) in self.nodes.drain(len ..) {
+            self.ids.remove(&node);
+        }
+    }
+}
+
+// The number of nodes of each kind in a Syntax at some point. Truncating the
+// Syntax to a mark removes the nodes interned after it, whose handles then no
+// longer refer to anything. Models that keep data indexed by handles can
+// truncate it to the same lengths.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SyntaxMark {
+    pub ctxs: usize,
+    pub morphs: usize,
+    pub tys: usize,
+    pub tms: usize,
+}
+
+// Handles that a garbage collection of a Syntax keeps, together with
+// everything they refer to.
+#[derive(Debug, Default, Clone)]
+pub struct Roots {
+    pub ctxs: Vec<CtxId>,
+    pub morphs: Vec<MorphId>,
+    pub tys: Vec<TyId>,
+    pub tms: Vec<TmId>,
+}
+
+// The handles of the nodes after a garbage collection, indexed by their
+// handles before it. Collected nodes map to None.
+#[derive(Debug, Clone)]
+pub struct Remap {
+    pub ctxs: Vec<Option<CtxId>>,
+    pub morphs: Vec<Option<MorphId>>,
+    pub tys: Vec<Option<TyId>>,
+    pub tms: Vec<Option<TmId>>,
+}
+
+// The accessors panic if the node was collected, i.e. if it was not reachable
+// from the roots.
+impl Remap {
+    pub fn ctx(&self, id: CtxId) -> CtxId { self.ctxs[id.index()].expect("collected ctx") }
+    pub fn morph(&self, id: MorphId) -> MorphId {
+        self.morphs[id.index()].expect("collected morph")
+    }
+    pub fn ty(&self, id: TyId) -> TyId { self.tys[id.index()].expect("collected ty") }
+    pub fn tm(&self, id: TmId) -> TmId { self.tms[id.index()].expect("collected tm") }
+}
+
+// Removes the entries of data, which is indexed by handles, whose nodes were
+// collected, so that it is indexed by the new handles.
+pub fn compact<T, I>(data: &mut Vec<T>, remap: &[Option<I>]) {
+    let mut index = 0;
+    data.retain(|_| {
+        let kept = remap[index].is_some();
+        index += 1;
+        kept
+    });
+}
+
+// Arena of hash-consed CwF syntax. Children of nodes are handles into the
+// same arena, so subtrees are shared and never cloned.
+pub struct Syntax {
+    ctxs: Interner<Ctx>,
+    morphs: Interner<Morph>,
+    tys: Interner<Ty>,
+    tms: Interner<Tm>,
+    // The number of truncations and collections so far.
+    generation: u32,
+}
+
+impl Syntax {
+    pub fn new() -> Self {
+        Syntax {
+            ctxs: Interner::new(),
+            morphs: Interner::new(),
+            tys: Interner::new(),
+            tms: Interner::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn ctx(&self, id: CtxId) -> Ctx { self.ctxs.get(id.0) }
+    pub fn morph(&self, id: MorphId) -> Morph { self.morphs.get(id.0) }
+    pub fn ty(&self, id: TyId) -> Ty { self.tys.get(id.0) }
+    pub fn tm(&self, id: TmId) -> Tm { self.tms.get(id.0) }
+
+    // The intern functions return the handle of the node and whether it was
+    // not present in the arena before.
+    pub fn intern_ctx(&mut self, node: Ctx) -> (CtxId, bool) {
+        let (id, fresh) = self.ctxs.intern(node, self.generation);
+        (CtxId(id), fresh)
+    }
+    pub fn intern_morph(&mut self, node: Morph) -> (MorphId, bool) {
+        let (id, fresh) = self.morphs.intern(node, self.generation);
+        (MorphId(id), fresh)
+    }
+    pub fn intern_ty(&mut self, node: Ty) -> (TyId, bool) {
+        let (id, fresh) = self.tys.intern(node, self.generation);
+        (TyId(id), fresh)
+    }
+    pub fn intern_tm(&mut self, node: Tm) -> (TmId, bool) {
+        let (id, fresh) = self.tms.intern(node, self.generation);
+        (TmId(id), fresh)
+    }
+
+    pub fn mark(&self) -> SyntaxMark {
+        SyntaxMark {
+            ctxs: self.ctxs.nodes.len(),
+            morphs: self.morphs.nodes.len(),
+            tys: self.tys.nodes.len(),
+            tms: self.tms.nodes.len(),
+        }
+    }
+
+    pub fn truncate(&mut self, mark: SyntaxMark) {
+        self.ctxs.truncate(mark.ctxs);
+        self.morphs.truncate(mark.morphs);
+        self.tys.truncate(mark.tys);
+        self.tms.truncate(mark.tms);
+        self.generation += 1;
+    }
+
+    // Removes the nodes that are not reachable from roots. The remaining nodes
+    // keep their order, so children still come before their parents.
+    pub fn collect(&mut self, roots: &Roots) -> Remap {
+        let mut ctxs = vec![false; self.ctxs.nodes.len()];
+        let mut morphs = vec![false; self.morphs.nodes.len()];
+        let mut tys = vec![false; self.tys.nodes.len()];
+        let mut tms = vec![false; self.tms.nodes.len()];
+        let mut ctx_stack: Vec<CtxId> = roots.ctxs.clone();
+        let mut morph_stack: Vec<MorphId> = roots.morphs.clone();
+        let mut ty_stack: Vec<TyId> = roots.tys.clone();
+        let mut tm_stack: Vec<TmId> = roots.tms.clone();
+        loop {
+            if let Some(id) = ctx_stack.pop() {
+                if !std::mem::replace(&mut ctxs[id.index()], true) {
+                    if let Ctx::Comprehension(ty) = self.ctx(id) {
+                        ty_stack.push(ty);
+                    }
+                }
+            } else if let Some(id) = morph_stack.pop() {
+                if !std::mem::replace(&mut morphs[id.index()], true) {
+                    match self.morph(id) {
+                        Morph::Identity(ctx) => ctx_stack.push(ctx),
+                        Morph::Weakening(ty) => ty_stack.push(ty),
+                        Morph::Composition(g, f) => morph_stack.extend(&[g, f]),
+                        Morph::Extension(f, ty, tm) => {
+                            morph_stack.push(f);
+                            ty_stack.push(ty);
+                            tm_stack.push(tm);
+                        },
+                    }
+                }
+            } else if let Some(id) = ty_stack.pop() {
+                if !std::mem::replace(&mut tys[id.index()], true) {
+                    match self.ty(id) {
+                        Ty::Subst(f, ty) => {
+                            morph_stack.push(f);
+                            ty_stack.push(ty);
+                        },
+                        Ty::Bool(ctx) => ctx_stack.push(ctx),
+                        Ty::Eq(a, b) => tm_stack.extend(&[a, b]),
+                    }
+                }
+            } else if let Some(id) = tm_stack.pop() {
+                if !std::mem::replace(&mut tms[id.index()], true) {
+                    match self.tm(id) {
+                        Tm::Subst(f, tm) => {
+                            morph_stack.push(f);
+                            tm_stack.push(tm);
+                        },
+                        Tm::Var(ty) => ty_stack.push(ty),
+                        Tm::Refl(tm) => tm_stack.push(tm),
+                        Tm::True(ctx) | Tm::False(ctx) => ctx_stack.push(ctx),
+                        Tm::ElimBool(ctx, ty, t, f) => {
+                            ctx_stack.push(ctx);
+                            ty_stack.push(ty);
+                            tm_stack.extend(&[t, f]);
+                        },
+                    }
+                }
+            } else {
+                break
+            }
+        }
+
+        let generation = self.generation + 1;
+        let remap = Remap {
+            ctxs: renumber(&ctxs, generation, CtxId),
+            morphs: renumber(&morphs, generation, MorphId),
+            tys: renumber(&tys, generation, TyId),
+            tms: renumber(&tms, generation, TmId),
+        };
+        let old = std::mem::replace(self, Syntax { generation, ..Syntax::new() });
+        for ((node, _), _) in old.ctxs.nodes.into_iter().zip(&ctxs).filter(|(_, &kept)| kept) {
+            self.intern_ctx(match node {
+                Ctx::Empty => Ctx::Empty,
+                Ctx::Comprehension(ty) => Ctx::Comprehension(remap.ty(ty)),
+            });
+        }
+        for ((node, _), _) in old.morphs.nodes.into_iter().zip(&morphs).filter(|(_, &kept)| kept) {
+            self.intern_morph(match node {
+                Morph::Identity(ctx) => Morph::Identity(remap.ctx(ctx)),
+                Morph::Weakening(ty) => Morph::Weakening(remap.ty(ty)),
+                Morph::Composition(g, f) => Morph::Composition(remap.morph(g), remap.morph(f)),
+                Morph::Extension(f, ty, tm) =>
+                    Morph::Extension(remap.morph(f), remap.ty(ty), remap.tm(tm)),
+            });
+        }
+        for ((node, _), _) in old.tys.nodes.into_iter().zip(&tys).filter(|(_, &kept)| kept) {
+            self.intern_ty(match node {
+                Ty::Subst(f, ty) => Ty::Subst(remap.morph(f), remap.ty(ty)),
+                Ty::Bool(ctx) => Ty::Bool(remap.ctx(ctx)),
+                Ty::Eq(a, b) => Ty::Eq(remap.tm(a), remap.tm(b)),
+            });
+        }
+        for ((node, _), _) in old.tms.nodes.into_iter().zip(&tms).filter(|(_, &kept)| kept) {
+            self.intern_tm(match node {
+                Tm::Subst(f, tm) => Tm::Subst(remap.morph(f), remap.tm(tm)),
+                Tm::Var(ty) => Tm::Var(remap.ty(ty)),
+                Tm::Refl(tm) => Tm::Refl(remap.tm(tm)),
+                Tm::True(ctx) => Tm::True(remap.ctx(ctx)),
+                Tm::False(ctx) => Tm::False(remap.ctx(ctx)),
+                Tm::ElimBool(ctx, ty, t, f) =>
+                    Tm::ElimBool(remap.ctx(ctx), remap.ty(ty), remap.tm(t), remap.tm(f)),
+            });
+        }
+        remap
+    }
+
+    // Returns the handle of a context if it has been interned.
+    pub fn find_ctx(&self, node: Ctx) -> Option<CtxId> {
+        self.ctxs.find(&node).map(CtxId)
+    }
+
+    // Wraps a handle so that it is debug printed as the full syntax tree
+    // rather than as an index.
+    pub fn show<I: Copy>(&self, id: I) -> Show<'_, I> {
+        Show { syntax: self, id }
+    }
+}
+
+// Numbers the kept entries consecutively.
+fn renumber<I>(kept: &[bool], generation: u32, id: fn(Handle) -> I) -> Vec<Option<I>> {
+    let mut next = 0;
+    kept.iter()
+        .map(|&kept| if kept {
+            next += 1;
+            Some(id(Handle::new(next - 1, generation)))
+        } else {
+            None
+        })
+        .collect()
+}
+
+pub struct Show<'a, I> {
+    syntax: &'a Syntax,
+    id: I,
+}
+
+impl<'a> fmt::Debug for Show<'a, CtxId> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.syntax;
+        match s.ctx(self.id) {
+            Ctx::Empty => write!(fmt, "Empty"),
+            Ctx::Comprehension(ty) => write!(fmt, "Comprehension({:?})", s.show(ty)),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Show<'a, MorphId> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.syntax;
+        match s.morph(self.id) {
+            Morph::Identity(ctx) => write!(fmt, "Identity({:?})", s.show(ctx)),
+            Morph::Weakening(ty) => write!(fmt, "Weakening({:?})", s.show(ty)),
+            Morph::Composition(g, f) =>
+                write!(fmt, "Composition({:?}, {:?})", s.show(g), s.show(f)),
+            Morph::Extension(f, ty, tm) =>
+                write!(fmt, "Extension({:?}, {:?}, {:?})", s.show(f), s.show(ty), s.show(tm)),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Show<'a, TyId> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.syntax;
+        match s.ty(self.id) {
+            Ty::Subst(f, ty) => write!(fmt, "Subst({:?}, {:?})", s.show(f), s.show(ty)),
+            Ty::Bool(ctx) => write!(fmt, "Bool({:?})", s.show(ctx)),
+            Ty::Eq(a, b) => write!(fmt, "Eq({:?}, {:?})", s.show(a), s.show(b)),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Show<'a, TmId> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.syntax;
+        match s.tm(self.id) {
+            Tm::Subst(f, tm) => write!(fmt, "Subst({:?}, {:?})", s.show(f), s.show(tm)),
+            Tm::Var(ty) => write!(fmt, "Var({:?})", s.show(ty)),
+            Tm::Refl(tm) => write!(fmt, "Refl({:?})", s.show(tm)),
+            Tm::True(ctx) => write!(fmt, "True({:?})", s.show(ctx)),
+            Tm::False(ctx) => write!(fmt, "False({:?})", s.show(ctx)),
+            Tm::ElimBool(ctx, into, t, f) =>
+                write!(fmt, "ElimBool({:?}, {:?}, {:?}, {:?})",
+                       s.show(ctx), s.show(into), s.show(t), s.show(f)),
+        }
+    }
 }
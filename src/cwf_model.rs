@@ -1,275 +1,447 @@
-use libc::{size_t};
-use std::clone::Clone;
-use std::collections::HashMap;
-use std::ffi::CString;
-use std::hash::Hash;
-use super::model::Model;
+use super::model::{Equality, GaveUp, Judgement, Model};
 use super::cwf::*;
+use super::phl::{ClosureStats, Element, Error, Limits, Operation, Predicate, Structure};
 
-mod phl {
-    use libc::{size_t, c_char};
-    #[allow(dead_code)]
-    extern {
-        pub fn create_cwf() -> size_t;
-        pub fn destroy_cwf(cwf: size_t);
-        pub fn get_sort(name: *const c_char) -> size_t;
-        pub fn get_operation(name: *const c_char) -> size_t;
-        pub fn get_operation_arity(op: size_t) -> size_t;
-        pub fn get_predicate(name: *const c_char) -> size_t;
-        pub fn are_equal(pstruct: size_t, l: size_t, r: size_t) -> bool;
-        pub fn define_operation(pstruct: size_t, op: size_t, args: *const size_t) -> size_t;
-        pub fn define_predicate(pstruct: size_t, pred: size_t, args: *const size_t) -> size_t;
-        pub fn compute_fixpoint(pstruct: size_t);
-    }
+fn get_op(name: &str) -> Operation {
+    Operation::by_name(name).unwrap()
 }
 
-fn get_op(name: &str) -> size_t {
-    let cstr = CString::new(name).unwrap();
-    unsafe { phl::get_operation(cstr.as_ptr()) }
+fn get_pred(name: &str) -> Predicate {
+    Predicate::by_name(name).unwrap()
 }
 
 lazy_static! {
-    static ref DOM: size_t = get_op("dom");
-    static ref COD: size_t = get_op("cod");
-    static ref ID_MORPH: size_t = get_op("id");
-    static ref COMP: size_t = get_op("comp");
-    static ref TY_CTX: size_t = get_op("ty_ctx");
-    static ref TM_TY: size_t = get_op("tm_ty");
-    static ref SUBST_TY: size_t = get_op("subst_ty");
-    static ref SUBST_TM: size_t = get_op("subst_tm");
-    static ref EMPTY_CTX: size_t = get_op("empty_ctx");
-    static ref CTX_EXT: size_t = get_op("ctx_ext");
-    static ref WKN: size_t = get_op("wkn");
-    static ref VAR: size_t = get_op("var");
-    static ref MOR_EXT: size_t = get_op("mor_ext");
-    static ref EQ_TY: size_t = get_op("Eq");
-    static ref REFL: size_t = get_op("refl");
-    static ref BOOL: size_t = get_op("bool");
-    static ref TRUE: size_t = get_op("true");
-    static ref FALSE: size_t = get_op("false");
-    static ref BOOL_ELIM: size_t = get_op("bool_elim");
+    static ref DOM: Operation = get_op("dom");
+    static ref COD: Operation = get_op("cod");
+    static ref ID_MORPH: Operation = get_op("id");
+    static ref COMP: Operation = get_op("comp");
+    static ref TY_CTX: Operation = get_op("ty_ctx");
+    static ref TM_TY: Operation = get_op("tm_ty");
+    static ref SUBST_TY: Operation = get_op("subst_ty");
+    static ref SUBST_TM: Operation = get_op("subst_tm");
+    static ref EMPTY_CTX: Operation = get_op("empty_ctx");
+    static ref CTX_EXT: Operation = get_op("ctx_ext");
+    static ref WKN: Operation = get_op("wkn");
+    static ref VAR: Operation = get_op("var");
+    static ref MOR_EXT: Operation = get_op("mor_ext");
+    static ref EQ_TY: Operation = get_op("Eq");
+    static ref REFL: Operation = get_op("refl");
+    static ref BOOL: Operation = get_op("bool");
+    static ref TRUE: Operation = get_op("true");
+    static ref FALSE: Operation = get_op("false");
+    static ref BOOL_ELIM: Operation = get_op("bool_elim");
+
+    static ref IS_PROP: Predicate = get_pred("is_prop");
+    static ref IS_CONTRACTIBLE: Predicate = get_pred("is_contractible");
+    static ref IS_DECIDABLE: Predicate = get_pred("is_decidable");
+    static ref TY_DISTINCT: Predicate = get_pred("ty_distinct");
+    static ref TM_DISTINCT: Predicate = get_pred("tm_distinct");
 }
 
 pub struct Cwf {
-    pstruct: size_t,
-    ctxs: HashMap<Ctx, size_t>,
-    morphs: HashMap<Morph, size_t>,
-    tys: HashMap<Ty, size_t>,
-    tms: HashMap<Tm, size_t>,
-    dirty: bool
+    pstruct: Structure,
+    syntax: Syntax,
+    // phl elements of the syntax nodes, indexed by their handles
+    ctxs: Vec<Element>,
+    morphs: Vec<Element>,
+    tys: Vec<Element>,
+    tms: Vec<Element>,
+    dirty: bool,
+    // compute the fixpoint after every definition instead of before queries
+    eager: bool,
+    // the syntax and whether the structure was dirty at each checkpoint
+    checkpoints: Vec<(SyntaxMark, bool)>,
 }
 
 impl Cwf {
     pub fn new() -> Self {
         Cwf {
-            pstruct: unsafe { phl::create_cwf() },
-            ctxs: HashMap::new(),
-            morphs: HashMap::new(),
-            tys: HashMap::new(),
-            tms: HashMap::new(),
-            dirty: false
+            pstruct: Structure::cwf().expect("failed to create cwf structure"),
+            syntax: Syntax::new(),
+            ctxs: vec![],
+            morphs: vec![],
+            tys: vec![],
+            tms: vec![],
+            dirty: false,
+            eager: false,
+            checkpoints: vec![],
         }
     }
-}
 
-impl Drop for Cwf {
-    fn drop(&mut self) {
-        unsafe { phl::destroy_cwf(self.pstruct) }
+    // A model that closes the structure after every definition. It must
+    // give the same answers as the lazy one, which only does so on queries.
+    #[cfg(test)]
+    pub fn eager() -> Self {
+        Cwf { eager: true, ..Cwf::new() }
+    }
+
+    // A model that computes fixpoints on thread_count threads, 0 meaning one
+    // per core.
+    pub fn threaded(thread_count: usize) -> Self {
+        let mut cwf = Cwf::new();
+        cwf.pstruct.set_thread_count(thread_count);
+        cwf
+    }
+
+    // Queries that need a fixpoint which is not reached within limits are
+    // answered with GaveUp.
+    pub fn set_limits(&mut self, limits: Limits) {
+        if let Err(err) = self.pstruct.set_limits(limits) {
+            panic!("failed to set closure limits: {}", err)
+        }
+    }
+
+    pub fn closure_stats(&self) -> ClosureStats {
+        match self.pstruct.closure_stats() {
+            Ok(stats) => stats,
+            Err(err) => panic!("failed to get closure stats: {}", err),
+        }
     }
 }
 
 impl Cwf {
-    pub fn check_id_eq(&mut self, lid: size_t, rid: size_t) -> bool {
+    // If a limit is exceeded the structure stays dirty, and the next call
+    // continues the closure.
+    fn compute_fixpoint(&mut self) -> Result<(), GaveUp> {
         if self.dirty {
-            unsafe { phl::compute_fixpoint(self.pstruct) };
-            self.dirty = false
+            match self.pstruct.compute_fixpoint() {
+                Ok(()) => self.dirty = false,
+                Err(Error::LimitExceeded(msg)) => return Err(GaveUp(msg)),
+                Err(err) => panic!("failed to compute fixpoint: {}", err),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_id_eq(&mut self, lid: Element, rid: Element) -> Result<bool, GaveUp> {
+        self.compute_fixpoint()?;
+        match self.pstruct.are_equal(lid, rid) {
+            Ok(eq) => Ok(eq),
+            Err(err) => panic!("failed to compare elements: {}", err)
         }
-        unsafe { phl::are_equal(self.pstruct, lid, rid) }
     }
 
-    fn check_eq<T: Eq + Hash, F>(&mut self, map: F, l: &T, r: &T) -> bool
-        where F: FnOnce(&Self) -> &HashMap<T, size_t>
+    // Elements are Distinct if the closure derived distinct for them, which
+    // ctxs and morphs have no predicate for.
+    fn equality(
+        &mut self, distinct: Option<Predicate>, lid: Element, rid: Element) -> Result<Equality, GaveUp>
     {
-        let map = map(self);
-        let lid = *map.get(l).unwrap();
-        let rid = *map.get(r).unwrap();
-        self.check_id_eq(lid, rid)
+        if self.check_id_eq(lid, rid)? {
+            return Ok(Equality::Equal)
+        }
+        let distinct = match distinct {
+            Some(pred) => match self.pstruct.holds(pred, &[lid, rid]) {
+                Ok(holds) => holds,
+                Err(err) => panic!("failed to query {}: {}", pred.name(), err)
+            },
+            None => false,
+        };
+        Ok(if distinct { Equality::Distinct } else { Equality::Unknown })
     }
 
-    pub fn def_op(&mut self, op: size_t, args: &[size_t]) -> size_t {
+    // Syntax is well-sorted by construction, so a failure to define an
+    // operation is a bug in the model.
+    pub fn def_op(&mut self, op: Operation, args: &[Element]) -> Element {
         self.dirty = true;
-        unsafe {
-            assert_eq!(args.len(), phl::get_operation_arity(op));
-            phl::define_operation(self.pstruct, op, args.as_ptr())
+        let el = match self.pstruct.define_operation(op, args) {
+            Ok(el) => el,
+            Err(err) => panic!("failed to define {}: {}", op.name(), err)
+        };
+        // Exceeding a limit is reported by the next query instead.
+        if self.eager {
+            let _ = self.compute_fixpoint();
         }
+        el
     }
 
-    fn def_op_syn<T: Eq + Hash + Clone, F>(&mut self, map: F, node: &T, op: size_t, args: &[size_t]) -> size_t
-        where F: FnOnce(&mut Self) -> &mut HashMap<T, size_t>
-    {
-        let id = self.def_op(op, args);
-        map(self).insert(node.clone(), id);
+    // Nodes that are already interned have been defined in the partial
+    // structure before, so only fresh nodes need a new element.
+    fn def_ctx(&mut self, node: Ctx, op: Operation, args: &[Element]) -> CtxId {
+        let (id, fresh) = self.syntax.intern_ctx(node);
+        if fresh {
+            let el = self.def_op(op, args);
+            self.ctxs.push(el);
+        }
         id
     }
-    fn def_ctx(&mut self, node: Ctx, op: size_t, args: &[size_t]) -> Ctx {
-        self.def_op_syn(|s| &mut s.ctxs, &node, op, args);
-        node
-    }
-    fn def_morph(&mut self, node: Morph, op: size_t, args: &[size_t]) -> Morph {
-        let id = self.def_op_syn(|s| &mut s.morphs, &node, op, args);
-        self.def_op(*DOM, &[id]);
-        self.def_op(*COD, &[id]);
-        node
+    fn def_morph(&mut self, node: Morph, op: Operation, args: &[Element]) -> MorphId {
+        let (id, fresh) = self.syntax.intern_morph(node);
+        if fresh {
+            let el = self.def_op(op, args);
+            self.def_op(*DOM, &[el]);
+            self.def_op(*COD, &[el]);
+            self.morphs.push(el);
+        }
+        id
     }
-    fn def_ty(&mut self, node: Ty, op: size_t, args: &[size_t]) -> Ty {
-        let id = self.def_op_syn(|s| &mut s.tys, &node, op, args);
-        self.def_op(*TY_CTX, &[id]);
-        node
+    fn def_ty(&mut self, node: Ty, op: Operation, args: &[Element]) -> TyId {
+        let (id, fresh) = self.syntax.intern_ty(node);
+        if fresh {
+            let el = self.def_op(op, args);
+            self.def_op(*TY_CTX, &[el]);
+            self.tys.push(el);
+        }
+        id
     }
-    fn def_tm(&mut self, node: Tm, op: size_t, args: &[size_t]) -> Tm {
-        let id = self.def_op_syn(|s| &mut s.tms, &node, op, args);
-        self.def_op(*TM_TY, &[id]);
-        node
+    fn def_tm(&mut self, node: Tm, op: Operation, args: &[Element]) -> TmId {
+        let (id, fresh) = self.syntax.intern_tm(node);
+        if fresh {
+            let el = self.def_op(op, args);
+            self.def_op(*TM_TY, &[el]);
+            self.tms.push(el);
+        }
+        id
     }
 
-    fn get_ctx(&self, ctx: &Ctx) -> size_t {
-        *self.ctxs.get(ctx).unwrap()
+    fn get_ctx(&self, ctx: CtxId) -> Element {
+        self.ctxs[ctx.index()]
+    }
+    fn get_morph(&self, morph: MorphId) -> Element {
+        self.morphs[morph.index()]
+    }
+    fn get_ty(&self, ty: TyId) -> Element {
+        self.tys[ty.index()]
     }
-    fn get_morph(&self, morph: &Morph) -> size_t {
-        *self.morphs.get(morph).unwrap()
+    fn get_tm(&self, tm: TmId) -> Element {
+        self.tms[tm.index()]
     }
-    fn get_ty(&self, ty: &Ty) -> size_t {
-        *self.tys.get(ty).unwrap()
+
+    // Adds judgement as an assumption. Assuming a judgement that does not
+    // hold in the theory, such as Judgement::Prop of bool, makes the model trivial.
+    #[cfg(test)]
+    pub fn assume(&mut self, judgement: Judgement) {
+        let (pred, el) = self.judgement_pred(judgement);
+        self.dirty = true;
+        if let Err(err) = self.pstruct.define_predicate(pred, &[el]) {
+            panic!("failed to define {}: {}", pred.name(), err)
+        }
+        if self.eager {
+            let _ = self.compute_fixpoint();
+        }
     }
-    fn get_tm(&self, tm: &Tm) -> size_t {
-        *self.tms.get(tm).unwrap()
+
+    fn judgement_pred(&self, judgement: Judgement) -> (Predicate, Element) {
+        match judgement {
+            Judgement::Prop(ty) => (*IS_PROP, self.get_ty(ty)),
+            Judgement::Contractible(ty) => (*IS_CONTRACTIBLE, self.get_ty(ty)),
+            Judgement::Decidable(ty) => (*IS_DECIDABLE, self.get_ty(ty)),
+        }
     }
 }
 
 impl Model for Cwf {
-    fn ctx_eq(&mut self, l: &Ctx, r: &Ctx) -> bool {
-        self.check_eq(|s| &s.ctxs, l, r)
+    fn syntax(&self) -> &Syntax {
+        &self.syntax
     }
-    fn morph_eq(&mut self, l: &Morph, r: &Morph) -> bool {
-        self.check_eq(|s| &s.morphs, l, r)
+
+    fn ctx_eq(&mut self, l: CtxId, r: CtxId) -> Result<Equality, GaveUp> {
+        let (l, r) = (self.get_ctx(l), self.get_ctx(r));
+        self.equality(None, l, r)
     }
-    fn ty_eq(&mut self, l: &Ty, r: &Ty) -> bool {
-        self.check_eq(|s| &s.tys, l, r)
+    fn morph_eq(&mut self, l: MorphId, r: MorphId) -> Result<Equality, GaveUp> {
+        let (l, r) = (self.get_morph(l), self.get_morph(r));
+        self.equality(None, l, r)
     }
-    fn tm_eq(&mut self, l: &Tm, r: &Tm) -> bool {
-        self.check_eq(|s| &s.tms, l, r)
+    fn ty_eq(&mut self, l: TyId, r: TyId) -> Result<Equality, GaveUp> {
+        let (l, r) = (self.get_ty(l), self.get_ty(r));
+        self.equality(Some(*TY_DISTINCT), l, r)
+    }
+    fn tm_eq(&mut self, l: TmId, r: TmId) -> Result<Equality, GaveUp> {
+        let (l, r) = (self.get_tm(l), self.get_tm(r));
+        self.equality(Some(*TM_DISTINCT), l, r)
+    }
+
+    fn holds(&mut self, judgement: Judgement) -> Result<bool, GaveUp> {
+        let (pred, el) = self.judgement_pred(judgement);
+        self.compute_fixpoint()?;
+        match self.pstruct.holds(pred, &[el]) {
+            Ok(holds) => Ok(holds),
+            Err(err) => panic!("failed to query {}: {}", pred.name(), err)
+        }
+    }
+    // The conflicts of phl find contexts in which true and false are equal,
+    // so both are constructed in ctx first.
+    fn is_inconsistent(&mut self, ctx: CtxId) -> Result<bool, GaveUp> {
+        self.true_tm(ctx);
+        self.false_tm(ctx);
+        self.compute_fixpoint()?;
+        let conflicts = match self.pstruct.conflicts() {
+            Ok(conflicts) => conflicts,
+            Err(err) => panic!("failed to find conflicts: {}", err),
+        };
+        for conflict in conflicts {
+            if self.check_id_eq(conflict.witness, self.get_ctx(ctx))? {
+                return Ok(true)
+            }
+        }
+        Ok(false)
     }
 
-    fn empty_ctx(&mut self) -> Ctx {
+    fn empty_ctx(&mut self) -> CtxId {
         self.def_ctx(Ctx::Empty, *EMPTY_CTX, &[])
     }
-    fn comprehension(&mut self, ty: &Ty) -> Ctx {
+    fn comprehension(&mut self, ty: TyId) -> CtxId {
         self.def_ctx(
-            Ctx::Comprehension(Box::new(ty.clone())),
+            Ctx::Comprehension(ty),
             *CTX_EXT,
             &[self.get_ty(ty)]
         )
     }
-    fn weakening(&mut self, ty: &Ty) -> Morph {
+    fn weakening(&mut self, ty: TyId) -> MorphId {
         self.def_morph(
-            Morph::Weakening(Box::new(ty.clone())),
+            Morph::Weakening(ty),
             *WKN,
             &[self.get_ty(ty)]
         )
     }
-    fn var(&mut self, ty: &Ty) -> Tm {
+    fn var(&mut self, ty: TyId) -> TmId {
         self.def_tm(
-            Tm::Var(Box::new(ty.clone())),
+            Tm::Var(ty),
             *VAR,
             &[self.get_ty(ty)]
         )
     }
 
-    fn id_morph(&mut self, ctx: &Ctx) -> Morph {
+    fn id_morph(&mut self, ctx: CtxId) -> MorphId {
         self.def_morph(
-            Morph::Identity(Box::new(ctx.clone())),
+            Morph::Identity(ctx),
             *ID_MORPH,
             &[self.get_ctx(ctx)]
         )
     }
-    fn compose(&mut self, g: &Morph, f: &Morph) -> Morph {
+    fn compose(&mut self, g: MorphId, f: MorphId) -> MorphId {
         self.def_morph(
-            Morph::Composition(Box::new(g.clone()), Box::new(f.clone())),
+            Morph::Composition(g, f),
             *COMP,
             &[self.get_morph(g), self.get_morph(f)]
         )
     }
-    fn extension(&mut self, f: &Morph, ty: &Ty, tm: &Tm) -> Morph {
+    fn extension(&mut self, f: MorphId, ty: TyId, tm: TmId) -> MorphId {
         self.def_morph(
-            Morph::Extension(Box::new(f.clone()), Box::new(ty.clone()), Box::new(tm.clone())),
+            Morph::Extension(f, ty, tm),
             *MOR_EXT,
             &[self.get_morph(f), self.get_ty(ty), self.get_tm(tm)]
         )
     }
 
-    fn subst_ty(&mut self, f: &Morph, ty: &Ty) -> Ty {
+    fn subst_ty(&mut self, f: MorphId, ty: TyId) -> TyId {
         self.def_ty(
-            Ty::Subst(Box::new(f.clone()), Box::new(ty.clone())),
+            Ty::Subst(f, ty),
             *SUBST_TY,
             &[self.get_morph(f), self.get_ty(ty)]
         )
     }
-    fn subst_tm(&mut self, f: &Morph, tm: &Tm) -> Tm {
+    fn subst_tm(&mut self, f: MorphId, tm: TmId) -> TmId {
         self.def_tm(
-            Tm::Subst(Box::new(f.clone()), Box::new(tm.clone())),
+            Tm::Subst(f, tm),
             *SUBST_TM,
             &[self.get_morph(f), self.get_tm(tm)]
         )
     }
 
-    fn eq_ty(&mut self, l: &Tm, r: &Tm) -> Ty {
+    fn eq_ty(&mut self, l: TmId, r: TmId) -> TyId {
         self.def_ty(
-            Ty::Eq(Box::new(l.clone()), Box::new(r.clone())),
+            Ty::Eq(l, r),
             *EQ_TY,
             &[self.get_tm(l), self.get_tm(r)]
         )
     }
-    fn refl(&mut self, tm: &Tm) -> Tm {
+    fn refl(&mut self, tm: TmId) -> TmId {
         self.def_tm(
-            Tm::Refl(Box::new(tm.clone())),
+            Tm::Refl(tm),
             *REFL,
             &[self.get_tm(tm)]
         )
     }
 
-    fn bool_ty(&mut self, ctx: &Ctx) -> Ty {
+    fn bool_ty(&mut self, ctx: CtxId) -> TyId {
         self.def_ty(
-            Ty::Bool(Box::new(ctx.clone())),
+            Ty::Bool(ctx),
             *BOOL,
             &[self.get_ctx(ctx)]
         )
     }
-    fn true_tm(&mut self, ctx: &Ctx) -> Tm {
+    fn true_tm(&mut self, ctx: CtxId) -> TmId {
         self.def_tm(
-            Tm::True(Box::new(ctx.clone())),
+            Tm::True(ctx),
             *TRUE,
             &[self.get_ctx(ctx)]
         )
     }
-    fn false_tm(&mut self, ctx: &Ctx) -> Tm {
+    fn false_tm(&mut self, ctx: CtxId) -> TmId {
         self.def_tm(
-            Tm::False(Box::new(ctx.clone())),
+            Tm::False(ctx),
             *FALSE,
             &[self.get_ctx(ctx)]
         )
     }
-    fn elim_bool(&mut self, base_ctx: &Ctx, into: &Ty, true_case: &Tm, false_case: &Tm) -> Tm {
+    fn elim_bool(&mut self, base_ctx: CtxId, into: TyId, true_case: TmId, false_case: TmId) -> TmId {
         self.def_tm(
-            Tm::ElimBool(
-                Box::new(base_ctx.clone()),
-                Box::new(into.clone()),
-                Box::new(true_case.clone()),
-                Box::new(false_case.clone())),
+            Tm::ElimBool(base_ctx, into, true_case, false_case),
             *BOOL_ELIM,
             &[self.get_ctx(base_ctx), self.get_ty(into),
               self.get_tm(true_case), self.get_tm(false_case)]
         )
     }
+
+    // The structure is exactly as it was at the checkpoint after rolling
+    // back, so it needs a fixpoint computation if it did then.
+    fn checkpoint(&mut self) {
+        if let Err(err) = self.pstruct.checkpoint() {
+            panic!("failed to take checkpoint: {}", err)
+        }
+        self.checkpoints.push((self.syntax.mark(), self.dirty));
+    }
+    fn rollback(&mut self) {
+        let (mark, dirty) = self.checkpoints.pop().expect("rollback without checkpoint");
+        if let Err(err) = self.pstruct.rollback() {
+            panic!("failed to roll back: {}", err)
+        }
+        self.syntax.truncate(mark);
+        self.ctxs.truncate(mark.ctxs);
+        self.morphs.truncate(mark.morphs);
+        self.tys.truncate(mark.tys);
+        self.tms.truncate(mark.tms);
+        self.dirty = dirty;
+    }
+    fn commit(&mut self) {
+        self.checkpoints.pop().expect("commit without checkpoint");
+        if let Err(err) = self.pstruct.commit() {
+            panic!("failed to commit: {}", err)
+        }
+    }
+
+    // The fixpoint is computed first, so that what follows from the collected
+    // elements for the remaining ones is kept. If it is not reached within
+    // the limits, only the syntax is collected and the elements stay in the
+    // structure for the closure to continue with.
+    fn collect_garbage(&mut self, roots: &Roots) -> Remap {
+        assert!(self.checkpoints.is_empty(), "garbage collection during a checkpoint");
+        let closed = self.compute_fixpoint().is_ok();
+        let remap = self.syntax.collect(roots);
+        compact(&mut self.ctxs, &remap.ctxs);
+        compact(&mut self.morphs, &remap.morphs);
+        compact(&mut self.tys, &remap.tys);
+        compact(&mut self.tms, &remap.tms);
+        if !closed {
+            return remap
+        }
+
+        let elements: Vec<Element> =
+            self.ctxs.iter().chain(&self.morphs).chain(&self.tys).chain(&self.tms).copied().collect();
+        let renumbering = match self.pstruct.collect_garbage(&elements) {
+            Ok(renumbering) => renumbering,
+            Err(err) => panic!("failed to collect garbage: {}", err)
+        };
+        for el in self.ctxs.iter_mut()
+            .chain(&mut self.morphs)
+            .chain(&mut self.tys)
+            .chain(&mut self.tms)
+        {
+            *el = renumbering.get(*el).expect("root was collected");
+        }
+        remap
+    }
 }
 
 #[test]
@@ -280,9 +452,9 @@ fn functionality1() {
     let bool2 = cwf.def_op(*BOOL, &[empty]);
     let id_morph = cwf.def_op(*ID_MORPH, &[empty]);
     let subst_bool1 = cwf.def_op(*SUBST_TY, &[id_morph, bool1]);
-    unsafe { phl::compute_fixpoint(cwf.pstruct) }
+    cwf.pstruct.compute_fixpoint().unwrap();
     let subst_bool2 = cwf.def_op(*SUBST_TY, &[id_morph, bool2]);
-    assert!(cwf.check_id_eq(subst_bool1, subst_bool2));
+    assert_eq!(cwf.check_id_eq(subst_bool1, subst_bool2), Ok(true));
 }
 
 #[test]
@@ -290,31 +462,31 @@ fn eq_subst() {
     // f(a == a) = fa == fa
     let mut cwf = Cwf::new();
     let empty = cwf.empty_ctx();
-    let empty_bool = cwf.bool_ty(&empty);
-    let bool_ctx = cwf.comprehension(&empty_bool);
-    let a = cwf.var(&empty_bool);
-    let aeq = cwf.eq_ty(&a, &a);
-    let bool_ctx_bool = cwf.bool_ty(&bool_ctx);
+    let empty_bool = cwf.bool_ty(empty);
+    let bool_ctx = cwf.comprehension(empty_bool);
+    let a = cwf.var(empty_bool);
+    let aeq = cwf.eq_ty(a, a);
+    let bool_ctx_bool = cwf.bool_ty(bool_ctx);
     // f injects to context with another variable
-    let f = cwf.weakening(&bool_ctx_bool);
-    let faa = cwf.subst_ty(&f, &aeq);
-    let fa = cwf.subst_tm(&f, &a);
-    let fafa = cwf.eq_ty(&fa, &fa);
-    assert!(cwf.ty_eq(&fafa, &faa));
+    let f = cwf.weakening(bool_ctx_bool);
+    let faa = cwf.subst_ty(f, aeq);
+    let fa = cwf.subst_tm(f, a);
+    let fafa = cwf.eq_ty(fa, fa);
+    assert_eq!(cwf.ty_eq(fafa, faa), Ok(Equality::Equal));
 }
 
 #[test]
 fn subst_id() {
     let mut cwf = Cwf::new();
     let empty = cwf.empty_ctx();
-    let id = cwf.id_morph(&empty);
-    let true_tm = cwf.true_tm(&empty);
-    let eq_true_true = cwf.eq_ty(&true_tm, &true_tm);
-    let refl_eq_true_true = cwf.refl(&true_tm);
-    let subst_eq_true_true = cwf.subst_ty(&id, &eq_true_true);
-    assert!(cwf.ty_eq(&eq_true_true, &subst_eq_true_true));
-    let subst_refl_eq_true_true = cwf.subst_tm(&id, &refl_eq_true_true);
-    assert!(cwf.tm_eq(&refl_eq_true_true, &subst_refl_eq_true_true));
+    let id = cwf.id_morph(empty);
+    let true_tm = cwf.true_tm(empty);
+    let eq_true_true = cwf.eq_ty(true_tm, true_tm);
+    let refl_eq_true_true = cwf.refl(true_tm);
+    let subst_eq_true_true = cwf.subst_ty(id, eq_true_true);
+    assert_eq!(cwf.ty_eq(eq_true_true, subst_eq_true_true), Ok(Equality::Equal));
+    let subst_refl_eq_true_true = cwf.subst_tm(id, refl_eq_true_true);
+    assert_eq!(cwf.tm_eq(refl_eq_true_true, subst_refl_eq_true_true), Ok(Equality::Equal));
 }
 
 #[test]
@@ -324,15 +496,215 @@ fn test_subst_var() {
     // <1(G), Bool(G), True(G)>(Var(Bool(G))) = True(G)
     let mut cwf = Cwf::new();
     let empty_ctx = cwf.empty_ctx();
-    let empty_ctx_bool = cwf.bool_ty(&empty_ctx);
-    let G = cwf.comprehension(&empty_ctx_bool);
-    let G_B = cwf.bool_ty(&G);
-
-    let G_id = cwf.id_morph(&G);
-    let G_T = cwf.true_tm(&G);
-    let G_T_bar = cwf.extension(&G_id, &G_B, &G_T);
-
-    let D_var = cwf.var(&G_B);
-    let subst_var = cwf.subst_tm(&G_T_bar, &D_var);
-    assert!(cwf.tm_eq(&subst_var, &G_T));
-}
\ No newline at end of file
+    let empty_ctx_bool = cwf.bool_ty(empty_ctx);
+    let G = cwf.comprehension(empty_ctx_bool);
+    let G_B = cwf.bool_ty(G);
+
+    let G_id = cwf.id_morph(G);
+    let G_T = cwf.true_tm(G);
+    let G_T_bar = cwf.extension(G_id, G_B, G_T);
+
+    let D_var = cwf.var(G_B);
+    let subst_var = cwf.subst_tm(G_T_bar, D_var);
+    assert_eq!(cwf.tm_eq(subst_var, G_T), Ok(Equality::Equal));
+}
+#[test]
+fn hash_consing() {
+    // Structurally equal syntax gets the same handle and is only defined
+    // once in the partial structure.
+    let mut cwf = Cwf::new();
+    let empty = cwf.empty_ctx();
+    let bool1 = cwf.bool_ty(empty);
+    let num_tys = cwf.tys.len();
+    let bool2 = cwf.bool_ty(empty);
+    assert_eq!(bool1, bool2);
+    assert_eq!(num_tys, cwf.tys.len());
+
+    let a = cwf.var(bool1);
+    let eq1 = cwf.eq_ty(a, a);
+    let eq2 = cwf.eq_ty(a, a);
+    assert_eq!(eq1, eq2);
+    assert_ne!(bool1, eq1);
+}
+
+#[test]
+fn judgements() {
+    let mut cwf = Cwf::new();
+    let empty = cwf.empty_ctx();
+    let bool_ty = cwf.bool_ty(empty);
+    let true_tm = cwf.true_tm(empty);
+    let false_tm = cwf.false_tm(empty);
+    let eq = cwf.eq_ty(true_tm, true_tm);
+    assert_eq!(cwf.holds(Judgement::Prop(eq)), Ok(true));
+    assert_eq!(cwf.holds(Judgement::Contractible(eq)), Ok(false));
+    assert_eq!(cwf.holds(Judgement::Decidable(bool_ty)), Ok(true));
+    assert_eq!(cwf.holds(Judgement::Prop(bool_ty)), Ok(false));
+
+    // An inhabited proposition is contractible, also after substitution.
+    cwf.refl(true_tm);
+    let bool_ctx = cwf.comprehension(bool_ty);
+    let bool_ctx_bool = cwf.bool_ty(bool_ctx);
+    let wkn = cwf.weakening(bool_ctx_bool);
+    let wkn_eq = cwf.subst_ty(wkn, eq);
+    assert_eq!(cwf.holds(Judgement::Contractible(eq)), Ok(true));
+    assert_eq!(cwf.holds(Judgement::Contractible(wkn_eq)), Ok(true));
+
+    // Proof irrelevance follows from the judgement rather than from an
+    // equality between the terms.
+    assert_eq!(cwf.tm_eq(true_tm, false_tm), Ok(Equality::Distinct));
+    cwf.assume(Judgement::Prop(bool_ty));
+    assert_eq!(cwf.tm_eq(true_tm, false_tm), Ok(Equality::Equal));
+}
+
+#[test]
+fn distinct_constructors() {
+    let mut cwf = Cwf::new();
+    let empty = cwf.empty_ctx();
+    let bool_ty = cwf.bool_ty(empty);
+    let true_tm = cwf.true_tm(empty);
+    let false_tm = cwf.false_tm(empty);
+    let true_eq = cwf.eq_ty(true_tm, true_tm);
+    let false_eq = cwf.eq_ty(false_tm, true_tm);
+    assert_eq!(cwf.tm_eq(true_tm, false_tm), Ok(Equality::Distinct));
+    assert_eq!(cwf.ty_eq(bool_ty, true_eq), Ok(Equality::Distinct));
+    assert_eq!(cwf.ty_eq(true_eq, false_eq), Ok(Equality::Distinct));
+
+    // Constructors stay distinct after extending by bool, but not after
+    // assuming that they are equal.
+    let bool_ctx = cwf.comprehension(bool_ty);
+    let var = cwf.var(bool_ty);
+    let true_in_bool = cwf.true_tm(bool_ctx);
+    let false_in_bool = cwf.false_tm(bool_ctx);
+    assert_eq!(cwf.tm_eq(true_in_bool, false_in_bool), Ok(Equality::Distinct));
+    assert_eq!(cwf.tm_eq(var, true_in_bool), Ok(Equality::Unknown));
+    let absurd = cwf.eq_ty(true_tm, false_tm);
+    let absurd_ctx = cwf.comprehension(absurd);
+    let true_in_absurd = cwf.true_tm(absurd_ctx);
+    let false_in_absurd = cwf.false_tm(absurd_ctx);
+    assert_ne!(cwf.tm_eq(true_in_absurd, false_in_absurd), Ok(Equality::Distinct));
+}
+
+#[test]
+fn inconsistent_ctx() {
+    let mut cwf = Cwf::new();
+    let empty = cwf.empty_ctx();
+    let bool_ty = cwf.bool_ty(empty);
+    let true_tm = cwf.true_tm(empty);
+    let false_tm = cwf.false_tm(empty);
+    let bool_ctx = cwf.comprehension(bool_ty);
+    assert_eq!(cwf.is_inconsistent(empty), Ok(false));
+    assert_eq!(cwf.is_inconsistent(bool_ctx), Ok(false));
+
+    // A variable of true = false identifies the weakenings of true and false.
+    let absurd = cwf.eq_ty(true_tm, false_tm);
+    let absurd_ctx = cwf.comprehension(absurd);
+    let wkn = cwf.weakening(absurd);
+    let wkn_true = cwf.subst_tm(wkn, true_tm);
+    let wkn_false = cwf.subst_tm(wkn, false_tm);
+    cwf.subst_ty(wkn, absurd);
+    cwf.eq_ty(wkn_true, wkn_false);
+    cwf.var(absurd);
+    assert_eq!(cwf.is_inconsistent(absurd_ctx), Ok(true));
+    assert_eq!(cwf.is_inconsistent(empty), Ok(false));
+
+    // So are its extensions.
+    let later_bool = cwf.bool_ty(absurd_ctx);
+    let later_ctx = cwf.comprehension(later_bool);
+    assert_eq!(cwf.is_inconsistent(later_ctx), Ok(true));
+
+    // Assuming that bool is a proposition makes every context inconsistent.
+    cwf.assume(Judgement::Prop(bool_ty));
+    assert_eq!(cwf.is_inconsistent(empty), Ok(true));
+}
+
+#[test]
+fn rollback() {
+    let mut cwf = Cwf::new();
+    let empty = cwf.empty_ctx();
+    let bool_ty = cwf.bool_ty(empty);
+    let true_tm = cwf.true_tm(empty);
+    let false_tm = cwf.false_tm(empty);
+    let carrier_size = cwf.pstruct.carrier_size();
+
+    cwf.checkpoint();
+    cwf.comprehension(bool_ty);
+    cwf.assume(Judgement::Prop(bool_ty));
+    assert_eq!(cwf.tm_eq(true_tm, false_tm), Ok(Equality::Equal));
+    cwf.rollback();
+    assert_eq!(cwf.tm_eq(true_tm, false_tm), Ok(Equality::Distinct));
+    assert_eq!(cwf.pstruct.carrier_size(), carrier_size);
+    assert_eq!(cwf.syntax().find_ctx(Ctx::Comprehension(bool_ty)), None);
+    let bool_ctx = cwf.comprehension(bool_ty);
+    assert_eq!(cwf.ctx_eq(bool_ctx, bool_ctx), Ok(Equality::Equal));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "stale handle")]
+fn stale_handle() {
+    let mut cwf = Cwf::new();
+    let empty = cwf.empty_ctx();
+    let bool_ty = cwf.bool_ty(empty);
+    let true_tm = cwf.true_tm(empty);
+
+    cwf.checkpoint();
+    let bool_ctx = cwf.comprehension(bool_ty);
+    cwf.rollback();
+    // The next node gets the index of the removed one, but the old handle
+    // does not refer to it.
+    let eq = cwf.eq_ty(true_tm, true_tm);
+    let eq_ctx = cwf.comprehension(eq);
+    assert_eq!(eq_ctx.index(), bool_ctx.index());
+    cwf.syntax().ctx(bool_ctx);
+}
+
+#[test]
+fn collect_garbage() {
+    let mut cwf = Cwf::new();
+    let empty = cwf.empty_ctx();
+    let bool_ty = cwf.bool_ty(empty);
+    let true_tm = cwf.true_tm(empty);
+    let false_tm = cwf.false_tm(empty);
+    let bool_ctx = cwf.comprehension(bool_ty);
+    let bool_ctx_bool = cwf.bool_ty(bool_ctx);
+    let wkn = cwf.weakening(bool_ctx_bool);
+    cwf.subst_tm(wkn, true_tm);
+    cwf.assume(Judgement::Prop(bool_ty));
+    let carrier_size = cwf.pstruct.carrier_size();
+
+    let remap = cwf.collect_garbage(&Roots {
+        tms: vec![true_tm, false_tm],
+        ..Roots::default()
+    });
+    assert!(cwf.pstruct.carrier_size() < carrier_size);
+    assert_eq!(remap.ctxs[bool_ctx.index()], None);
+    assert_eq!(remap.tys[bool_ty.index()], None);
+    let empty = remap.ctx(empty);
+    let true_tm = remap.tm(true_tm);
+    let false_tm = remap.tm(false_tm);
+    assert_eq!(cwf.syntax().tm(true_tm), Tm::True(empty));
+
+    // The assumption was about the type of the terms, which is kept by the
+    // structure even though its syntax was collected.
+    assert_eq!(cwf.tm_eq(true_tm, false_tm), Ok(Equality::Equal));
+    let bool_ty = cwf.bool_ty(empty);
+    assert_eq!(cwf.holds(Judgement::Prop(bool_ty)), Ok(true));
+}
+
+#[test]
+fn limits() {
+    let mut cwf = Cwf::new();
+    cwf.set_limits(Limits { max_rounds: Some(1), ..Limits::default() });
+    let empty = cwf.empty_ctx();
+    let bool_ty = cwf.bool_ty(empty);
+    let bool_ctx = cwf.comprehension(bool_ty);
+    let wkn = cwf.weakening(bool_ty);
+    let true_tm = cwf.true_tm(empty);
+    let wkn_true = cwf.subst_tm(wkn, true_tm);
+    let ctx_true = cwf.true_tm(bool_ctx);
+    assert_eq!(cwf.tm_eq(wkn_true, ctx_true),
+               Err(GaveUp("no fixpoint after 1 rounds".to_owned())));
+    // Every query continues the closure where the last one stopped.
+    cwf.set_limits(Limits::default());
+    assert_eq!(cwf.tm_eq(wkn_true, ctx_true), Ok(Equality::Equal));
+}
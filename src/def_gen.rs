@@ -0,0 +1,218 @@
+// Generates random definitions for property tests of the type checker.
+// Well-typed definitions are built from bool, equalities, nested elims and
+// lets, and are well-typed by construction: whenever the checker has to
+// compare two types, they are equal because they were built from the same
+// expressions. Mutations then break a well-typed definition in one place.
+use super::lang::ast::*;
+use super::random::Rng;
+
+fn app(name: &str, args: Vec<Expr>) -> Expr {
+    Expr::App(name.to_owned(), args)
+}
+
+fn var(name: &str) -> Expr {
+    app(name, vec![])
+}
+
+fn eq(a: Expr, b: Expr) -> Expr {
+    app("eq", vec![a, b])
+}
+
+fn refl(a: Expr) -> Expr {
+    app("refl", vec![a])
+}
+
+fn ext(name: &str, ty: Expr) -> CtxExt {
+    CtxExt(Some(name.to_owned()), ty, Binding::Explicit, Span::default())
+}
+
+fn elim(val: Expr, into_ctx: Vec<CtxExt>, into_ty: Expr, cases: Vec<Expr>) -> Expr {
+    Expr::Elim {
+        val: Box::new(val),
+        into_ctx,
+        into_ty: Some(Box::new(into_ty)),
+        cases: cases.into_iter().map(|case| ElimCase(vec![], case)).collect(),
+    }
+}
+
+fn let_in(name: &str, ty: Expr, val: Expr, body: Expr) -> Expr {
+    Expr::Let {
+        name: Some(name.to_owned()),
+        name_span: Span::default(),
+        ty: Some(Box::new(ty)),
+        val: Box::new(val),
+        body: Box::new(body),
+    }
+}
+
+pub struct DefGen {
+    rng: Rng,
+    bools: Vec<String>,
+    // hypotheses p : a = b between bool variables
+    hyps: Vec<(String, String, String)>,
+    next_name: usize,
+}
+
+impl DefGen {
+    pub fn new(seed: u64) -> Self {
+        DefGen { rng: Rng::new(seed), bools: vec![], hyps: vec![], next_name: 0 }
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.next_name += 1;
+        format!("{}{}", prefix, self.next_name)
+    }
+
+    // An expression of type bool in the current scope.
+    fn bool_expr(&mut self, depth: usize) -> Expr {
+        let choice = if depth == 0 { self.rng.below(3) } else { self.rng.below(6) };
+        match choice {
+            0 => var("true"),
+            1 => var("false"),
+            2 if self.bools.is_empty() => var("true"),
+            2 => var(&self.rng.choose(&self.bools).clone()),
+            3 => {
+                let motive = self.fresh("m");
+                let val = self.bool_expr(depth - 1);
+                let true_case = self.bool_expr(depth - 1);
+                let false_case = self.bool_expr(depth - 1);
+                elim(val, vec![ext(&motive, var("bool"))], var("bool"), vec![true_case, false_case])
+            },
+            4 => {
+                // The motive may mention the eliminated bool as long as the
+                // type does not depend on it.
+                let motive = self.fresh("m");
+                let val = self.bool_expr(depth - 1);
+                let body = self.bool_expr(depth - 1);
+                let into_ty = let_in(&self.fresh("t"), var("bool"), var(&motive), var("bool"));
+                elim(val, vec![ext(&motive, var("bool"))], into_ty, vec![body.clone(), body])
+            },
+            _ => {
+                let name = self.fresh("l");
+                let val = self.bool_expr(depth - 1);
+                self.bools.push(name.clone());
+                let body = self.bool_expr(depth - 1);
+                self.bools.pop();
+                let_in(&name, var("bool"), val, body)
+            },
+        }
+    }
+
+    // Returns a type and a term of that type.
+    fn typed_body(&mut self, depth: usize) -> (Expr, Expr) {
+        match self.rng.below(6) {
+            0 => (var("bool"), self.bool_expr(depth)),
+            1 => {
+                let a = self.bool_expr(depth);
+                (eq(a.clone(), a.clone()), refl(a))
+            },
+            2 if !self.bools.is_empty() => {
+                // Dependent elimination: x = x by cases on x.
+                let x = self.rng.choose(&self.bools).clone();
+                let b = self.fresh("b");
+                let body = elim(
+                    var(&x),
+                    vec![ext(&b, var("bool"))],
+                    eq(var(&b), var(&b)),
+                    vec![refl(var("true")), refl(var("false"))]);
+                (eq(var(&x), var(&x)), body)
+            },
+            3 if !self.hyps.is_empty() => {
+                let (p, a, b) = self.rng.choose(&self.hyps).clone();
+                let body = if self.rng.chance(1, 2) { var(&p) } else { refl(var(&a)) };
+                (eq(var(&a), var(&b)), body)
+            },
+            4 if !self.hyps.is_empty() => {
+                let (p, _, _) = self.rng.choose(&self.hyps).clone();
+                (eq(var(&p), var(&p)), refl(var(&p)))
+            },
+            _ => {
+                // A let in the type, whose value the body must unfold.
+                let name = self.fresh("l");
+                let val = self.bool_expr(depth);
+                let ty = let_in(&name, var("bool"), val.clone(), eq(var(&name), var(&name)));
+                (ty, refl(val))
+            },
+        }
+    }
+
+    pub fn def(&mut self) -> Def {
+        self.bools.clear();
+        self.hyps.clear();
+        let mut ctx = vec![];
+        for _ in 0..self.rng.below(4) {
+            let name = self.fresh("x");
+            ctx.push(ext(&name, var("bool")));
+            self.bools.push(name);
+        }
+        if self.bools.len() >= 2 {
+            for _ in 0..self.rng.below(3) {
+                // Equalities between variables can always be satisfied, so
+                // the context stays consistent.
+                let a = self.rng.choose(&self.bools).clone();
+                let b = self.rng.choose(&self.bools).clone();
+                let p = self.fresh("p");
+                ctx.push(ext(&p, eq(var(&a), var(&b))));
+                self.hyps.push((p, a, b));
+            }
+        }
+
+        let (ret_ty, body) = self.typed_body(2);
+        Def {
+            doc: None,
+            name: Some(self.fresh("d")),
+            ctx,
+            ret_ty,
+            body,
+            span: Span::default(),
+            name_span: Span::default(),
+        }
+    }
+
+    // Returns a definition that does not type check, generated by breaking
+    // def, which has to be the last definition generated.
+    pub fn mutate(&mut self, def: &Def) -> Def {
+        let mut def = def.clone();
+        let choices = if self.hyps.is_empty() { 6 } else { 8 };
+        match self.rng.below(choices) {
+            0 => {
+                def.ret_ty = var("bool");
+                def.body = refl(var("true"));
+            },
+            1 => def.body = var(&self.fresh("unbound")),
+            2 => {
+                def.ret_ty = eq(var("true"), var("false"));
+                def.body = refl(var("true"));
+            },
+            3 => {
+                def.ret_ty = var("bool");
+                def.body = elim(
+                    var("true"), vec![ext("m", var("bool"))], var("bool"), vec![var("true")]);
+            },
+            4 => {
+                def.body = let_in("l", var("bool"), refl(var("true")), def.body);
+            },
+            5 => {
+                def.ret_ty = var("bool");
+                def.body = elim(
+                    var("true"),
+                    vec![ext("m", eq(var("true"), var("true")))],
+                    var("bool"),
+                    vec![var("true"), var("false")]);
+            },
+            6 => {
+                let (p, _, _) = self.rng.choose(&self.hyps).clone();
+                def.ret_ty = var("bool");
+                def.body = elim(
+                    var(&p), vec![ext("m", var("bool"))], var("bool"),
+                    vec![var("true"), var("false")]);
+            },
+            _ => {
+                let (p, a, _) = self.rng.choose(&self.hyps).clone();
+                def.ret_ty = eq(var(&a), var(&p));
+                def.body = refl(var(&a));
+            },
+        }
+        def
+    }
+}
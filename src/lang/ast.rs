@@ -1,31 +1,104 @@
 pub type Id = String;
 pub type DefId = Option<Id>;
 
+// Byte offsets of a piece of syntax in the source. Spans are not part of the
+// syntax itself: they are ignored when comparing syntax trees, so that trees
+// built by hand compare equal to parsed ones.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Span) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+impl Span {
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+}
+
+// Implicit binders are only allowed in the context of a def. Their
+// arguments are not passed explicitly but inferred by the type checker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Explicit,
+    Implicit,
+}
+
+// The span is the span of the name.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct CtxExt(pub DefId, pub Expr);
+pub struct CtxExt(pub DefId, pub Expr, pub Binding, pub Span);
 
 pub type Unit = Vec<Def>;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Def {
+    pub doc: Option<String>,
     pub name: DefId,
     pub ctx: Vec<CtxExt>,
     pub ret_ty: Expr,
     pub body: Expr,
+    pub span: Span,
+    pub name_span: Span,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub enum Expr {
     App(Id, Vec<Expr>),
+    // _ or ?name, a term to be inferred by the type checker or, if it
+    // cannot be inferred, reported with its expected type
+    Hole(Option<Id>),
+    // The type may be omitted if the type of val can be inferred.
     Let { name: DefId,
-          ty: Box<Expr>,
+          name_span: Span,
+          ty: Option<Box<Expr>>,
           val: Box<Expr>,
           body: Box<Expr> },
+    // Without an into clause, into_ctx is empty and into_ty is None.
     Elim { val: Box<Expr>,
            into_ctx : Vec<CtxExt>,
-           into_ty: Box<Expr>,
-           cases: Vec<ElimCase> }
+           into_ty: Option<Box<Expr>>,
+           cases: Vec<ElimCase> },
+    // The parser wraps every expression in its span.
+    Spanned(Span, Box<Expr>),
+}
+
+impl Expr {
+    pub fn unspanned(&self) -> &Expr {
+        match self {
+            Expr::Spanned(_, expr) => expr.unspanned(),
+            expr => expr,
+        }
+    }
 }
 
+// Expressions compare equal regardless of their spans.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Expr) -> bool {
+        match (self.unspanned(), other.unspanned()) {
+            (Expr::App(f, args), Expr::App(g, other_args)) => f == g && args == other_args,
+            (Expr::Hole(name), Expr::Hole(other_name)) => name == other_name,
+            (Expr::Let { name, ty, val, body, .. },
+             Expr::Let { name: other_name, ty: other_ty, val: other_val, body: other_body, .. }) =>
+                name == other_name && ty == other_ty && val == other_val && body == other_body,
+            (Expr::Elim { val, into_ctx, into_ty, cases },
+             Expr::Elim { val: other_val, into_ctx: other_into_ctx, into_ty: other_into_ty,
+                          cases: other_cases }) =>
+                val == other_val && into_ctx == other_into_ctx && into_ty == other_into_ty &&
+                cases == other_cases,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Expr {}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ElimCase(pub Vec<CtxExt>, pub Expr);
\ No newline at end of file
@@ -0,0 +1,84 @@
+use std::fmt;
+use lalrpop_util::ParseError;
+use super::ast::Span;
+use super::lexer::{LexicalError, Tok};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    // of the start of span, 1-based, columns count characters
+    pub line: usize,
+    pub column: usize,
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Diagnostic {
+    pub fn new(src: &str, span: Span, message: String) -> Self {
+        let before = &src[.. span.start.min(src.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Diagnostic {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start ..].chars().count() + 1,
+            span,
+            message,
+        }
+    }
+
+    pub fn at(src: &str, offset: usize, message: String) -> Self {
+        Diagnostic::new(src, Span { start: offset, end: offset }, message)
+    }
+
+    pub fn from_parse_error(src: &str, err: &ParseError<usize, Tok, LexicalError>) -> Self {
+        match err {
+            ParseError::InvalidToken { location } =>
+                Diagnostic::at(src, *location, "invalid token".to_owned()),
+            ParseError::UnrecognizedEOF { location, expected } =>
+                Diagnostic::at(
+                    src, *location,
+                    format!("unexpected end of input{}", expected_list(expected))),
+            ParseError::UnrecognizedToken { token: (start, tok, end), expected } =>
+                Diagnostic::new(
+                    src, Span { start: *start, end: *end },
+                    format!("unexpected `{}`{}", tok, expected_list(expected))),
+            ParseError::ExtraToken { token: (start, tok, end) } =>
+                Diagnostic::new(
+                    src, Span { start: *start, end: *end },
+                    format!("unexpected `{}` after the end of input", tok)),
+            ParseError::User { error } =>
+                Diagnostic::at(src, error.location, error.to_string()),
+        }
+    }
+}
+
+// Terminals are named as in the grammar, i.e. fixed tokens are quoted and the
+// others are named by their terminal. Both are replaced by something readable.
+fn describe_terminal(terminal: &str) -> String {
+    if terminal == "Ident" {
+        "identifier".to_owned()
+    } else if terminal == "Num" {
+        "number".to_owned()
+    } else if terminal == "DocComment" {
+        "doc comment".to_owned()
+    } else if terminal == "NamedHole" {
+        "hole".to_owned()
+    } else if terminal.len() >= 2 && terminal.starts_with('"') && terminal.ends_with('"') {
+        format!("`{}`", &terminal[1 .. terminal.len() - 1])
+    } else {
+        terminal.to_owned()
+    }
+}
+
+fn expected_list(expected: &[String]) -> String {
+    let described: Vec<String> = expected.iter().map(|t| describe_terminal(t)).collect();
+    match described.len() {
+        0 => String::new(),
+        1 => format!(", expected {}", described[0]),
+        _ => format!(", expected one of {}", described.join(", ")),
+    }
+}
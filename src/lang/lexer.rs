@@ -0,0 +1,241 @@
+// Lexer for qt files. Besides the tokens of the grammar it handles
+//
+//   -- line comments
+//   /- block comments -/, which nest
+//   /-- doc comments -/, which are tokens and precede a def
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tok<'input> {
+    Def,
+    Let,
+    In,
+    Elim,
+    Into,
+    End,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Colon,
+    ColonEq,
+    Eq,
+    Plus,
+    Dot,
+    Bar,
+    FatArrow,
+    Underscore,
+    Id(&'input str),
+    Nat(u32),
+    DocComment(&'input str),
+    // ?name, without the ?
+    Hole(&'input str),
+}
+
+const KEYWORDS: &[(&str, Tok<'static>)] = &[
+    ("def", Tok::Def),
+    ("let", Tok::Let),
+    ("in", Tok::In),
+    ("elim", Tok::Elim),
+    ("into", Tok::Into),
+    ("end", Tok::End),
+];
+
+// Longer symbols come first so that e.g. ":=" is not lexed as ":" "=".
+const SYMBOLS: &[(&str, Tok<'static>)] = &[
+    (":=", Tok::ColonEq),
+    ("=>", Tok::FatArrow),
+    ("(", Tok::LParen),
+    (")", Tok::RParen),
+    ("{", Tok::LBrace),
+    ("}", Tok::RBrace),
+    (":", Tok::Colon),
+    ("=", Tok::Eq),
+    ("+", Tok::Plus),
+    (".", Tok::Dot),
+    ("|", Tok::Bar),
+];
+
+impl<'input> fmt::Display for Tok<'input> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fixed = KEYWORDS.iter().chain(SYMBOLS).find(|(_, tok)| tok == self);
+        match (self, fixed) {
+            (_, Some((text, _))) => write!(f, "{}", text),
+            (Tok::Underscore, _) => write!(f, "_"),
+            (Tok::Id(name), _) => write!(f, "{}", name),
+            (Tok::Nat(n), _) => write!(f, "{}", n),
+            (Tok::DocComment(_), _) => write!(f, "/-- ... -/"),
+            (Tok::Hole(name), _) => write!(f, "?{}", name),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LexicalErrorKind {
+    UnexpectedChar(char),
+    UnterminatedComment,
+    NumeralOverflow,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LexicalError {
+    pub location: usize,
+    pub kind: LexicalErrorKind,
+}
+
+impl fmt::Display for LexicalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            LexicalErrorKind::UnexpectedChar(c) => write!(f, "unexpected character `{}`", c),
+            LexicalErrorKind::UnterminatedComment => write!(f, "unterminated comment"),
+            LexicalErrorKind::NumeralOverflow =>
+                write!(f, "numeral is larger than {}", u32::MAX),
+        }
+    }
+}
+
+pub type Spanned<'input> = Result<(usize, Tok<'input>, usize), LexicalError>;
+
+pub struct Lexer<'input> {
+    src: &'input str,
+    pos: usize,
+}
+
+// Whether text starts with a doc comment. /--/ is an empty ordinary comment,
+// its dashes are taken by the opening and the closing delimiter.
+fn is_doc_comment(text: &str) -> bool {
+    text.starts_with("/--") && !text.starts_with("/--/")
+}
+
+fn is_id_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_id_continue(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '\''
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(src: &'input str) -> Self {
+        Lexer { src, pos: 0 }
+    }
+
+    fn rest(&self) -> &'input str {
+        &self.src[self.pos ..]
+    }
+
+    fn error(&self, location: usize, kind: LexicalErrorKind) -> Spanned<'input> {
+        Err(LexicalError { location, kind })
+    }
+
+    // Skips a block comment whose opening "/-" is at pos. Returns the end of
+    // the comment, i.e. the position after the matching "-/".
+    fn block_comment_end(&self, start: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut pos = start;
+        while pos < self.src.len() {
+            let rest = &self.src[pos ..];
+            if rest.starts_with("/-") {
+                depth += 1;
+                pos += 2;
+            } else if rest.starts_with("-/") {
+                depth -= 1;
+                pos += 2;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            } else {
+                pos += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+        None
+    }
+
+    // Skips whitespace and comments other than doc comments.
+    fn skip_trivia(&mut self) -> Result<(), LexicalError> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("--") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/-") && !is_doc_comment(trimmed) {
+                match self.block_comment_end(self.pos) {
+                    Some(end) => self.pos = end,
+                    None => return Err(LexicalError {
+                        location: self.pos,
+                        kind: LexicalErrorKind::UnterminatedComment,
+                    }),
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Spanned<'input>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(err) = self.skip_trivia() {
+            // Stop after the error, the rest of the input is inside the
+            // comment.
+            self.pos = self.src.len();
+            return Some(Err(err));
+        }
+        let start = self.pos;
+        let rest = self.rest();
+        let c = rest.chars().next()?;
+
+        if is_doc_comment(rest) {
+            return Some(match self.block_comment_end(start) {
+                Some(end) => {
+                    self.pos = end;
+                    let text = self.src[start + 3 .. end - 2].trim();
+                    Ok((start, Tok::DocComment(text), end))
+                },
+                None => {
+                    self.pos = self.src.len();
+                    self.error(start, LexicalErrorKind::UnterminatedComment)
+                },
+            });
+        }
+
+        if let Some((text, tok)) = SYMBOLS.iter().find(|(text, _)| rest.starts_with(text)) {
+            self.pos += text.len();
+            return Some(Ok((start, *tok, self.pos)));
+        }
+
+        if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            self.pos += len;
+            return Some(match rest[.. len].parse() {
+                Ok(n) => Ok((start, Tok::Nat(n), self.pos)),
+                Err(_) => self.error(start, LexicalErrorKind::NumeralOverflow),
+            });
+        }
+
+        if c == '?' && rest[1 ..].starts_with(is_id_start) {
+            let len = 1 + rest[1 ..].find(|c| !is_id_continue(c)).unwrap_or_else(|| rest.len() - 1);
+            self.pos += len;
+            return Some(Ok((start, Tok::Hole(&rest[1 .. len]), self.pos)));
+        }
+
+        if is_id_start(c) {
+            let len = rest.find(|c| !is_id_continue(c)).unwrap_or(rest.len());
+            let word = &rest[.. len];
+            self.pos += len;
+            let tok = match KEYWORDS.iter().find(|(text, _)| *text == word) {
+                Some((_, tok)) => *tok,
+                None if word == "_" => Tok::Underscore,
+                None => Tok::Id(word),
+            };
+            return Some(Ok((start, tok, self.pos)));
+        }
+
+        self.pos += c.len_utf8();
+        Some(self.error(start, LexicalErrorKind::UnexpectedChar(c)))
+    }
+}
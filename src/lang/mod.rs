@@ -1,6 +1,60 @@
 pub mod ast;
+pub mod diagnostic;
+pub mod lexer;
 
 lalrpop_mod!(#[allow(unused_parens, dead_code)] pub parser, "/lang/qt.rs");
 
 #[cfg(test)]
-mod parser_tests;
\ No newline at end of file
+mod parser_tests;
+
+use self::ast::{Def, Expr, Unit};
+use self::diagnostic::Diagnostic;
+use self::lexer::Lexer;
+
+pub fn parse_def(src: &str) -> Result<Def, Diagnostic> {
+    parser::DefParser::new()
+        .parse(Lexer::new(src))
+        .map_err(|err| Diagnostic::from_parse_error(src, &err))
+}
+
+pub fn parse_expr(src: &str) -> Result<Expr, Diagnostic> {
+    parser::ExprParser::new()
+        .parse(Lexer::new(src))
+        .map_err(|err| Diagnostic::from_parse_error(src, &err))
+}
+
+pub fn parse_expr_pair(src: &str) -> Result<(Expr, Expr), Diagnostic> {
+    parser::ExprPairParser::new()
+        .parse(Lexer::new(src))
+        .map_err(|err| Diagnostic::from_parse_error(src, &err))
+}
+
+// Parses all defs in src. A def with a syntax error does not stop parsing of
+// the defs after it, so all their errors are reported together.
+pub fn parse_unit(src: &str) -> Result<Unit, Vec<Diagnostic>> {
+    let (unit, diagnostics) = parse_unit_partial(src);
+    if diagnostics.is_empty() {
+        Ok(unit)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+// Like parse_unit, but also returns the defs that could be parsed if there are
+// syntax errors.
+pub fn parse_unit_partial(src: &str) -> (Unit, Vec<Diagnostic>) {
+    let mut diagnostics = vec![];
+    let mut unit = vec![];
+    match parser::UnitItemsParser::new().parse(Lexer::new(src)) {
+        Ok(items) =>
+            for item in items {
+                match item {
+                    Ok(def) => unit.push(def),
+                    Err(recovery) =>
+                        diagnostics.push(Diagnostic::from_parse_error(src, &recovery.error)),
+                }
+            },
+        Err(err) => diagnostics.push(Diagnostic::from_parse_error(src, &err)),
+    }
+    (unit, diagnostics)
+}
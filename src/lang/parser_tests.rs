@@ -1,5 +1,6 @@
 use super::parser::*;
 use super::ast::*;
+use super::lexer::Lexer;
 
 macro_rules! assert_parse {
     ($parse:expr, $expected:expr) => {
@@ -25,7 +26,7 @@ fn name(name: &str) -> DefId {
 #[test]
 fn bool() {
     assert_parse!(
-        ExprParser::new().parse("bool"),
+        ExprParser::new().parse(Lexer::new("bool")),
         id("bool")
     );
 }
@@ -33,14 +34,14 @@ fn bool() {
 #[test]
 fn discard_() {
     assert_parse!(
-        DefIdParser::new().parse("_"),
+        DefIdParser::new().parse(Lexer::new("_")),
         discard());
 }
 
 #[test]
 fn x() {
     assert_parse!(
-        DefIdParser::new().parse("x"),
+        DefIdParser::new().parse(Lexer::new("x")),
         name("x")
     );
 }
@@ -48,8 +49,8 @@ fn x() {
 #[test]
 fn ctx_ext() {
     assert_parse!(
-        CtxExtsParser::new().parse("(x : bool)"),
-        vec![CtxExt(name("x"), id("bool"))]);
+        CtxExtsParser::new().parse(Lexer::new("(x : bool)")),
+        vec![CtxExt(name("x"), id("bool"), Binding::Explicit, Span::default())]);
 }
 
 #[test]
@@ -61,16 +62,19 @@ def negb (x : bool) : bool :=
   | => true
   end.";
   assert_parse!(
-      DefParser::new().parse(negb),
+      DefParser::new().parse(Lexer::new(negb)),
       Def {
+          doc: None,
+          span: Span::default(),
+          name_span: Span::default(),
           name: name("negb"),
-          ctx: vec![CtxExt(Some("x".to_string()), id("bool"))],
+          ctx: vec![CtxExt(Some("x".to_string()), id("bool"), Binding::Explicit, Span::default())],
           ret_ty: id("bool"),
           body:
             Expr::Elim {
                 val: Box::new(id("x")),
-                into_ctx: vec![CtxExt(discard(), id("bool"))],
-                into_ty: Box::new(id("bool")),
+                into_ctx: vec![CtxExt(discard(), id("bool"), Binding::Explicit, Span::default())],
+                into_ty: Some(Box::new(id("bool"))),
                 cases: vec![
                     ElimCase(vec![], id("false")),
                     ElimCase(vec![], id("true")),
@@ -83,7 +87,7 @@ def negb (x : bool) : bool :=
 fn eq_plus() {
     let eq_plus = "a + b = c + e + f";
     assert_parse!(
-        ExprParser::new().parse(eq_plus),
+        ExprParser::new().parse(Lexer::new(eq_plus)),
         Expr::App(
             "eq".to_string(),
             vec![
@@ -113,15 +117,17 @@ let x : bool := true in
 let y : bool := false in
 x = y";
     assert_parse!(
-        ExprParser::new().parse(multi_let),
+        ExprParser::new().parse(Lexer::new(multi_let)),
         Expr::Let {
             name: Some("x".to_string()),
-            ty: Box::new(id("bool")),
+            name_span: Span::default(),
+            ty: Some(Box::new(id("bool"))),
             val: Box::new(id("true")),
             body: Box::new(
                 Expr::Let {
                     name: Some("y".to_string()),
-                    ty: Box::new(id("bool")),
+                    name_span: Span::default(),
+                    ty: Some(Box::new(id("bool"))),
                     val: Box::new(id("false")),
                     body: Box::new(
                         Expr::App(
@@ -138,7 +144,7 @@ x = y";
 #[test]
 fn app() {
     assert_parse!(
-        ExprParser::new().parse("plus a b"),
+        ExprParser::new().parse(Lexer::new("plus a b")),
         Expr::App("plus".to_string(), vec![id("a"), id("b")])
     );
 }
@@ -146,7 +152,7 @@ fn app() {
 #[test]
 fn app2() {
     assert_parse!(
-        ExprParser::new().parse("plus a (plus b c)"),
+        ExprParser::new().parse(Lexer::new("plus a (plus b c)")),
         Expr::App(
             "plus".to_string(),
             vec![
@@ -163,7 +169,7 @@ fn app2() {
 #[test]
 fn zero() {
     assert_parse!(
-        ExprParser::new().parse("0"),
+        ExprParser::new().parse(Lexer::new("0")),
         id("O")
     );
 }
@@ -171,7 +177,7 @@ fn zero() {
 #[test]
 fn three() {
     assert_parse!(
-        ExprParser::new().parse("3"),
+        ExprParser::new().parse(Lexer::new("3")),
         Expr::App(
             "S".to_string(),
             vec![
@@ -187,4 +193,123 @@ fn three() {
             ]
         )
     );
-}
\ No newline at end of file
+}
+#[test]
+fn unexpected_token() {
+    let err = super::parse_def("def f : bool :=\n  true true).").unwrap_err();
+    assert_eq!(err.to_string(), "2:12: unexpected `)`, expected `.`");
+
+    let err = super::parse_def("def : bool := true.").unwrap_err();
+    assert_eq!(err.to_string(), "1:5: unexpected `:`, expected one of `_`, identifier");
+}
+
+#[test]
+fn unexpected_eof() {
+    let err = super::parse_def("def f : bool := true").unwrap_err();
+    assert_eq!((err.line, err.column), (1, 21));
+    assert!(err.message.starts_with("unexpected end of input"), "{}", err);
+}
+
+#[test]
+fn unexpected_char() {
+    let err = super::parse_def("def f : bool := tru$e.").unwrap_err();
+    assert_eq!(err.to_string(), "1:20: unexpected character `$`");
+}
+
+#[test]
+fn recovery() {
+    let errs = super::parse_unit(
+        "def a : bool := true.\n\
+         def b : := true.\n\
+         def c : bool := false.\n\
+         def d (x : ) : bool := x.\n").unwrap_err();
+    let locations: Vec<_> = errs.iter().map(|err| (err.line, err.column)).collect();
+    assert_eq!(locations, vec![(2, 9), (4, 12)]);
+
+    let unit = super::parse_unit("def a : bool := true. def b : bool := false.").unwrap();
+    assert_eq!(unit.len(), 2);
+}
+
+#[test]
+fn comments() {
+    let def = super::parse_def("
+-- a line comment
+def f /- a block /- nested -/ comment -/ : bool := -- another one
+  true.").unwrap();
+    assert_eq!(def.name, name("f"));
+    assert_eq!(def.doc, None);
+    assert_eq!(def.body, id("true"));
+
+    let err = super::parse_def("def f : bool := /- /- -/ true.").unwrap_err();
+    assert_eq!(err.to_string(), "1:17: unterminated comment");
+}
+
+#[test]
+fn doc_comments() {
+    let unit = super::parse_unit("
+/-- The identity. -/
+def id (b : bool) : bool := b.
+def neg (b : bool) : bool := b.").unwrap();
+    assert_eq!(unit[0].doc, Some("The identity.".to_string()));
+    assert_eq!(unit[1].doc, None);
+
+    let err = super::parse_def("def f : bool := /-- not here -/ true.").unwrap_err();
+    assert!(err.message.starts_with("unexpected `/-- ... -/`"), "{}", err);
+}
+
+#[test]
+fn empty_comments() {
+    let def = super::parse_def("/--/ def f : bool := true.").unwrap();
+    assert_eq!(def.doc, None);
+    let def = super::parse_def("/---/ def f : bool := true.").unwrap();
+    assert_eq!(def.doc, Some(String::new()));
+}
+
+#[test]
+fn keywords_are_reserved() {
+    let err = super::parse_def("def end : bool := true.").unwrap_err();
+    assert_eq!(err.to_string(), "1:5: unexpected `end`, expected one of `_`, identifier");
+    assert_parse!(ExprParser::new().parse(Lexer::new("ending")), id("ending"));
+}
+
+#[test]
+fn numeral_overflow() {
+    let err = super::parse_def("def f : nat := 4294967296.").unwrap_err();
+    assert_eq!(err.to_string(), "1:16: numeral is larger than 4294967295");
+}
+
+#[test]
+fn implicit_binders() {
+    let def = super::parse_def("def f {a b : bool} (p : a = b) : bool := g _ p.").unwrap();
+    assert_eq!(
+        def.ctx.iter().map(|ext| ext.2).collect::<Vec<_>>(),
+        vec![Binding::Implicit, Binding::Implicit, Binding::Explicit]);
+    assert_eq!(def.body, Expr::App("g".to_string(), vec![Expr::Hole(None), id("p")]));
+}
+
+#[test]
+fn optional_annotations() {
+    assert_parse!(
+        ExprParser::new().parse(Lexer::new("let x := a in elim x | => b | => c end")),
+        Expr::Let {
+            name: name("x"),
+            name_span: Span::default(),
+            ty: None,
+            val: Box::new(id("a")),
+            body: Box::new(Expr::Elim {
+                val: Box::new(id("x")),
+                into_ctx: vec![],
+                into_ty: None,
+                cases: vec![ElimCase(vec![], id("b")), ElimCase(vec![], id("c"))],
+            }),
+        });
+}
+
+#[test]
+fn holes() {
+    assert_parse!(
+        ExprParser::new().parse(Lexer::new("f ?goal _")),
+        Expr::App("f".to_string(), vec![Expr::Hole(Some("goal".to_string())), Expr::Hole(None)]));
+    let err = super::parse_def("def f : bool := ? x.").unwrap_err();
+    assert_eq!(err.to_string(), "1:17: unexpected character `?`");
+}
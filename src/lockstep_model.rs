@@ -0,0 +1,484 @@
+// A model that forwards everything to two other models and records where
+// their answers to equality queries differ. Used to test models against each
+// other, including two configurations of the same model.
+use std::fmt;
+use super::model::{Equality, GaveUp, Judgement, Model};
+use super::cwf::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disagreement {
+    pub query: &'static str,
+    pub lhs: String,
+    pub rhs: String,
+    pub left: Equality,
+    pub right: Equality,
+}
+
+impl fmt::Display for Disagreement {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}(\n  {},\n  {}\n) left: {:?}, right: {:?}",
+               self.query, self.lhs, self.rhs, self.left, self.right)
+    }
+}
+
+pub struct Lockstep<L: Model, R: Model> {
+    left: L,
+    right: R,
+    syntax: Syntax,
+    // handles in the left and right model, indexed by our handles
+    ctxs: Vec<(CtxId, CtxId)>,
+    morphs: Vec<(MorphId, MorphId)>,
+    tys: Vec<(TyId, TyId)>,
+    tms: Vec<(TmId, TmId)>,
+    disagreements: Vec<Disagreement>,
+    checkpoints: Vec<SyntaxMark>,
+}
+
+impl<L: Model, R: Model> Lockstep<L, R> {
+    pub fn new(left: L, right: R) -> Self {
+        Lockstep {
+            left,
+            right,
+            syntax: Syntax::new(),
+            ctxs: vec![],
+            morphs: vec![],
+            tys: vec![],
+            tms: vec![],
+            disagreements: vec![],
+            checkpoints: vec![],
+        }
+    }
+
+    pub fn disagreements(&self) -> &[Disagreement] {
+        &self.disagreements
+    }
+
+    // Both models hash-cons, so a node that is not fresh maps to the same
+    // handles as when it was first constructed.
+    fn add_ctx(&mut self, node: Ctx, handles: (CtxId, CtxId)) -> CtxId {
+        let (id, fresh) = self.syntax.intern_ctx(node);
+        if fresh {
+            self.ctxs.push(handles);
+        }
+        id
+    }
+    fn add_morph(&mut self, node: Morph, handles: (MorphId, MorphId)) -> MorphId {
+        let (id, fresh) = self.syntax.intern_morph(node);
+        if fresh {
+            self.morphs.push(handles);
+        }
+        id
+    }
+    fn add_ty(&mut self, node: Ty, handles: (TyId, TyId)) -> TyId {
+        let (id, fresh) = self.syntax.intern_ty(node);
+        if fresh {
+            self.tys.push(handles);
+        }
+        id
+    }
+    fn add_tm(&mut self, node: Tm, handles: (TmId, TmId)) -> TmId {
+        let (id, fresh) = self.syntax.intern_tm(node);
+        if fresh {
+            self.tms.push(handles);
+        }
+        id
+    }
+
+    // The judgement about the handles of the left model.
+    fn left_judgement(&self, judgement: Judgement) -> Judgement {
+        match judgement {
+            Judgement::Prop(ty) => Judgement::Prop(self.tys[ty.index()].0),
+            Judgement::Contractible(ty) => Judgement::Contractible(self.tys[ty.index()].0),
+            Judgement::Decidable(ty) => Judgement::Decidable(self.tys[ty.index()].0),
+        }
+    }
+
+    // Every difference is recorded, including an Unknown of a model that is
+    // less complete than the other. A model that gave up does not disagree
+    // with the other one.
+    fn compare<I: Copy>(
+        &mut self, query: &'static str, l: I, r: I,
+        left: Result<Equality, GaveUp>, right: Result<Equality, GaveUp>) -> Result<Equality, GaveUp>
+        where for<'a> Show<'a, I>: fmt::Debug
+    {
+        if let (&Ok(left_eq), &Ok(right_eq)) = (&left, &right) {
+            if left_eq != right_eq {
+                self.disagreements.push(Disagreement {
+                    query,
+                    lhs: format!("{:?}", self.syntax.show(l)),
+                    rhs: format!("{:?}", self.syntax.show(r)),
+                    left: left_eq,
+                    right: right_eq,
+                });
+            }
+        }
+        left
+    }
+}
+
+impl<L: Model, R: Model> Model for Lockstep<L, R> {
+    fn syntax(&self) -> &Syntax {
+        &self.syntax
+    }
+
+    fn ctx_eq(&mut self, l: CtxId, r: CtxId) -> Result<Equality, GaveUp> {
+        let ((ll, lr), (rl, rr)) = (self.ctxs[l.index()], self.ctxs[r.index()]);
+        let (left, right) = (self.left.ctx_eq(ll, rl), self.right.ctx_eq(lr, rr));
+        self.compare("ctx_eq", l, r, left, right)
+    }
+    fn morph_eq(&mut self, l: MorphId, r: MorphId) -> Result<Equality, GaveUp> {
+        let ((ll, lr), (rl, rr)) = (self.morphs[l.index()], self.morphs[r.index()]);
+        let (left, right) = (self.left.morph_eq(ll, rl), self.right.morph_eq(lr, rr));
+        self.compare("morph_eq", l, r, left, right)
+    }
+    fn ty_eq(&mut self, l: TyId, r: TyId) -> Result<Equality, GaveUp> {
+        let ((ll, lr), (rl, rr)) = (self.tys[l.index()], self.tys[r.index()]);
+        let (left, right) = (self.left.ty_eq(ll, rl), self.right.ty_eq(lr, rr));
+        self.compare("ty_eq", l, r, left, right)
+    }
+    fn tm_eq(&mut self, l: TmId, r: TmId) -> Result<Equality, GaveUp> {
+        let ((ll, lr), (rl, rr)) = (self.tms[l.index()], self.tms[r.index()]);
+        let (left, right) = (self.left.tm_eq(ll, rl), self.right.tm_eq(lr, rr));
+        self.compare("tm_eq", l, r, left, right)
+    }
+
+    // Judgements and inconsistency depend on which terms a model has seen,
+    // so they are not compared.
+    fn holds(&mut self, judgement: Judgement) -> Result<bool, GaveUp> {
+        let judgement = self.left_judgement(judgement);
+        self.left.holds(judgement)
+    }
+    fn is_inconsistent(&mut self, ctx: CtxId) -> Result<bool, GaveUp> {
+        let (l, _) = self.ctxs[ctx.index()];
+        self.left.is_inconsistent(l)
+    }
+
+    fn empty_ctx(&mut self) -> CtxId {
+        let handles = (self.left.empty_ctx(), self.right.empty_ctx());
+        self.add_ctx(Ctx::Empty, handles)
+    }
+    fn comprehension(&mut self, ty: TyId) -> CtxId {
+        let (l, r) = self.tys[ty.index()];
+        let handles = (self.left.comprehension(l), self.right.comprehension(r));
+        self.add_ctx(Ctx::Comprehension(ty), handles)
+    }
+    fn weakening(&mut self, ty: TyId) -> MorphId {
+        let (l, r) = self.tys[ty.index()];
+        let handles = (self.left.weakening(l), self.right.weakening(r));
+        self.add_morph(Morph::Weakening(ty), handles)
+    }
+    fn var(&mut self, ty: TyId) -> TmId {
+        let (l, r) = self.tys[ty.index()];
+        let handles = (self.left.var(l), self.right.var(r));
+        self.add_tm(Tm::Var(ty), handles)
+    }
+
+    fn id_morph(&mut self, ctx: CtxId) -> MorphId {
+        let (l, r) = self.ctxs[ctx.index()];
+        let handles = (self.left.id_morph(l), self.right.id_morph(r));
+        self.add_morph(Morph::Identity(ctx), handles)
+    }
+    fn compose(&mut self, g: MorphId, f: MorphId) -> MorphId {
+        let ((gl, gr), (fl, fr)) = (self.morphs[g.index()], self.morphs[f.index()]);
+        let handles = (self.left.compose(gl, fl), self.right.compose(gr, fr));
+        self.add_morph(Morph::Composition(g, f), handles)
+    }
+    fn extension(&mut self, morph: MorphId, ty: TyId, tm: TmId) -> MorphId {
+        let (ml, mr) = self.morphs[morph.index()];
+        let (yl, yr) = self.tys[ty.index()];
+        let (tl, tr) = self.tms[tm.index()];
+        let handles = (self.left.extension(ml, yl, tl), self.right.extension(mr, yr, tr));
+        self.add_morph(Morph::Extension(morph, ty, tm), handles)
+    }
+
+    fn subst_ty(&mut self, f: MorphId, ty: TyId) -> TyId {
+        let ((fl, fr), (yl, yr)) = (self.morphs[f.index()], self.tys[ty.index()]);
+        let handles = (self.left.subst_ty(fl, yl), self.right.subst_ty(fr, yr));
+        self.add_ty(Ty::Subst(f, ty), handles)
+    }
+    fn subst_tm(&mut self, f: MorphId, tm: TmId) -> TmId {
+        let ((fl, fr), (tl, tr)) = (self.morphs[f.index()], self.tms[tm.index()]);
+        let handles = (self.left.subst_tm(fl, tl), self.right.subst_tm(fr, tr));
+        self.add_tm(Tm::Subst(f, tm), handles)
+    }
+
+    fn eq_ty(&mut self, l: TmId, r: TmId) -> TyId {
+        let ((ll, lr), (rl, rr)) = (self.tms[l.index()], self.tms[r.index()]);
+        let handles = (self.left.eq_ty(ll, rl), self.right.eq_ty(lr, rr));
+        self.add_ty(Ty::Eq(l, r), handles)
+    }
+    fn refl(&mut self, tm: TmId) -> TmId {
+        let (l, r) = self.tms[tm.index()];
+        let handles = (self.left.refl(l), self.right.refl(r));
+        self.add_tm(Tm::Refl(tm), handles)
+    }
+
+    fn bool_ty(&mut self, ctx: CtxId) -> TyId {
+        let (l, r) = self.ctxs[ctx.index()];
+        let handles = (self.left.bool_ty(l), self.right.bool_ty(r));
+        self.add_ty(Ty::Bool(ctx), handles)
+    }
+    fn true_tm(&mut self, ctx: CtxId) -> TmId {
+        let (l, r) = self.ctxs[ctx.index()];
+        let handles = (self.left.true_tm(l), self.right.true_tm(r));
+        self.add_tm(Tm::True(ctx), handles)
+    }
+    fn false_tm(&mut self, ctx: CtxId) -> TmId {
+        let (l, r) = self.ctxs[ctx.index()];
+        let handles = (self.left.false_tm(l), self.right.false_tm(r));
+        self.add_tm(Tm::False(ctx), handles)
+    }
+    fn elim_bool(&mut self, base_ctx: CtxId, into: TyId, true_case: TmId, false_case: TmId) -> TmId {
+        let (cl, cr) = self.ctxs[base_ctx.index()];
+        let (il, ir) = self.tys[into.index()];
+        let ((tl, tr), (fl, fr)) = (self.tms[true_case.index()], self.tms[false_case.index()]);
+        let handles = (self.left.elim_bool(cl, il, tl, fl), self.right.elim_bool(cr, ir, tr, fr));
+        self.add_tm(Tm::ElimBool(base_ctx, into, true_case, false_case), handles)
+    }
+
+    // Disagreements found before a rollback are kept.
+    fn checkpoint(&mut self) {
+        self.left.checkpoint();
+        self.right.checkpoint();
+        self.checkpoints.push(self.syntax.mark());
+    }
+    fn rollback(&mut self) {
+        self.left.rollback();
+        self.right.rollback();
+        let mark = self.checkpoints.pop().expect("rollback without checkpoint");
+        self.syntax.truncate(mark);
+        self.ctxs.truncate(mark.ctxs);
+        self.morphs.truncate(mark.morphs);
+        self.tys.truncate(mark.tys);
+        self.tms.truncate(mark.tms);
+    }
+    fn commit(&mut self) {
+        self.left.commit();
+        self.right.commit();
+        self.checkpoints.pop().expect("commit without checkpoint");
+    }
+
+    // Both models keep exactly the nodes of ours that survive.
+    fn collect_garbage(&mut self, roots: &Roots) -> Remap {
+        assert!(self.checkpoints.is_empty(), "garbage collection during a checkpoint");
+        let remap = self.syntax.collect(roots);
+        compact(&mut self.ctxs, &remap.ctxs);
+        compact(&mut self.morphs, &remap.morphs);
+        compact(&mut self.tys, &remap.tys);
+        compact(&mut self.tms, &remap.tms);
+
+        let left_roots = Roots {
+            ctxs: self.ctxs.iter().map(|ids| ids.0).collect(),
+            morphs: self.morphs.iter().map(|ids| ids.0).collect(),
+            tys: self.tys.iter().map(|ids| ids.0).collect(),
+            tms: self.tms.iter().map(|ids| ids.0).collect(),
+        };
+        let right_roots = Roots {
+            ctxs: self.ctxs.iter().map(|ids| ids.1).collect(),
+            morphs: self.morphs.iter().map(|ids| ids.1).collect(),
+            tys: self.tys.iter().map(|ids| ids.1).collect(),
+            tms: self.tms.iter().map(|ids| ids.1).collect(),
+        };
+        let left = self.left.collect_garbage(&left_roots);
+        let right = self.right.collect_garbage(&right_roots);
+        for ids in &mut self.ctxs {
+            *ids = (left.ctx(ids.0), right.ctx(ids.1));
+        }
+        for ids in &mut self.morphs {
+            *ids = (left.morph(ids.0), right.morph(ids.1));
+        }
+        for ids in &mut self.tys {
+            *ids = (left.ty(ids.0), right.ty(ids.1));
+        }
+        for ids in &mut self.tms {
+            *ids = (left.tm(ids.0), right.tm(ids.1));
+        }
+        remap
+    }
+}
+
+#[cfg(all(test, feature = "phl"))]
+mod tests {
+    use std::fs;
+    use crate::cwf::*;
+    use crate::cwf_model::Cwf;
+    use crate::lang::ast::Def;
+    use crate::lang;
+    use crate::model::{Equality, Model};
+    use crate::nbe_model::NbeModel;
+    use crate::random::Rng;
+    use crate::type_checker::TypeChecker;
+    use super::{Disagreement, Lockstep};
+
+    fn examples() -> Vec<(String, Vec<Def>)> {
+        let mut paths = vec!["src/example.qt".to_owned()];
+        for entry in fs::read_dir("examples").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "qt") {
+                paths.push(path.to_str().unwrap().to_owned());
+            }
+        }
+
+        paths.into_iter().map(|path| {
+            let code = fs::read_to_string(&path).unwrap();
+            let unit = lang::parse_unit(&code).unwrap();
+            (path, unit)
+        }).collect()
+    }
+
+    // Defs may refer to the defs before them in the same file, so each file
+    // is checked by a single type checker.
+    fn check_examples<L: Model, R: Model, F: Fn() -> Lockstep<L, R>>(new_model: F) {
+        for (path, unit) in examples() {
+            let mut tc = TypeChecker::new(new_model());
+            for def in unit {
+                let name = format!("{}: {}", path, def.name.as_ref().map_or("_", |n| n.as_str()));
+                let result = tc.check_def(&def);
+                let disagreements = tc.model().disagreements();
+                for d in disagreements {
+                    println!("{}\n{}", name, d);
+                }
+                assert!(disagreements.is_empty(), "models disagree on {}", name);
+                if let Err(err) = result {
+                    panic!("{} does not check:\n{}", name, err);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn examples_lazy_eager() {
+        check_examples(|| Lockstep::new(Cwf::new(), Cwf::eager()));
+    }
+
+    #[test]
+    fn examples_sequential_threaded() {
+        check_examples(|| Lockstep::new(Cwf::new(), Cwf::threaded(4)));
+    }
+
+    #[test]
+    fn examples_cwf_nbe() {
+        check_examples(|| Lockstep::new(Cwf::new(), NbeModel::new()));
+    }
+
+    // Builds random well-typed bool terms through the model API. ctxs[i + 1]
+    // is ctxs[i] extended by entries[i], which is either bool or an equality
+    // between bools.
+    struct TermGen<'a, M: Model> {
+        model: &'a mut M,
+        rng: Rng,
+        ctxs: Vec<CtxId>,
+        entries: Vec<TyId>,
+    }
+
+    impl<'a, M: Model> TermGen<'a, M> {
+        fn new(model: &'a mut M, seed: u64, depth: usize) -> Self {
+            let empty = model.empty_ctx();
+            let mut gen = TermGen { model, rng: Rng::new(seed), ctxs: vec![empty], entries: vec![] };
+            for i in 0..depth {
+                let ctx = gen.ctxs[i];
+                let entry = if i == 0 || gen.rng.chance(2, 3) {
+                    gen.model.bool_ty(ctx)
+                } else {
+                    let l = gen.bool_tm(i, 2);
+                    let r = gen.bool_tm(i, 2);
+                    gen.model.eq_ty(l, r)
+                };
+                let ext = gen.model.comprehension(entry);
+                gen.entries.push(entry);
+                gen.ctxs.push(ext);
+            }
+            gen
+        }
+
+        fn is_bool_entry(&mut self, i: usize) -> bool {
+            let bool_ty = self.model.bool_ty(self.ctxs[i]);
+            self.entries.get(i) == Some(&bool_ty)
+        }
+
+        // A term of type bool in ctxs[i].
+        fn bool_tm(&mut self, i: usize, depth: usize) -> TmId {
+            let ctx = self.ctxs[i];
+            let mut choices = vec![0, 1];
+            if i > 0 && depth > 0 {
+                choices.push(2);
+            }
+            if i > 0 && self.is_bool_entry(i - 1) {
+                choices.push(3);
+                if depth > 0 {
+                    choices.push(4);
+                }
+            }
+            if depth > 0 && i + 1 < self.ctxs.len() && self.is_bool_entry(i) {
+                choices.push(5);
+            }
+
+            match *self.rng.choose(&choices) {
+                0 => self.model.true_tm(ctx),
+                1 => self.model.false_tm(ctx),
+                2 => {
+                    let tm = self.bool_tm(i - 1, depth - 1);
+                    let wkn = self.model.weakening(self.entries[i - 1]);
+                    self.model.subst_tm(wkn, tm)
+                },
+                3 => self.model.var(self.entries[i - 1]),
+                4 => {
+                    let into = self.model.bool_ty(ctx);
+                    let true_case = self.bool_tm(i - 1, depth - 1);
+                    let false_case = self.bool_tm(i - 1, depth - 1);
+                    self.model.elim_bool(self.ctxs[i - 1], into, true_case, false_case)
+                },
+                _ => {
+                    let val = self.bool_tm(i, depth - 1);
+                    let tm = self.bool_tm(i + 1, depth - 1);
+                    let id = self.model.id_morph(ctx);
+                    let bar = self.model.extension(id, self.entries[i], val);
+                    self.model.subst_tm(bar, tm)
+                },
+            }
+        }
+    }
+
+    // Fails if the models disagree on random terms in a way that is_bug
+    // considers wrong.
+    fn check_random_terms<L, R, F, B>(new_model: F, is_bug: B)
+        where L: Model, R: Model, F: Fn() -> Lockstep<L, R>, B: Fn(&Disagreement) -> bool
+    {
+        for seed in 0..40 {
+            let mut model = new_model();
+            let mut gen = TermGen::new(&mut model, seed, 3);
+            let tms: Vec<TmId> = (0..6).map(|_| gen.bool_tm(3, 3)).collect();
+            for &l in &tms {
+                for &r in &tms {
+                    model.tm_eq(l, r).unwrap();
+                }
+            }
+            let bugs: Vec<_> = model.disagreements().iter().filter(|d| is_bug(d)).collect();
+            for d in &bugs {
+                println!("seed {}\n{}", seed, d);
+            }
+            assert!(bugs.is_empty(), "models disagree for seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn random_terms_lazy_eager() {
+        check_random_terms(|| Lockstep::new(Cwf::new(), Cwf::eager()), |_| true);
+    }
+
+    #[test]
+    fn random_terms_sequential_threaded() {
+        check_random_terms(|| Lockstep::new(Cwf::new(), Cwf::threaded(4)), |_| true);
+    }
+
+    #[test]
+    fn random_terms_cwf_nbe() {
+        // The closure only relates terms that have been defined, so it can
+        // miss equalities of the standard model when the subterms needed to
+        // derive them are never constructed, and it proves few terms
+        // distinct. What it does decide must agree with the standard model.
+        check_random_terms(
+            || Lockstep::new(Cwf::new(), NbeModel::new()),
+            |d| d.left != Equality::Unknown);
+    }
+}
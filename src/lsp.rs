@@ -0,0 +1,497 @@
+// A language server for qt files, speaking LSP over stdio. Documents are
+// parsed and checked from scratch whenever they change, with the types of all
+// checked expressions recorded for hovers.
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, SymbolKind, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use super::lang;
+use super::lang::ast::*;
+use super::lang::diagnostic::Diagnostic;
+use super::model::Model;
+use super::type_checker::TypeChecker;
+
+// Editors wait for the diagnostics after every change, so sessions give up on
+// fixpoints after this long unless a timeout is configured.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// What is known about a document after checking it.
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    // printed types of the checked expressions
+    types: Vec<(Span, String)>,
+    // uses of names, with the span of the name that binds them
+    references: Vec<(Span, Span)>,
+    // named defs, with their span and the span of their name
+    symbols: Vec<(String, Span, Span)>,
+}
+
+impl Analysis {
+    pub fn new<M: Model>(src: &str, model: M) -> Self {
+        let (unit, mut diagnostics) = lang::parse_unit_partial(src);
+        let mut tc = TypeChecker::new(model);
+        tc.record_types();
+        for def in unit.iter() {
+            if let Err(err) = tc.check_def(def) {
+                let span = tc.error_span().unwrap_or(def.name_span);
+                diagnostics.push(Diagnostic::new(src, span, err));
+            }
+        }
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+
+        let mut resolver = Resolver { src, globals: HashMap::new(), scope: vec![], references: vec![] };
+        for def in unit.iter() {
+            resolver.def(def);
+        }
+        let symbols = unit.iter().filter_map(|def| {
+            def.name.as_ref().map(|name| (name.clone(), def.span, def.name_span))
+        }).collect();
+
+        Analysis {
+            diagnostics,
+            types: tc.annotations().to_vec(),
+            references: resolver.references,
+            symbols,
+        }
+    }
+
+    // The type of the innermost checked expression at offset.
+    pub fn hover(&self, offset: usize) -> Option<(Span, &str)> {
+        self.types.iter()
+            .filter(|(span, _)| span.contains(offset))
+            .min_by_key(|(span, _)| span.end - span.start)
+            .map(|(span, ty)| (*span, &ty[..]))
+    }
+
+    // The span of the name that binds the name at offset.
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        self.references.iter()
+            .find(|(span, _)| span.contains(offset))
+            .map(|(_, binder)| *binder)
+    }
+
+    pub fn symbols(&self) -> &[(String, Span, Span)] {
+        &self.symbols
+    }
+}
+
+// Resolves names to their binders: parameters of the def, let and elim
+// binders, and the defs before the current one.
+struct Resolver<'a> {
+    src: &'a str,
+    globals: HashMap<String, Span>,
+    scope: Vec<(String, Span)>,
+    references: Vec<(Span, Span)>,
+}
+
+impl<'a> Resolver<'a> {
+    fn def(&mut self, def: &Def) {
+        self.binders(&def.ctx, |s| {
+            s.expr(&def.ret_ty, None);
+            s.expr(&def.body, None);
+        });
+        if let Some(ref name) = def.name {
+            self.globals.insert(name.clone(), def.name_span);
+        }
+    }
+
+    // Resolves the types of exts, each in the scope of the ones before it, and
+    // then calls f in the scope of all of them.
+    fn binders(&mut self, exts: &[CtxExt], f: impl FnOnce(&mut Self)) {
+        let depth = self.scope.len();
+        for ext in exts {
+            self.expr(&ext.1, None);
+            if let Some(ref name) = ext.0 {
+                self.scope.push((name.clone(), ext.3));
+            }
+        }
+        f(self);
+        self.scope.truncate(depth);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Span> {
+        self.scope.iter().rev()
+            .find(|(bound, _)| bound == name)
+            .map(|(_, span)| *span)
+            .or_else(|| self.globals.get(name).cloned())
+    }
+
+    // span is the span of expr, if it is known.
+    fn expr(&mut self, expr: &Expr, span: Option<Span>) {
+        match expr {
+            Expr::Spanned(span, expr) => self.expr(expr, Some(*span)),
+            Expr::App(name, args) => {
+                // The head of an application is at the start of its span,
+                // unless it was introduced by the parser as for = and +.
+                if let Some(span) = span {
+                    let rest = &self.src[span.start ..];
+                    let is_head = rest.starts_with(name.as_str()) &&
+                        !rest[name.len() ..].starts_with(|c: char| {
+                            c.is_ascii_alphanumeric() || c == '_' || c == '\''
+                        });
+                    if let (true, Some(binder)) = (is_head, self.lookup(name)) {
+                        let end = span.start + name.len();
+                        self.references.push((Span { start: span.start, end }, binder));
+                    }
+                }
+                for arg in args {
+                    self.expr(arg, None);
+                }
+            },
+            Expr::Hole(_) => (),
+            Expr::Let { name, name_span, ty, val, body } => {
+                if let Some(ty) = ty {
+                    self.expr(ty, None);
+                }
+                self.expr(val, None);
+                let depth = self.scope.len();
+                if let Some(name) = name {
+                    self.scope.push((name.clone(), *name_span));
+                }
+                self.expr(body, None);
+                self.scope.truncate(depth);
+            },
+            Expr::Elim { val, into_ctx, into_ty, cases } => {
+                self.expr(val, None);
+                self.binders(into_ctx, |s| {
+                    if let Some(into_ty) = into_ty {
+                        s.expr(into_ty, None);
+                    }
+                });
+                for ElimCase(ctx, body) in cases {
+                    self.binders(ctx, |s| s.expr(body, None));
+                }
+            },
+        }
+    }
+}
+
+// Positions count lines and UTF-16 code units, as LSP wants it by default.
+fn position(src: &str, offset: usize) -> Position {
+    let before = &src[.. offset.min(src.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start ..].encode_utf16().count() as u32,
+    }
+}
+
+fn offset(src: &str, position: Position) -> usize {
+    let line_start = if position.line == 0 {
+        0
+    } else {
+        src.match_indices('\n').nth(position.line as usize - 1).map_or(src.len(), |(i, _)| i + 1)
+    };
+    let mut units = 0;
+    for (i, c) in src[line_start ..].char_indices() {
+        if units >= position.character as usize || c == '\n' {
+            return line_start + i
+        }
+        units += c.len_utf16();
+    }
+    src.len()
+}
+
+fn range(src: &str, span: Span) -> Range {
+    Range { start: position(src, span.start), end: position(src, span.end) }
+}
+
+fn diagnostics(src: &str, analysis: &Analysis) -> Vec<lsp_types::Diagnostic> {
+    analysis.diagnostics.iter().map(|diagnostic| lsp_types::Diagnostic {
+        range: range(src, diagnostic.span),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("qt".to_owned()),
+        message: diagnostic.message.clone(),
+        ..Default::default()
+    }).collect()
+}
+
+struct Document {
+    src: String,
+    analysis: Analysis,
+}
+
+struct Server<'a, M> {
+    connection: &'a Connection,
+    documents: HashMap<Url, Document>,
+    // checks every version of a document with a fresh model
+    new_model: &'a dyn Fn() -> M,
+}
+
+impl<'a, M: Model> Server<'a, M> {
+    fn publish(&self, uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Result<(), Box<dyn Error>> {
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+        self.connection.sender.send(Message::Notification(notification))?;
+        Ok(())
+    }
+
+    fn update(&mut self, uri: Url, src: String) -> Result<(), Box<dyn Error>> {
+        let analysis = Analysis::new(&src, (self.new_model)());
+        let diagnostics = diagnostics(&src, &analysis);
+        self.documents.insert(uri.clone(), Document { src, analysis });
+        self.publish(uri, diagnostics)
+    }
+
+    fn notification(&mut self, notification: Notification) -> Result<(), Box<dyn Error>> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.update(params.text_document.uri, params.text_document.text)
+            },
+            // The server asks for full syncs, so the last change is the
+            // whole document.
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+                match params.content_changes.into_iter().last() {
+                    Some(change) => self.update(params.text_document.uri, change.text),
+                    None => Ok(()),
+                }
+            },
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                self.publish(params.text_document.uri, vec![])
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        match self.respond(request) {
+            Ok(result) => Response::new_ok(id, result),
+            Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    fn respond(&self, request: Request) -> Result<serde_json::Value, serde_json::Error> {
+        match request.method.as_str() {
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position_params;
+                serde_json::to_value(self.hover(&position.text_document.uri, position.position))
+            },
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position_params;
+                serde_json::to_value(self.definition(&position.text_document.uri, position.position))
+            },
+            DocumentSymbolRequest::METHOD => {
+                let params: DocumentSymbolParams = serde_json::from_value(request.params)?;
+                serde_json::to_value(self.symbols(&params.text_document.uri))
+            },
+            _ => Ok(serde_json::Value::Null),
+        }
+    }
+
+    fn hover(&self, uri: &Url, at: Position) -> Option<Hover> {
+        let document = self.documents.get(uri)?;
+        let (span, ty) = document.analysis.hover(offset(&document.src, at))?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::PlainText,
+                value: ty.to_owned(),
+            }),
+            range: Some(range(&document.src, span)),
+        })
+    }
+
+    fn definition(&self, uri: &Url, at: Position) -> Option<GotoDefinitionResponse> {
+        let document = self.documents.get(uri)?;
+        let span = document.analysis.definition(offset(&document.src, at))?;
+        Some(GotoDefinitionResponse::Scalar(Location {
+            uri: uri.clone(),
+            range: range(&document.src, span),
+        }))
+    }
+
+    fn symbols(&self, uri: &Url) -> Option<DocumentSymbolResponse> {
+        let document = self.documents.get(uri)?;
+        let symbols = document.analysis.symbols().iter().map(|(name, span, name_span)| {
+            #[allow(deprecated)]
+            DocumentSymbol {
+                name: name.clone(),
+                detail: None,
+                kind: SymbolKind::FUNCTION,
+                tags: None,
+                deprecated: None,
+                range: range(&document.src, *span),
+                selection_range: range(&document.src, *name_span),
+                children: None,
+            }
+        }).collect();
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+}
+
+// Serves connection until the client shuts it down.
+fn serve<M: Model>(connection: &Connection, new_model: &dyn Fn() -> M) -> Result<(), Box<dyn Error>> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server { connection, documents: HashMap::new(), new_model };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(())
+                }
+                let response = server.request(request);
+                connection.sender.send(Message::Response(response))?;
+            },
+            Message::Notification(notification) => server.notification(notification)?,
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+pub fn run<M: Model>(new_model: impl Fn() -> M) -> Result<(), Box<dyn Error>> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection, &new_model)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "phl"))]
+mod tests {
+    use std::thread;
+    use lsp_server::{Connection, Message, Notification, Request, RequestId};
+    use lsp_types::Position;
+    use crate::cwf_model::Cwf;
+    use crate::phl::Limits;
+    use super::{offset, position, serve, Analysis};
+
+    const SRC: &str = "\
+def id (b : bool) : bool := b.
+def twice (b : bool) : bool :=
+  let c : bool := id b in id c.
+def bad : bool := refl true.
+";
+
+    fn at(src: &str, needle: &str, nth: usize) -> usize {
+        src.match_indices(needle).nth(nth).unwrap().0
+    }
+
+    #[test]
+    fn hover() {
+        let analysis = Analysis::new(SRC, Cwf::new());
+        let (span, ty) = analysis.hover(at(SRC, "id b", 0)).unwrap();
+        assert_eq!((&SRC[span.start .. span.end], ty), ("id b", "bool"));
+        let (span, ty) = analysis.hover(at(SRC, "true", 0)).unwrap();
+        assert_eq!((&SRC[span.start .. span.end], ty), ("true", "bool"));
+        assert!(analysis.hover(0).is_none());
+    }
+
+    #[test]
+    fn definition() {
+        let analysis = Analysis::new(SRC, Cwf::new());
+        let binder = |offset| analysis.definition(offset).map(|span| span.start);
+        // the parameter of id
+        assert_eq!(binder(at(SRC, "b.", 0)), Some(at(SRC, "b :", 0)));
+        // a def before the current one
+        assert_eq!(binder(at(SRC, "id b", 0) + 1), Some(at(SRC, "id", 0)));
+        // let
+        assert_eq!(binder(at(SRC, "c.", 0)), Some(at(SRC, "c :", 0)));
+        assert_eq!(binder(at(SRC, "bool", 0)), None);
+    }
+
+    #[test]
+    fn symbols_and_diagnostics() {
+        let analysis = Analysis::new(SRC, Cwf::new());
+        let names: Vec<&str> = analysis.symbols().iter().map(|(name, _, _)| &name[..]).collect();
+        assert_eq!(names, vec!["id", "twice", "bad"]);
+        assert_eq!(analysis.diagnostics.len(), 1);
+        assert_eq!(analysis.diagnostics[0].line, 4);
+        assert!(analysis.diagnostics[0].message.starts_with("expected:"));
+
+        let analysis = Analysis::new("def x : bool := (.\ndef y : bool := refl true.", Cwf::new());
+        let lines: Vec<usize> = analysis.diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![1, 2]);
+    }
+
+    #[test]
+    fn limits() {
+        let src = "def trans (a b c : bool) (p : a = b) (q : b = c) : a = c := refl a.";
+        let mut model = Cwf::new();
+        model.set_limits(Limits { max_rounds: Some(1), ..Limits::default() });
+        let analysis = Analysis::new(src, model);
+        assert_eq!(analysis.diagnostics.len(), 1);
+        assert_eq!(analysis.diagnostics[0].message, "the model gave up: no fixpoint after 1 rounds");
+    }
+
+    #[test]
+    fn positions() {
+        let src = "ab\n\u{1d54f}c\n";
+        for &offset_ in &[0, 2, 3, 7, 8, 9] {
+            assert_eq!(offset(src, position(src, offset_)), offset_);
+        }
+        assert_eq!(position(src, 7), Position { line: 1, character: 2 });
+    }
+
+    #[test]
+    fn session() {
+        let (client, server) = Connection::memory();
+        let handle = thread::spawn(move || serve(&server, &Cwf::new).map_err(|err| err.to_string()));
+        let request = |id: i32, method: &str, params: serde_json::Value| {
+            let request = Request::new(RequestId::from(id), method.to_owned(), params);
+            client.sender.send(Message::Request(request)).unwrap();
+            match client.receiver.recv().unwrap() {
+                Message::Response(response) => response.result,
+                message => panic!("unexpected {:?}", message),
+            }
+        };
+        let notify = |method: &str, params: serde_json::Value| {
+            let notification = Notification::new(method.to_owned(), params);
+            client.sender.send(Message::Notification(notification)).unwrap();
+        };
+
+        request(1, "initialize", serde_json::json!({ "capabilities": {} }));
+        notify("initialized", serde_json::json!({}));
+        let uri = "file:///test.qt";
+        notify("textDocument/didOpen", serde_json::json!({
+            "textDocument": { "uri": uri, "languageId": "qt", "version": 1, "text": SRC },
+        }));
+        match client.receiver.recv().unwrap() {
+            Message::Notification(notification) => {
+                assert_eq!(notification.method, "textDocument/publishDiagnostics");
+                assert_eq!(notification.params["diagnostics"][0]["range"]["start"]["line"], 3);
+            },
+            message => panic!("unexpected {:?}", message),
+        }
+        let hover = request(2, "textDocument/hover", serde_json::json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 0, "character": 28 },
+        })).unwrap();
+        assert_eq!(hover["contents"]["value"], "bool");
+        let symbols = request(3, "textDocument/documentSymbol", serde_json::json!({
+            "textDocument": { "uri": uri },
+        })).unwrap();
+        assert_eq!(symbols[1]["name"], "twice");
+
+        request(4, "shutdown", serde_json::Value::Null);
+        notify("exit", serde_json::Value::Null);
+        handle.join().unwrap().unwrap();
+    }
+}
@@ -1,28 +1,197 @@
+// Without phl, the code that compares phl with the reference model is unused.
+#![cfg_attr(not(feature = "phl"), allow(dead_code))]
+
 #[macro_use]
 extern crate lalrpop_util;
+#[cfg(feature = "phl")]
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "phl")]
 extern crate libc;
 extern crate scopeguard;
 
 mod lang;
 mod cwf;
 mod model;
+#[cfg(feature = "phl")]
+mod phl;
+#[cfg(feature = "phl")]
 mod cwf_model;
+mod nbe_model;
+mod lockstep_model;
+mod tracing_model;
+mod pretty;
+mod repl;
+mod lsp;
+#[cfg(feature = "phl")]
+mod bench;
+#[cfg(test)]
+mod random;
+#[cfg(test)]
+mod def_gen;
 mod type_checker;
 
+#[cfg(feature = "phl")]
 use cwf_model::Cwf;
+use lang::ast::Unit;
+#[cfg(feature = "phl")]
+use lockstep_model::Lockstep;
+use model::Model;
+use nbe_model::NbeModel;
+#[cfg(feature = "phl")]
+use phl::{ClosureStats, Limits};
+use std::env;
 use std::fs;
+use std::process;
+#[cfg(feature = "phl")]
+use std::time::Duration;
+use tracing_model::TracingModel;
 use type_checker::TypeChecker;
 
+fn check<T: Model>(model: T, unit: &Unit) -> TypeChecker<T> {
+    let mut tc = TypeChecker::new(model);
+    for def in unit {
+        match tc.check_def(def) {
+            Ok(_) => println!("Ok"),
+            Err(s) => println!("Err:\n{}", s)
+        };
+    }
+    tc
+}
+
+// Checks unit while logging every model call, followed by a summary.
+fn trace<T: Model>(model: T, unit: &Unit) -> TypeChecker<TracingModel<T>> {
+    let tc = check(TracingModel::logging(model), unit);
+    for (name, (count, time)) in tc.model().summary() {
+        eprintln!("{:>14} {:>6} calls {:>12?}", name, count, time);
+    }
+    tc
+}
+
+// Prints the work done by the phl fixpoints, the most expensive sequents
+// first.
+#[cfg(feature = "phl")]
+fn print_stats(stats: &ClosureStats) {
+    eprintln!("{} closures, {} rounds", stats.closures, stats.rounds);
+    eprintln!("{:>12} {:>10} {:>10} {:>8} {:>8}  sequent",
+              "time", "visited", "joined", "merges", "facts");
+    let mut sequents: Vec<_> = stats.sequents.iter().collect();
+    sequents.sort_by_key(|seq| std::cmp::Reverse(seq.time));
+    for seq in sequents {
+        eprintln!("{:>12?} {:>10} {:>10} {:>8} {:>8}  {}",
+                  seq.time, seq.visited_rows, seq.joined_rows, seq.merges, seq.facts, seq.name);
+    }
+}
+
+// The number following flag, if flag is given.
+#[cfg(feature = "phl")]
+fn numeric_flag(args: &[String], flag: &str) -> Option<u64> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    match args.get(i + 1).and_then(|n| n.parse().ok()) {
+        Some(n) => Some(n),
+        None => {
+            eprintln!("{} expects a number", flag);
+            process::exit(1);
+        }
+    }
+}
+
+// Checks unit with the phl models made by new_cwf, or with them and the
+// reference model for --compare and --replay.
+#[cfg(feature = "phl")]
+fn check_phl(new_cwf: impl Fn() -> Cwf, unit: &Unit, tracing: bool) {
+    let stats = env::args().any(|arg| arg == "--stats");
+    if env::args().any(|arg| arg == "--compare") {
+        let tc = check(Lockstep::new(new_cwf(), NbeModel::new()), unit);
+        for disagreement in tc.model().disagreements() {
+            println!("phl and nbe disagree on {}", disagreement);
+        }
+    } else if env::args().any(|arg| arg == "--replay") {
+        let tc = check(TracingModel::new(new_cwf()), unit);
+        for divergence in tracing_model::replay(tc.model().trace(), &mut NbeModel::new()) {
+            println!("nbe diverges from phl at {}", divergence);
+        }
+    } else if tracing {
+        let tc = trace(new_cwf(), unit);
+        if stats {
+            print_stats(&tc.model().inner().closure_stats());
+        }
+    } else {
+        let tc = check(new_cwf(), unit);
+        if stats {
+            print_stats(&tc.model().closure_stats());
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // --threads n computes phl fixpoints on n threads, 0 meaning one per core.
+    // --max-rounds n, --max-carrier n and --timeout ms limit every fixpoint
+    // computation, and queries that need more are reported as errors. The
+    // language server times out after lsp::DEFAULT_TIMEOUT without --timeout.
+    let args: Vec<String> = env::args().collect();
+    let subcommand = args.get(1).map(String::as_str);
+    #[cfg(feature = "phl")]
+    let thread_count = numeric_flag(&args, "--threads").unwrap_or(1) as usize;
+    #[cfg(feature = "phl")]
+    let new_model = {
+        let default_timeout = if subcommand == Some("lsp") { Some(lsp::DEFAULT_TIMEOUT) } else { None };
+        let limits = Limits {
+            max_rounds: numeric_flag(&args, "--max-rounds").map(|n| n as usize),
+            max_carrier_size: numeric_flag(&args, "--max-carrier").map(|n| n as usize),
+            timeout: numeric_flag(&args, "--timeout").map(Duration::from_millis).or(default_timeout),
+        };
+        move || {
+            let mut cwf = Cwf::threaded(thread_count);
+            cwf.set_limits(limits);
+            cwf
+        }
+    };
+    // Without phl, everything is checked with the reference model.
+    #[cfg(not(feature = "phl"))]
+    let new_model = NbeModel::new;
+
+    match subcommand {
+        #[cfg(feature = "phl")]
+        Some("bench") => {
+            bench::run(thread_count);
+            return Ok(())
+        },
+        Some("repl") => {
+            repl::run(new_model())?;
+            return Ok(())
+        },
+        Some("lsp") => return lsp::run(new_model),
+        _ => {},
+    }
+
     let program = fs::read_to_string("src/example.qt")?;
-    let p = lang::parser::DefParser::new().parse(program.as_str()).unwrap();
-    let model = Cwf::new();
-    let mut tc = TypeChecker::new(model);
-    match tc.check_def(&p) {
-        Ok(_) => println!("Ok"),
-        Err(s) => println!("Err:\n{}", s)
+    let unit = match lang::parse_unit(&program) {
+        Ok(unit) => unit,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("src/example.qt:{}", diagnostic);
+            }
+            process::exit(1);
+        }
     };
+    // --nbe checks with the reference model instead of phl, --compare checks
+    // with both and reports where they disagree. --replay records the calls
+    // made to phl and replays them against the reference model afterwards.
+    // --trace logs the calls made to the model. --stats prints what the phl
+    // fixpoints spent their time on.
+    let tracing = env::args().any(|arg| arg == "--trace");
+    #[cfg(feature = "phl")]
+    {
+        if !env::args().any(|arg| arg == "--nbe") {
+            check_phl(new_model, &unit, tracing);
+            return Ok(())
+        }
+    }
+    if tracing {
+        trace(NbeModel::new(), &unit);
+    } else {
+        check(NbeModel::new(), &unit);
+    }
     Ok(())
 }
@@ -1,28 +1,94 @@
+use std::fmt;
 use super::cwf::*;
 
+// Properties of types that the model tracks in addition to equality.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Judgement {
+    // All terms of the type are equal.
+    Prop(TyId),
+    // The type is a proposition with a term.
+    Contractible(TyId),
+    // The type is a proposition or has decidable equality.
+    Decidable(TyId),
+}
+
+// The answer to an equality query.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Equality {
+    Equal,
+    // Provably not equal.
+    Distinct,
+    // Neither follows from what the model knows, for example because phl
+    // derives equalities only between terms it has seen.
+    Unknown,
+}
+
+// A query the model gave up on, e.g. because phl exceeded the limits of its
+// closure. It might be answered with more time or memory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GaveUp(pub String);
+
+impl fmt::Display for GaveUp {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "the model gave up: {}", self.0)
+    }
+}
+
+// The type checker reports errors as strings.
+impl From<GaveUp> for String {
+    fn from(gave_up: GaveUp) -> String {
+        gave_up.to_string()
+    }
+}
+
 pub trait Model {
-    fn ctx_eq(&mut self, l: &Ctx, r: &Ctx) -> bool;
-    fn morph_eq(&mut self, l: &Morph, r: &Morph) -> bool;
-    fn ty_eq(&mut self, l: &Ty, r: &Ty) -> bool;
-    fn tm_eq(&mut self, l: &Tm, r: &Tm) -> bool;
-
-    fn empty_ctx(&mut self) -> Ctx;
-    fn comprehension(&mut self, ty: &Ty) -> Ctx;
-    fn weakening(&mut self, ty: &Ty) -> Morph;
-    fn var(&mut self, ty: &Ty) -> Tm;
-
-    fn id_morph(&mut self, ctx: &Ctx) -> Morph;
-    fn compose(&mut self, g: &Morph, f: &Morph) -> Morph;
-    fn extension(&mut self, morph: &Morph, ty: &Ty, tm: &Tm) -> Morph;
-
-    fn subst_ty(&mut self, f: &Morph, ty: &Ty) -> Ty;
-    fn subst_tm(&mut self, f: &Morph, tm: &Tm) -> Tm;
-
-    fn eq_ty(&mut self, l: &Tm, r: &Tm) -> Ty;
-    fn refl(&mut self, tm: &Tm) -> Tm;
-
-    fn bool_ty(&mut self, ctx: &Ctx) -> Ty;
-    fn true_tm(&mut self, ctx: &Ctx) -> Tm;
-    fn false_tm(&mut self, ctx: &Ctx) -> Tm;
-    fn elim_bool(&mut self, base_ctx : &Ctx, into: &Ty, true_case: &Tm, false_case: &Tm) -> Tm;
-}
\ No newline at end of file
+    // The arena that all handles returned by this model refer to.
+    fn syntax(&self) -> &Syntax;
+
+    // Queries fail with GaveUp if the model runs out of resources.
+    fn ctx_eq(&mut self, l: CtxId, r: CtxId) -> Result<Equality, GaveUp>;
+    fn morph_eq(&mut self, l: MorphId, r: MorphId) -> Result<Equality, GaveUp>;
+    fn ty_eq(&mut self, l: TyId, r: TyId) -> Result<Equality, GaveUp>;
+    fn tm_eq(&mut self, l: TmId, r: TmId) -> Result<Equality, GaveUp>;
+
+    // Whether judgement follows from the axioms.
+    fn holds(&mut self, judgement: Judgement) -> Result<bool, GaveUp>;
+    // Whether ctx is known to have no closed substitution into it, e.g.
+    // because it assumes true = false. All terms of a type are equal in such
+    // a context.
+    fn is_inconsistent(&mut self, ctx: CtxId) -> Result<bool, GaveUp>;
+
+    fn empty_ctx(&mut self) -> CtxId;
+    fn comprehension(&mut self, ty: TyId) -> CtxId;
+    fn weakening(&mut self, ty: TyId) -> MorphId;
+    fn var(&mut self, ty: TyId) -> TmId;
+
+    fn id_morph(&mut self, ctx: CtxId) -> MorphId;
+    fn compose(&mut self, g: MorphId, f: MorphId) -> MorphId;
+    fn extension(&mut self, morph: MorphId, ty: TyId, tm: TmId) -> MorphId;
+
+    fn subst_ty(&mut self, f: MorphId, ty: TyId) -> TyId;
+    fn subst_tm(&mut self, f: MorphId, tm: TmId) -> TmId;
+
+    fn eq_ty(&mut self, l: TmId, r: TmId) -> TyId;
+    fn refl(&mut self, tm: TmId) -> TmId;
+
+    fn bool_ty(&mut self, ctx: CtxId) -> TyId;
+    fn true_tm(&mut self, ctx: CtxId) -> TmId;
+    fn false_tm(&mut self, ctx: CtxId) -> TmId;
+    fn elim_bool(&mut self, base_ctx : CtxId, into: TyId, true_case: TmId, false_case: TmId) -> TmId;
+
+    // Checkpoints nest. rollback undoes everything since the most recent
+    // checkpoint: syntax constructed since then is forgotten, its handles
+    // become invalid, and so do the equalities and assumptions it gave rise
+    // to. commit keeps all of it. Both end the checkpoint.
+    fn checkpoint(&mut self);
+    fn rollback(&mut self);
+    fn commit(&mut self);
+
+    // Forgets the syntax that is not reachable from roots, together with
+    // whatever the model knows only about it, and returns the new handles of
+    // the remaining syntax. Handles that are not in the returned remap are
+    // invalid afterwards. Must not be called while a checkpoint is active.
+    fn collect_garbage(&mut self, roots: &Roots) -> Remap;
+}
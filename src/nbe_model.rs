@@ -0,0 +1,480 @@
+// A model that decides equality by normalisation by evaluation, without
+// relying on phl.
+//
+// Every type of the theory is built from bool and equality types, so a
+// context denotes a finite set of environments: one boolean for every bool in
+// the telescope, and the unique proof for every equality type whose sides
+// evaluate to the same value. Syntax is evaluated in all environments of its
+// context and read back as a decision tree over the bools of the telescope.
+// Environments that make an equality in the telescope false are absurd. The
+// trees are reduced, so two terms are equal exactly when their trees are.
+use super::model::{Equality, GaveUp, Judgement, Model};
+use super::cwf::*;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Val {
+    True,
+    False,
+    Refl,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum TyVal {
+    Bool,
+    Eq(Val, Val),
+}
+
+impl TyVal {
+    fn is_inhabited(self) -> bool {
+        match self {
+            TyVal::Bool => true,
+            TyVal::Eq(a, b) => a == b,
+        }
+    }
+}
+
+// The part of a type that does not depend on the environment.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Skeleton {
+    Bool,
+    Eq(Box<Skeleton>),
+}
+
+type Env = Vec<Val>;
+
+// Normal forms: decision trees that split on the bools of a telescope in
+// order, with Split(when_true, when_false).
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Nf<V> {
+    Leaf(V),
+    Split(Box<Nf<V>>, Box<Nf<V>>),
+    Absurd,
+}
+
+impl<V> Nf<V> {
+    fn all<F: Fn(&V) -> bool + Copy>(&self, f: F) -> bool {
+        match self {
+            Nf::Leaf(v) => f(v),
+            Nf::Split(t, e) => t.all(f) && e.all(f),
+            Nf::Absurd => true,
+        }
+    }
+}
+
+pub struct NbeModel {
+    syntax: Syntax,
+    checkpoints: Vec<SyntaxMark>,
+}
+
+impl NbeModel {
+    pub fn new() -> Self {
+        NbeModel { syntax: Syntax::new(), checkpoints: vec![] }
+    }
+
+    fn eval_tm(&self, tm: TmId, env: &[Val]) -> Val {
+        match self.syntax.tm(tm) {
+            Tm::Subst(f, tm) => self.eval_tm(tm, &self.eval_morph(f, env)),
+            Tm::Var(_) => *env.last().expect("variable in empty context"),
+            Tm::Refl(_) => Val::Refl,
+            Tm::True(_) => Val::True,
+            Tm::False(_) => Val::False,
+            Tm::ElimBool(_, _, true_case, false_case) => {
+                let (&last, base) = env.split_last().expect("bool elimination in empty context");
+                match last {
+                    Val::True => self.eval_tm(true_case, base),
+                    Val::False => self.eval_tm(false_case, base),
+                    Val::Refl => panic!("eliminating a proof as a bool"),
+                }
+            },
+        }
+    }
+
+    // Evaluates the type of tm without constructing it.
+    fn eval_tm_ty(&self, tm: TmId, env: &[Val]) -> TyVal {
+        match self.syntax.tm(tm) {
+            Tm::Subst(f, tm) => self.eval_tm_ty(tm, &self.eval_morph(f, env)),
+            Tm::Var(ty) => self.eval_ty(ty, &env[..env.len() - 1]),
+            Tm::Refl(a) => {
+                let a = self.eval_tm(a, env);
+                TyVal::Eq(a, a)
+            },
+            Tm::True(_) | Tm::False(_) => TyVal::Bool,
+            Tm::ElimBool(_, into, _, _) => self.eval_ty(into, env),
+        }
+    }
+
+    fn eval_ty(&self, ty: TyId, env: &[Val]) -> TyVal {
+        match self.syntax.ty(ty) {
+            Ty::Subst(f, ty) => self.eval_ty(ty, &self.eval_morph(f, env)),
+            Ty::Bool(_) => TyVal::Bool,
+            Ty::Eq(a, b) => TyVal::Eq(self.eval_tm(a, env), self.eval_tm(b, env)),
+        }
+    }
+
+    // Morphisms f : G -> D map environments of D to environments of G.
+    fn eval_morph(&self, morph: MorphId, env: &[Val]) -> Env {
+        match self.syntax.morph(morph) {
+            Morph::Identity(_) => env.to_vec(),
+            Morph::Weakening(_) => env[..env.len() - 1].to_vec(),
+            Morph::Composition(g, f) => self.eval_morph(f, &self.eval_morph(g, env)),
+            Morph::Extension(f, _, tm) => {
+                let mut ext = self.eval_morph(f, env);
+                ext.push(self.eval_tm(tm, env));
+                ext
+            },
+        }
+    }
+
+    fn telescope(&self, ctx: CtxId) -> Vec<TyId> {
+        match self.syntax.ctx(ctx) {
+            Ctx::Empty => vec![],
+            Ctx::Comprehension(ty) => {
+                let ty_ctx = self.ty_ctx(ty);
+                let mut tele = self.telescope(ty_ctx);
+                tele.push(ty);
+                tele
+            },
+        }
+    }
+
+    // Evaluates f in every environment of ctx.
+    fn read_back<V: PartialEq, F: Fn(&[Val]) -> V>(&self, ctx: CtxId, f: F) -> Nf<V> {
+        let tele = self.telescope(ctx);
+        self.read_back_from(&tele, &mut vec![], &f)
+    }
+
+    fn read_back_from<V: PartialEq, F: Fn(&[Val]) -> V>(
+        &self, tele: &[TyId], env: &mut Env, f: &F) -> Nf<V>
+    {
+        if env.len() == tele.len() {
+            return Nf::Leaf(f(env))
+        }
+
+        let ty = self.eval_ty(tele[env.len()], env);
+        let mut extend = |val| {
+            env.push(val);
+            let nf = self.read_back_from(tele, env, f);
+            env.pop();
+            nf
+        };
+        match ty {
+            TyVal::Bool => {
+                let when_true = extend(Val::True);
+                let when_false = extend(Val::False);
+                if when_true == when_false {
+                    when_true
+                } else {
+                    Nf::Split(Box::new(when_true), Box::new(when_false))
+                }
+            },
+            TyVal::Eq(a, b) if a == b => extend(Val::Refl),
+            TyVal::Eq(_, _) => Nf::Absurd,
+        }
+    }
+
+    fn ty_ctx(&self, ty: TyId) -> CtxId {
+        match self.syntax.ty(ty) {
+            Ty::Subst(f, _) => self.cod(f),
+            Ty::Bool(ctx) => ctx,
+            Ty::Eq(a, _) => self.tm_ctx(a),
+        }
+    }
+
+    fn tm_ctx(&self, tm: TmId) -> CtxId {
+        match self.syntax.tm(tm) {
+            Tm::Subst(f, _) => self.cod(f),
+            Tm::Var(ty) => self.comprehension_of(ty),
+            Tm::Refl(a) => self.tm_ctx(a),
+            Tm::True(ctx) | Tm::False(ctx) => ctx,
+            Tm::ElimBool(_, into, _, _) => self.ty_ctx(into),
+        }
+    }
+
+    fn dom(&self, morph: MorphId) -> CtxId {
+        match self.syntax.morph(morph) {
+            Morph::Identity(ctx) => ctx,
+            Morph::Weakening(ty) => self.ty_ctx(ty),
+            Morph::Composition(_, f) => self.dom(f),
+            Morph::Extension(_, ty, _) => self.comprehension_of(ty),
+        }
+    }
+
+    fn cod(&self, morph: MorphId) -> CtxId {
+        match self.syntax.morph(morph) {
+            Morph::Identity(ctx) => ctx,
+            Morph::Weakening(ty) => self.comprehension_of(ty),
+            Morph::Composition(g, _) => self.cod(g),
+            Morph::Extension(f, _, _) => self.cod(f),
+        }
+    }
+
+    // The constructors intern G.A along with any syntax that refers to it,
+    // so the comprehension can always be found.
+    fn comprehension_of(&self, ty: TyId) -> CtxId {
+        self.syntax.find_ctx(Ctx::Comprehension(ty)).expect("comprehension was not constructed")
+    }
+
+    fn ty_skeleton(&self, ty: TyId) -> Skeleton {
+        match self.syntax.ty(ty) {
+            Ty::Subst(_, ty) => self.ty_skeleton(ty),
+            Ty::Bool(_) => Skeleton::Bool,
+            Ty::Eq(a, _) => Skeleton::Eq(Box::new(self.tm_skeleton(a))),
+        }
+    }
+
+    // The skeleton of the type of tm.
+    fn tm_skeleton(&self, tm: TmId) -> Skeleton {
+        match self.syntax.tm(tm) {
+            Tm::Subst(_, tm) => self.tm_skeleton(tm),
+            Tm::Var(ty) => self.ty_skeleton(ty),
+            Tm::Refl(a) => Skeleton::Eq(Box::new(self.tm_skeleton(a))),
+            Tm::True(_) | Tm::False(_) => Skeleton::Bool,
+            Tm::ElimBool(_, into, _, _) => self.ty_skeleton(into),
+        }
+    }
+
+    fn ctx_eq_impl(&self, l: CtxId, r: CtxId) -> bool {
+        match (self.syntax.ctx(l), self.syntax.ctx(r)) {
+            (Ctx::Empty, Ctx::Empty) => true,
+            (Ctx::Comprehension(l), Ctx::Comprehension(r)) => self.ty_eq_impl(l, r),
+            _ => false,
+        }
+    }
+
+    fn ty_eq_impl(&self, l: TyId, r: TyId) -> bool {
+        let ctx = self.ty_ctx(l);
+        self.ctx_eq_impl(ctx, self.ty_ctx(r)) &&
+            self.ty_skeleton(l) == self.ty_skeleton(r) &&
+            self.read_back(ctx, |env| self.eval_ty(l, env)) ==
+                self.read_back(ctx, |env| self.eval_ty(r, env))
+    }
+
+    fn tm_eq_impl(&self, l: TmId, r: TmId) -> bool {
+        let ctx = self.tm_ctx(l);
+        let nf = |tm| self.read_back(ctx, |env| (self.eval_tm_ty(tm, env), self.eval_tm(tm, env)));
+        self.ctx_eq_impl(ctx, self.tm_ctx(r)) &&
+            self.tm_skeleton(l) == self.tm_skeleton(r) &&
+            nf(l) == nf(r)
+    }
+
+    fn morph_eq_impl(&self, l: MorphId, r: MorphId) -> bool {
+        let cod = self.cod(l);
+        self.ctx_eq_impl(self.dom(l), self.dom(r)) &&
+            self.ctx_eq_impl(cod, self.cod(r)) &&
+            self.read_back(cod, |env| self.eval_morph(l, env)) ==
+                self.read_back(cod, |env| self.eval_morph(r, env))
+    }
+
+    fn holds_impl(&self, judgement: Judgement) -> bool {
+        match judgement {
+            Judgement::Prop(ty) => {
+                let ctx = self.ty_ctx(ty);
+                self.read_back(ctx, |env| self.eval_ty(ty, env))
+                    .all(|ty| ty != &TyVal::Bool)
+            },
+            Judgement::Contractible(ty) => {
+                let ctx = self.ty_ctx(ty);
+                self.read_back(ctx, |env| self.eval_ty(ty, env))
+                    .all(|&ty| ty != TyVal::Bool && ty.is_inhabited())
+            },
+            // Bools and all equality types have decidable equality.
+            Judgement::Decidable(_) => true,
+        }
+    }
+}
+
+fn decided(equal: bool) -> Equality {
+    if equal { Equality::Equal } else { Equality::Distinct }
+}
+
+impl Model for NbeModel {
+    fn syntax(&self) -> &Syntax {
+        &self.syntax
+    }
+
+    // Normalisation always terminates and normal forms are unique, so every
+    // query is decided.
+    fn ctx_eq(&mut self, l: CtxId, r: CtxId) -> Result<Equality, GaveUp> {
+        Ok(decided(self.ctx_eq_impl(l, r)))
+    }
+    fn morph_eq(&mut self, l: MorphId, r: MorphId) -> Result<Equality, GaveUp> {
+        Ok(decided(self.morph_eq_impl(l, r)))
+    }
+    fn ty_eq(&mut self, l: TyId, r: TyId) -> Result<Equality, GaveUp> {
+        Ok(decided(self.ty_eq_impl(l, r)))
+    }
+    fn tm_eq(&mut self, l: TmId, r: TmId) -> Result<Equality, GaveUp> {
+        Ok(decided(self.tm_eq_impl(l, r)))
+    }
+
+    fn holds(&mut self, judgement: Judgement) -> Result<bool, GaveUp> {
+        Ok(self.holds_impl(judgement))
+    }
+    // Exactly the contexts without environments.
+    fn is_inconsistent(&mut self, ctx: CtxId) -> Result<bool, GaveUp> {
+        Ok(self.read_back(ctx, |_| ()) == Nf::Absurd)
+    }
+
+    fn empty_ctx(&mut self) -> CtxId {
+        self.syntax.intern_ctx(Ctx::Empty).0
+    }
+    fn comprehension(&mut self, ty: TyId) -> CtxId {
+        self.syntax.intern_ctx(Ctx::Comprehension(ty)).0
+    }
+    fn weakening(&mut self, ty: TyId) -> MorphId {
+        self.comprehension(ty);
+        self.syntax.intern_morph(Morph::Weakening(ty)).0
+    }
+    fn var(&mut self, ty: TyId) -> TmId {
+        self.comprehension(ty);
+        self.syntax.intern_tm(Tm::Var(ty)).0
+    }
+
+    fn id_morph(&mut self, ctx: CtxId) -> MorphId {
+        self.syntax.intern_morph(Morph::Identity(ctx)).0
+    }
+    fn compose(&mut self, g: MorphId, f: MorphId) -> MorphId {
+        self.syntax.intern_morph(Morph::Composition(g, f)).0
+    }
+    fn extension(&mut self, morph: MorphId, ty: TyId, tm: TmId) -> MorphId {
+        self.comprehension(ty);
+        self.syntax.intern_morph(Morph::Extension(morph, ty, tm)).0
+    }
+
+    fn subst_ty(&mut self, f: MorphId, ty: TyId) -> TyId {
+        self.syntax.intern_ty(Ty::Subst(f, ty)).0
+    }
+    fn subst_tm(&mut self, f: MorphId, tm: TmId) -> TmId {
+        self.syntax.intern_tm(Tm::Subst(f, tm)).0
+    }
+
+    fn eq_ty(&mut self, l: TmId, r: TmId) -> TyId {
+        self.syntax.intern_ty(Ty::Eq(l, r)).0
+    }
+    fn refl(&mut self, tm: TmId) -> TmId {
+        self.syntax.intern_tm(Tm::Refl(tm)).0
+    }
+
+    fn bool_ty(&mut self, ctx: CtxId) -> TyId {
+        self.syntax.intern_ty(Ty::Bool(ctx)).0
+    }
+    fn true_tm(&mut self, ctx: CtxId) -> TmId {
+        self.syntax.intern_tm(Tm::True(ctx)).0
+    }
+    fn false_tm(&mut self, ctx: CtxId) -> TmId {
+        self.syntax.intern_tm(Tm::False(ctx)).0
+    }
+    fn elim_bool(&mut self, base_ctx: CtxId, into: TyId, true_case: TmId, false_case: TmId) -> TmId {
+        let bool_ty = self.bool_ty(base_ctx);
+        self.comprehension(bool_ty);
+        self.syntax.intern_tm(Tm::ElimBool(base_ctx, into, true_case, false_case)).0
+    }
+
+    // Equality is decided from the syntax alone, so there is nothing else
+    // to roll back.
+    fn checkpoint(&mut self) {
+        self.checkpoints.push(self.syntax.mark());
+    }
+    fn rollback(&mut self) {
+        let mark = self.checkpoints.pop().expect("rollback without checkpoint");
+        self.syntax.truncate(mark);
+    }
+    fn commit(&mut self) {
+        self.checkpoints.pop().expect("commit without checkpoint");
+    }
+
+    fn collect_garbage(&mut self, roots: &Roots) -> Remap {
+        assert!(self.checkpoints.is_empty(), "garbage collection during a checkpoint");
+        self.syntax.collect(roots)
+    }
+}
+
+#[test]
+fn eq_subst() {
+    // f(a == a) = fa == fa
+    let mut nbe = NbeModel::new();
+    let empty = nbe.empty_ctx();
+    let empty_bool = nbe.bool_ty(empty);
+    let bool_ctx = nbe.comprehension(empty_bool);
+    let a = nbe.var(empty_bool);
+    let aeq = nbe.eq_ty(a, a);
+    let bool_ctx_bool = nbe.bool_ty(bool_ctx);
+    let f = nbe.weakening(bool_ctx_bool);
+    let faa = nbe.subst_ty(f, aeq);
+    let fa = nbe.subst_tm(f, a);
+    let fafa = nbe.eq_ty(fa, fa);
+    assert_eq!(nbe.ty_eq(fafa, faa), Ok(Equality::Equal));
+    assert_eq!(nbe.ty_eq(aeq, faa), Ok(Equality::Distinct));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn subst_var() {
+    // <1(G), Bool(G), True(G)>(Var(Bool(G))) = True(G)
+    let mut nbe = NbeModel::new();
+    let empty_ctx = nbe.empty_ctx();
+    let empty_ctx_bool = nbe.bool_ty(empty_ctx);
+    let G = nbe.comprehension(empty_ctx_bool);
+    let G_B = nbe.bool_ty(G);
+    let G_id = nbe.id_morph(G);
+    let G_T = nbe.true_tm(G);
+    let G_F = nbe.false_tm(G);
+    let G_T_bar = nbe.extension(G_id, G_B, G_T);
+    let D_var = nbe.var(G_B);
+    let subst_var = nbe.subst_tm(G_T_bar, D_var);
+    assert_eq!(nbe.tm_eq(subst_var, G_T), Ok(Equality::Equal));
+    assert_eq!(nbe.tm_eq(subst_var, G_F), Ok(Equality::Distinct));
+}
+
+#[test]
+fn bool_eta() {
+    // x : bool |- elim x into bool | true | false = x
+    let mut nbe = NbeModel::new();
+    let empty = nbe.empty_ctx();
+    let bool_ty = nbe.bool_ty(empty);
+    let ctx = nbe.comprehension(bool_ty);
+    let ctx_bool = nbe.bool_ty(ctx);
+    let true_tm = nbe.true_tm(empty);
+    let false_tm = nbe.false_tm(empty);
+    let x = nbe.var(bool_ty);
+    let elim = nbe.elim_bool(empty, ctx_bool, true_tm, false_tm);
+    let negb = nbe.elim_bool(empty, ctx_bool, false_tm, true_tm);
+    assert_eq!(nbe.tm_eq(elim, x), Ok(Equality::Equal));
+    assert_eq!(nbe.tm_eq(negb, x), Ok(Equality::Distinct));
+
+    // x[<wkn(bool), bool, x>] = x and <wkn(bool), bool, x> = 1(x : bool)
+    let wkn = nbe.weakening(bool_ty);
+    let ext = nbe.extension(wkn, bool_ty, x);
+    let subst_x = nbe.subst_tm(ext, x);
+    assert_eq!(nbe.tm_eq(subst_x, x), Ok(Equality::Equal));
+    let id = nbe.id_morph(ctx);
+    assert_eq!(nbe.morph_eq(ext, id), Ok(Equality::Equal));
+    let ctx_true = nbe.true_tm(ctx);
+    let bar_true = nbe.extension(wkn, bool_ty, ctx_true);
+    let subst_true = nbe.subst_tm(bar_true, x);
+    assert_eq!(nbe.tm_eq(subst_true, x), Ok(Equality::Distinct));
+    assert_eq!(nbe.tm_eq(subst_true, ctx_true), Ok(Equality::Equal));
+    assert_eq!(nbe.morph_eq(bar_true, id), Ok(Equality::Distinct));
+}
+
+#[test]
+fn reflection() {
+    // x : bool, p : x = true |- x = true
+    let mut nbe = NbeModel::new();
+    let empty = nbe.empty_ctx();
+    let bool_ty = nbe.bool_ty(empty);
+    let x_ctx = nbe.comprehension(bool_ty);
+    let x = nbe.var(bool_ty);
+    let true_x = nbe.true_tm(x_ctx);
+    let x_is_true = nbe.eq_ty(x, true_x);
+    let wkn = nbe.weakening(x_is_true);
+    let wkn_x = nbe.subst_tm(wkn, x);
+    let wkn_true = nbe.subst_tm(wkn, true_x);
+    assert_eq!(nbe.tm_eq(x, true_x), Ok(Equality::Distinct));
+    assert_eq!(nbe.tm_eq(wkn_x, wkn_true), Ok(Equality::Equal));
+    let wkn_x_is_true = nbe.subst_ty(wkn, x_is_true);
+    assert_eq!(nbe.holds(Judgement::Contractible(x_is_true)), Ok(false));
+    assert_eq!(nbe.holds(Judgement::Contractible(wkn_x_is_true)), Ok(true));
+    assert_eq!(nbe.holds(Judgement::Prop(x_is_true)), Ok(true));
+    assert_eq!(nbe.holds(Judgement::Prop(bool_ty)), Ok(false));
+}
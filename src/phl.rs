@@ -0,0 +1,812 @@
+#![allow(dead_code)]
+
+// Safe bindings to the phl engine. Everything unsafe about talking to the C++
+// side is contained in this module.
+
+use libc::{c_int, size_t};
+use std::error;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::ptr::NonNull;
+use std::slice;
+use std::str;
+use std::time::Duration;
+
+mod ffi {
+    use libc::{size_t, c_char, c_int};
+
+    // Opaque C++ types; only ever handled through pointers.
+    #[repr(C)] pub struct PartialStructure { _private: [u8; 0] }
+    #[repr(C)] pub struct Sort { _private: [u8; 0] }
+    #[repr(C)] pub struct Operation { _private: [u8; 0] }
+    #[repr(C)] pub struct Predicate { _private: [u8; 0] }
+
+    pub const PHL_OK: c_int = 0;
+    pub const PHL_INVALID_ELEMENT: c_int = 1;
+    pub const PHL_SORT_MISMATCH: c_int = 2;
+    pub const PHL_INVALID_ARGUMENT: c_int = 3;
+    pub const PHL_MALFORMED_SEQUENT: c_int = 4;
+    pub const PHL_INTERNAL_ERROR: c_int = 5;
+    pub const PHL_NO_CHECKPOINT: c_int = 6;
+    pub const PHL_LIMIT_EXCEEDED: c_int = 7;
+
+    #[repr(C)]
+    pub struct ClosureLimits {
+        pub max_rounds: size_t,
+        pub max_carrier_size: size_t,
+        pub timeout_nanoseconds: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct ClosureStats {
+        pub closures: size_t,
+        pub rounds: size_t,
+        pub sequent_count: size_t,
+    }
+
+    #[repr(C)]
+    pub struct Conflict {
+        pub name: *const c_char,
+        pub name_len: size_t,
+        pub witness: size_t,
+        pub witness_sort: *const Sort,
+    }
+
+    #[repr(C)]
+    pub struct SequentStats {
+        pub name: *const c_char,
+        pub name_len: size_t,
+        pub visited_rows: size_t,
+        pub joined_rows: size_t,
+        pub merges: size_t,
+        pub facts: size_t,
+        pub nanoseconds: u64,
+    }
+
+    extern "C" {
+        pub fn get_last_error() -> *const c_char;
+        pub fn create_cwf() -> *mut PartialStructure;
+        pub fn destroy_cwf(pstruct: *mut PartialStructure);
+        pub fn get_sort(name: *const c_char) -> *const Sort;
+        pub fn get_sort_name(sort: *const Sort, len: *mut size_t) -> *const c_char;
+        pub fn get_operation(name: *const c_char) -> *const Operation;
+        pub fn get_operation_name(op: *const Operation, len: *mut size_t) -> *const c_char;
+        pub fn get_operation_arity(op: *const Operation) -> size_t;
+        pub fn get_operation_domain(op: *const Operation, index: size_t) -> *const Sort;
+        pub fn get_operation_codomain(op: *const Operation) -> *const Sort;
+        pub fn get_predicate(name: *const c_char) -> *const Predicate;
+        pub fn get_predicate_name(pred: *const Predicate, len: *mut size_t) -> *const c_char;
+        pub fn get_predicate_arity(pred: *const Predicate) -> size_t;
+        pub fn get_predicate_domain(pred: *const Predicate, index: size_t) -> *const Sort;
+        pub fn get_carrier_size(pstruct: *const PartialStructure) -> size_t;
+        pub fn are_equal(
+            pstruct: *mut PartialStructure,
+            l: size_t,
+            r: size_t,
+            result: *mut bool) -> c_int;
+        pub fn define_operation(
+            pstruct: *mut PartialStructure,
+            op: *const Operation,
+            args: *const size_t,
+            result: *mut size_t) -> c_int;
+        pub fn define_predicate(
+            pstruct: *mut PartialStructure,
+            pred: *const Predicate,
+            args: *const size_t) -> c_int;
+        pub fn predicate_holds(
+            pstruct: *mut PartialStructure,
+            pred: *const Predicate,
+            args: *const size_t,
+            result: *mut bool) -> c_int;
+        pub fn compute_fixpoint(pstruct: *mut PartialStructure, thread_count: size_t) -> c_int;
+        pub fn set_closure_limits(
+            pstruct: *mut PartialStructure,
+            limits: *const ClosureLimits) -> c_int;
+        pub fn get_closure_stats(pstruct: *mut PartialStructure, result: *mut ClosureStats) -> c_int;
+        pub fn get_sequent_stats(
+            pstruct: *mut PartialStructure,
+            index: size_t,
+            result: *mut SequentStats) -> c_int;
+        pub fn reset_closure_stats(pstruct: *mut PartialStructure) -> c_int;
+        pub fn find_conflicts(pstruct: *mut PartialStructure, count: *mut size_t) -> c_int;
+        pub fn get_conflict(
+            pstruct: *mut PartialStructure,
+            index: size_t,
+            result: *mut Conflict) -> c_int;
+        pub fn push_checkpoint(pstruct: *mut PartialStructure) -> c_int;
+        pub fn rollback_checkpoint(pstruct: *mut PartialStructure) -> c_int;
+        pub fn commit_checkpoint(pstruct: *mut PartialStructure) -> c_int;
+        pub fn collect_garbage(
+            pstruct: *mut PartialStructure,
+            roots: *const size_t,
+            root_count: size_t,
+            new_ids: *mut size_t) -> c_int;
+    }
+
+    pub const PHL_REMOVED_ELEMENT: size_t = size_t::MAX;
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    UnknownSort(String),
+    UnknownOperation(String),
+    UnknownPredicate(String),
+    ArityMismatch { name: &'static str, expected: usize, got: usize },
+    SortMismatch { name: &'static str, index: usize, expected: Sort, got: Sort },
+    InvalidElement(usize),
+    // A rollback or commit without an active checkpoint.
+    NoCheckpoint,
+    // An axiom could not be evaluated by the closure.
+    MalformedSequent(String),
+    // The closure gave up before reaching its fixpoint; the message says
+    // which limit it exceeded.
+    LimitExceeded(String),
+    // Anything else the engine reported, such as allocation failures.
+    Internal(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnknownSort(name) => write!(fmt, "unknown sort {}", name),
+            Error::UnknownOperation(name) => write!(fmt, "unknown operation {}", name),
+            Error::UnknownPredicate(name) => write!(fmt, "unknown predicate {}", name),
+            Error::ArityMismatch { name, expected, got } =>
+                write!(fmt, "{} expects {} arguments but got {}", name, expected, got),
+            Error::SortMismatch { name, index, expected, got } =>
+                write!(fmt, "argument {} of {} should have sort {} but has sort {}",
+                       index, name, expected.name(), got.name()),
+            Error::InvalidElement(id) => write!(fmt, "{} is not an element of the structure", id),
+            Error::NoCheckpoint => write!(fmt, "there is no active checkpoint"),
+            Error::MalformedSequent(msg) => write!(fmt, "malformed sequent: {}", msg),
+            Error::LimitExceeded(msg) => write!(fmt, "closure limit exceeded: {}", msg),
+            Error::Internal(msg) => write!(fmt, "internal phl error: {}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+fn last_error() -> String {
+    unsafe { CStr::from_ptr(ffi::get_last_error()) }.to_string_lossy().into_owned()
+}
+
+// Translates statuses that are not specific to a single entry point.
+fn check(status: c_int) -> Result<(), Error> {
+    match status {
+        ffi::PHL_OK => Ok(()),
+        ffi::PHL_MALFORMED_SEQUENT => Err(Error::MalformedSequent(last_error())),
+        ffi::PHL_NO_CHECKPOINT => Err(Error::NoCheckpoint),
+        ffi::PHL_LIMIT_EXCEEDED => Err(Error::LimitExceeded(last_error())),
+        _ => Err(Error::Internal(last_error())),
+    }
+}
+
+// Reads a name owned by the static signature on the C++ side.
+unsafe fn static_str(data: *const libc::c_char, len: size_t) -> &'static str {
+    let bytes = slice::from_raw_parts(data as *const u8, len);
+    str::from_utf8(bytes).expect("phl names are utf-8")
+}
+
+// Sorts, operations and predicates point into the signature, which is
+// immutable and lives for the whole program, so they can be freely shared.
+#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Sort(NonNull<ffi::Sort>);
+unsafe impl Send for Sort {}
+unsafe impl Sync for Sort {}
+
+impl Sort {
+    pub fn by_name(name: &str) -> Result<Sort, Error> {
+        let cstr = CString::new(name).map_err(|_| Error::UnknownSort(name.to_owned()))?;
+        let raw = unsafe { ffi::get_sort(cstr.as_ptr()) };
+        NonNull::new(raw as *mut _).map(Sort).ok_or_else(|| Error::UnknownSort(name.to_owned()))
+    }
+
+    pub fn name(self) -> &'static str {
+        let mut len = 0;
+        unsafe {
+            let data = ffi::get_sort_name(self.0.as_ptr(), &mut len);
+            static_str(data, len)
+        }
+    }
+}
+
+impl fmt::Debug for Sort {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Sort({})", self.name())
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Operation(NonNull<ffi::Operation>);
+unsafe impl Send for Operation {}
+unsafe impl Sync for Operation {}
+
+impl Operation {
+    pub fn by_name(name: &str) -> Result<Operation, Error> {
+        let cstr = CString::new(name).map_err(|_| Error::UnknownOperation(name.to_owned()))?;
+        let raw = unsafe { ffi::get_operation(cstr.as_ptr()) };
+        NonNull::new(raw as *mut _)
+            .map(Operation)
+            .ok_or_else(|| Error::UnknownOperation(name.to_owned()))
+    }
+
+    pub fn name(self) -> &'static str {
+        let mut len = 0;
+        unsafe {
+            let data = ffi::get_operation_name(self.0.as_ptr(), &mut len);
+            static_str(data, len)
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        unsafe { ffi::get_operation_arity(self.0.as_ptr()) }
+    }
+
+    pub fn domain(self) -> Vec<Sort> {
+        (0..self.arity())
+            .map(|i| {
+                let raw = unsafe { ffi::get_operation_domain(self.0.as_ptr(), i) };
+                Sort(NonNull::new(raw as *mut _).expect("operation domain is in signature"))
+            })
+            .collect()
+    }
+
+    pub fn codomain(self) -> Sort {
+        let raw = unsafe { ffi::get_operation_codomain(self.0.as_ptr()) };
+        Sort(NonNull::new(raw as *mut _).expect("operation codomain is in signature"))
+    }
+}
+
+impl fmt::Debug for Operation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Operation({})", self.name())
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Predicate(NonNull<ffi::Predicate>);
+unsafe impl Send for Predicate {}
+unsafe impl Sync for Predicate {}
+
+impl Predicate {
+    pub fn by_name(name: &str) -> Result<Predicate, Error> {
+        let cstr = CString::new(name).map_err(|_| Error::UnknownPredicate(name.to_owned()))?;
+        let raw = unsafe { ffi::get_predicate(cstr.as_ptr()) };
+        NonNull::new(raw as *mut _)
+            .map(Predicate)
+            .ok_or_else(|| Error::UnknownPredicate(name.to_owned()))
+    }
+
+    pub fn name(self) -> &'static str {
+        let mut len = 0;
+        unsafe {
+            let data = ffi::get_predicate_name(self.0.as_ptr(), &mut len);
+            static_str(data, len)
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        unsafe { ffi::get_predicate_arity(self.0.as_ptr()) }
+    }
+
+    pub fn domain(self) -> Vec<Sort> {
+        (0..self.arity())
+            .map(|i| {
+                let raw = unsafe { ffi::get_predicate_domain(self.0.as_ptr(), i) };
+                Sort(NonNull::new(raw as *mut _).expect("predicate domain is in signature"))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for Predicate {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Predicate({})", self.name())
+    }
+}
+
+// Checks that args fit the domain of the operation or predicate called name.
+fn check_args(name: &'static str, domain: &[Sort], args: &[Element]) -> Result<(), Error> {
+    if domain.len() != args.len() {
+        return Err(Error::ArityMismatch { name, expected: domain.len(), got: args.len() })
+    }
+    for (index, (&expected, arg)) in domain.iter().zip(args).enumerate() {
+        if arg.sort != expected {
+            return Err(Error::SortMismatch { name, index, expected, got: arg.sort })
+        }
+    }
+    Ok(())
+}
+
+// An element of the carrier of a partial structure. Elements are only
+// meaningful for the structure they were defined in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Element {
+    id: usize,
+    sort: Sort,
+}
+
+impl Element {
+    pub fn id(self) -> usize {
+        self.id
+    }
+
+    pub fn sort(self) -> Sort {
+        self.sort
+    }
+}
+
+// Statistics of the fixpoint computations of a structure.
+#[derive(Debug, Clone, Default)]
+pub struct ClosureStats {
+    pub closures: usize,
+    pub rounds: usize,
+    // one per axiom and per operation, whose functionality is an implicit
+    // axiom
+    pub sequents: Vec<SequentStats>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SequentStats {
+    pub name: String,
+    // rows of the relations in the premise that were tried
+    pub visited_rows: usize,
+    // matches of the premise
+    pub joined_rows: usize,
+    // equivalence classes merged and predicate rows added by the conclusion
+    pub merges: usize,
+    pub facts: usize,
+    pub time: Duration,
+}
+
+// A match of one of the conflicts of the theory, which no consistent
+// structure has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub name: &'static str,
+    // where the structure is inconsistent, a context for cwf
+    pub witness: Element,
+}
+
+// Bounds on every fixpoint computation of a structure, None meaning no bound.
+// The closure adds no elements, so the carrier size is only checked before it
+// starts, while rounds and time are checked after every round that derived
+// something.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_rounds: Option<usize>,
+    pub max_carrier_size: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+// The ids of elements after a garbage collection, by their ids before it.
+pub struct Renumbering(Vec<size_t>);
+
+impl Renumbering {
+    // Returns None if el was removed.
+    pub fn get(&self, el: Element) -> Option<Element> {
+        match self.0.get(el.id) {
+            Some(&id) if id != ffi::PHL_REMOVED_ELEMENT => Some(Element { id, sort: el.sort }),
+            _ => None,
+        }
+    }
+}
+
+pub struct Structure {
+    raw: NonNull<ffi::PartialStructure>,
+    // threads used by compute_fixpoint, 0 meaning one per core
+    thread_count: usize,
+}
+
+impl Structure {
+    // Creates an empty partial structure over the cwf signature.
+    pub fn cwf() -> Result<Self, Error> {
+        let raw = unsafe { ffi::create_cwf() };
+        NonNull::new(raw)
+            .map(|raw| Structure { raw, thread_count: 1 })
+            .ok_or_else(|| Error::Internal(last_error()))
+    }
+
+    pub fn carrier_size(&self) -> usize {
+        unsafe { ffi::get_carrier_size(self.raw.as_ptr()) }
+    }
+
+    fn check_element(&self, el: Element) -> Result<(), Error> {
+        if el.id < self.carrier_size() {
+            Ok(())
+        } else {
+            Err(Error::InvalidElement(el.id))
+        }
+    }
+
+    pub fn define_operation(&mut self, op: Operation, args: &[Element]) -> Result<Element, Error> {
+        check_args(op.name(), &op.domain(), args)?;
+
+        let ids: Vec<size_t> = args.iter().map(|arg| arg.id).collect();
+        let mut id = 0;
+        let status = unsafe {
+            ffi::define_operation(self.raw.as_ptr(), op.0.as_ptr(), ids.as_ptr(), &mut id)
+        };
+        match status {
+            ffi::PHL_OK => Ok(Element { id, sort: op.codomain() }),
+            // The sorts of the arguments were checked above, so phl can only
+            // disagree about them if the element is from another structure.
+            // phl stores the index of that argument in id then.
+            ffi::PHL_INVALID_ELEMENT | ffi::PHL_SORT_MISMATCH => Err(match args.get(id) {
+                Some(arg) => Error::InvalidElement(arg.id),
+                None => Error::Internal(last_error()),
+            }),
+            status => Err(check(status).unwrap_err()),
+        }
+    }
+
+    pub fn are_equal(&mut self, l: Element, r: Element) -> Result<bool, Error> {
+        self.check_element(l)?;
+        self.check_element(r)?;
+        let mut result = false;
+        check(unsafe { ffi::are_equal(self.raw.as_ptr(), l.id, r.id, &mut result) })?;
+        Ok(result)
+    }
+
+    // Makes pred hold for args. Consequences of this only become visible
+    // after the next fixpoint computation.
+    pub fn define_predicate(&mut self, pred: Predicate, args: &[Element]) -> Result<(), Error> {
+        check_args(pred.name(), &pred.domain(), args)?;
+        for &arg in args {
+            self.check_element(arg)?;
+        }
+        let ids: Vec<size_t> = args.iter().map(|arg| arg.id).collect();
+        check(unsafe { ffi::define_predicate(self.raw.as_ptr(), pred.0.as_ptr(), ids.as_ptr()) })
+    }
+
+    pub fn holds(&mut self, pred: Predicate, args: &[Element]) -> Result<bool, Error> {
+        check_args(pred.name(), &pred.domain(), args)?;
+        for &arg in args {
+            self.check_element(arg)?;
+        }
+        let ids: Vec<size_t> = args.iter().map(|arg| arg.id).collect();
+        let mut result = false;
+        check(unsafe {
+            ffi::predicate_holds(self.raw.as_ptr(), pred.0.as_ptr(), ids.as_ptr(), &mut result)
+        })?;
+        Ok(result)
+    }
+
+    // Fails with LimitExceeded if the fixpoint is not reached within the
+    // limits. The structure then lacks some consequences of the axioms but is
+    // otherwise intact, and the next call continues where this one stopped.
+    pub fn compute_fixpoint(&mut self) -> Result<(), Error> {
+        check(unsafe { ffi::compute_fixpoint(self.raw.as_ptr(), self.thread_count) })
+    }
+
+    // The matches of the conflicts, which like holds only reflect the axioms
+    // after the next fixpoint computation.
+    pub fn conflicts(&mut self) -> Result<Vec<Conflict>, Error> {
+        let mut count = 0;
+        check(unsafe { ffi::find_conflicts(self.raw.as_ptr(), &mut count) })?;
+        let mut conflicts = Vec::with_capacity(count);
+        for index in 0 .. count {
+            let mut raw = ffi::Conflict {
+                name: std::ptr::null(),
+                name_len: 0,
+                witness: 0,
+                witness_sort: std::ptr::null(),
+            };
+            check(unsafe { ffi::get_conflict(self.raw.as_ptr(), index, &mut raw) })?;
+            let sort = NonNull::new(raw.witness_sort as *mut _).expect("witness sort is in signature");
+            conflicts.push(Conflict {
+                name: unsafe { static_str(raw.name, raw.name_len) },
+                witness: Element { id: raw.witness, sort: Sort(sort) },
+            });
+        }
+        Ok(conflicts)
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) -> Result<(), Error> {
+        let raw = ffi::ClosureLimits {
+            max_rounds: limits.max_rounds.unwrap_or(0),
+            max_carrier_size: limits.max_carrier_size.unwrap_or(0),
+            // at least a nanosecond, since 0 means no timeout, and at most
+            // what phl's clock can represent
+            timeout_nanoseconds: limits.timeout.map_or(0, |timeout| {
+                timeout.as_nanos().clamp(1, i64::MAX as u128) as u64
+            }),
+        };
+        check(unsafe { ffi::set_closure_limits(self.raw.as_ptr(), &raw) })
+    }
+
+    // Sums over all fixpoint computations since the structure was created or
+    // the statistics were reset.
+    pub fn closure_stats(&self) -> Result<ClosureStats, Error> {
+        let mut raw = ffi::ClosureStats::default();
+        check(unsafe { ffi::get_closure_stats(self.raw.as_ptr(), &mut raw) })?;
+        let mut sequents = Vec::with_capacity(raw.sequent_count);
+        for index in 0 .. raw.sequent_count {
+            let mut seq = ffi::SequentStats {
+                name: std::ptr::null(),
+                name_len: 0,
+                visited_rows: 0,
+                joined_rows: 0,
+                merges: 0,
+                facts: 0,
+                nanoseconds: 0,
+            };
+            check(unsafe { ffi::get_sequent_stats(self.raw.as_ptr(), index, &mut seq) })?;
+            // The name is owned by the structure, so it is copied.
+            let name = unsafe { slice::from_raw_parts(seq.name as *const u8, seq.name_len) };
+            sequents.push(SequentStats {
+                name: String::from_utf8_lossy(name).into_owned(),
+                visited_rows: seq.visited_rows,
+                joined_rows: seq.joined_rows,
+                merges: seq.merges,
+                facts: seq.facts,
+                time: Duration::from_nanos(seq.nanoseconds),
+            });
+        }
+        Ok(ClosureStats { closures: raw.closures, rounds: raw.rounds, sequents })
+    }
+
+    pub fn reset_closure_stats(&mut self) -> Result<(), Error> {
+        check(unsafe { ffi::reset_closure_stats(self.raw.as_ptr()) })
+    }
+
+    // Sets the number of threads that compute_fixpoint evaluates the axioms
+    // on, 0 meaning one per core. The fixpoint is the same for any number.
+    pub fn set_thread_count(&mut self, thread_count: usize) {
+        self.thread_count = thread_count;
+    }
+
+    // Checkpoints nest. Rolling back to a checkpoint undoes everything since
+    // it was taken: elements defined since then are no longer in the carrier
+    // and their ids are handed out again, and derived equalities and
+    // predicates are forgotten. Committing keeps the changes.
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        check(unsafe { ffi::push_checkpoint(self.raw.as_ptr()) })
+    }
+
+    pub fn rollback(&mut self) -> Result<(), Error> {
+        check(unsafe { ffi::rollback_checkpoint(self.raw.as_ptr()) })
+    }
+
+    pub fn commit(&mut self) -> Result<(), Error> {
+        check(unsafe { ffi::commit_checkpoint(self.raw.as_ptr()) })
+    }
+
+    // Removes the elements that roots are not defined from and renumbers the
+    // rest. Elements that were not roots may be renumbered to a representative
+    // of their equality class or removed even if they are still needed, so
+    // they should not be used afterwards.
+    pub fn collect_garbage(&mut self, roots: &[Element]) -> Result<Renumbering, Error> {
+        for &root in roots {
+            self.check_element(root)?;
+        }
+        let ids: Vec<size_t> = roots.iter().map(|root| root.id).collect();
+        let mut new_ids = vec![0; self.carrier_size()];
+        check(unsafe {
+            ffi::collect_garbage(self.raw.as_ptr(), ids.as_ptr(), ids.len(), new_ids.as_mut_ptr())
+        })?;
+        Ok(Renumbering(new_ids))
+    }
+}
+
+impl Drop for Structure {
+    fn drop(&mut self) {
+        unsafe { ffi::destroy_cwf(self.raw.as_ptr()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(name: &str) -> Operation {
+        Operation::by_name(name).unwrap()
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(op("comp").name(), "comp");
+        assert_eq!(op("comp").arity(), 2);
+        let mor = Sort::by_name("Mor").unwrap();
+        assert_eq!(op("comp").domain(), vec![mor, mor]);
+        assert_eq!(op("dom").codomain(), Sort::by_name("Ctx").unwrap());
+        assert_eq!(Operation::by_name("nonexistent"),
+                   Err(Error::UnknownOperation("nonexistent".to_owned())));
+        assert_eq!(Sort::by_name("Nat"), Err(Error::UnknownSort("Nat".to_owned())));
+        let is_prop = Predicate::by_name("is_prop").unwrap();
+        assert_eq!(is_prop.name(), "is_prop");
+        assert_eq!(is_prop.domain(), vec![Sort::by_name("Ty").unwrap()]);
+    }
+
+    #[test]
+    fn define_checks_sorts() {
+        let mut pstruct = Structure::cwf().unwrap();
+        let empty = pstruct.define_operation(op("empty_ctx"), &[]).unwrap();
+        assert_eq!(empty.sort(), Sort::by_name("Ctx").unwrap());
+
+        match pstruct.define_operation(op("bool"), &[]) {
+            Err(Error::ArityMismatch { expected: 1, got: 0, .. }) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+        match pstruct.define_operation(op("wkn"), &[empty]) {
+            Err(Error::SortMismatch { index: 0, .. }) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let bool1 = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        let bool2 = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+        assert_eq!(pstruct.are_equal(bool1, bool2), Ok(true));
+    }
+
+    #[test]
+    fn predicates() {
+        let is_prop = Predicate::by_name("is_prop").unwrap();
+        let mut pstruct = Structure::cwf().unwrap();
+        let empty = pstruct.define_operation(op("empty_ctx"), &[]).unwrap();
+        match pstruct.define_predicate(is_prop, &[empty]) {
+            Err(Error::SortMismatch { name: "is_prop", index: 0, .. }) => (),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let bool_ty = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        let id = pstruct.define_operation(op("id"), &[empty]).unwrap();
+        let subst_bool = pstruct.define_operation(op("subst_ty"), &[id, bool_ty]).unwrap();
+        let tt = pstruct.define_operation(op("true"), &[empty]).unwrap();
+        let ff = pstruct.define_operation(op("false"), &[empty]).unwrap();
+        pstruct.define_operation(op("tm_ty"), &[tt]).unwrap();
+        pstruct.define_operation(op("tm_ty"), &[ff]).unwrap();
+        assert_eq!(pstruct.holds(is_prop, &[subst_bool]), Ok(false));
+
+        pstruct.define_predicate(is_prop, &[bool_ty]).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+        assert_eq!(pstruct.holds(is_prop, &[subst_bool]), Ok(true));
+        assert_eq!(pstruct.are_equal(tt, ff), Ok(true));
+    }
+
+    #[test]
+    fn foreign_element() {
+        let mut small = Structure::cwf().unwrap();
+        let mut big = Structure::cwf().unwrap();
+        let mut ctx = big.define_operation(op("empty_ctx"), &[]).unwrap();
+        for _ in 0..3 {
+            let ty = big.define_operation(op("bool"), &[ctx]).unwrap();
+            ctx = big.define_operation(op("ctx_ext"), &[ty]).unwrap();
+        }
+        // ctx is not an element of small, which is still empty.
+        assert_eq!(small.define_operation(op("id"), &[ctx]), Err(Error::InvalidElement(ctx.id())));
+        assert_eq!(small.are_equal(ctx, ctx), Err(Error::InvalidElement(ctx.id())));
+    }
+
+    #[test]
+    fn rollback() {
+        let mut pstruct = Structure::cwf().unwrap();
+        let empty = pstruct.define_operation(op("empty_ctx"), &[]).unwrap();
+        let bool1 = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        assert_eq!(pstruct.rollback(), Err(Error::NoCheckpoint));
+
+        pstruct.checkpoint().unwrap();
+        let bool2 = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+        assert_eq!(pstruct.are_equal(bool1, bool2), Ok(true));
+        pstruct.rollback().unwrap();
+        assert_eq!(pstruct.carrier_size(), 2);
+        assert_eq!(pstruct.are_equal(bool1, bool2), Err(Error::InvalidElement(bool2.id())));
+
+        pstruct.checkpoint().unwrap();
+        let bool3 = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        assert_eq!(bool3.id(), bool2.id());
+        pstruct.commit().unwrap();
+        assert_eq!(pstruct.commit(), Err(Error::NoCheckpoint));
+        pstruct.compute_fixpoint().unwrap();
+        assert_eq!(pstruct.are_equal(bool1, bool3), Ok(true));
+    }
+
+    #[test]
+    fn collect_garbage() {
+        let mut pstruct = Structure::cwf().unwrap();
+        let empty = pstruct.define_operation(op("empty_ctx"), &[]).unwrap();
+        let bool_ty = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        let ctx = pstruct.define_operation(op("ctx_ext"), &[bool_ty]).unwrap();
+        let unused = pstruct.define_operation(op("bool"), &[ctx]).unwrap();
+        pstruct.define_operation(op("ty_ctx"), &[bool_ty]).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+
+        let renumbering = pstruct.collect_garbage(&[empty, bool_ty, ctx]).unwrap();
+        assert_eq!(renumbering.get(unused), None);
+        assert!(pstruct.carrier_size() < 5);
+        let empty = renumbering.get(empty).unwrap();
+        let bool_ty = renumbering.get(bool_ty).unwrap();
+        let ctx = renumbering.get(ctx).unwrap();
+        // Equalities between kept elements survive, whether or not ty_ctx was.
+        let again = pstruct.define_operation(op("ty_ctx"), &[bool_ty]).unwrap();
+        let ext = pstruct.define_operation(op("ctx_ext"), &[bool_ty]).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+        assert_eq!(pstruct.are_equal(again, empty), Ok(true));
+        assert_eq!(pstruct.are_equal(ext, ctx), Ok(true));
+
+        pstruct.checkpoint().unwrap();
+        assert!(pstruct.collect_garbage(&[ctx]).is_err());
+    }
+
+    #[test]
+    fn closure_stats() {
+        let mut pstruct = Structure::cwf().unwrap();
+        let empty = pstruct.define_operation(op("empty_ctx"), &[]).unwrap();
+        let bool_ty = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        pstruct.define_operation(op("ty_ctx"), &[bool_ty]).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+        pstruct.compute_fixpoint().unwrap();
+
+        let stats = pstruct.closure_stats().unwrap();
+        assert_eq!(stats.closures, 2);
+        assert!(stats.rounds >= 3);
+        let ty_ctx_bool = stats.sequents.iter()
+            .find(|seq| seq.name == "ty_ctx(bool(G)) -= G")
+            .unwrap();
+        assert!(ty_ctx_bool.joined_rows >= 1);
+        assert_eq!(ty_ctx_bool.merges, 1);
+        assert!(stats.sequents.iter().any(|seq| seq.name == "functionality of comp"));
+
+        pstruct.reset_closure_stats().unwrap();
+        assert_eq!(pstruct.closure_stats().unwrap().closures, 0);
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut pstruct = Structure::cwf().unwrap();
+        let empty = pstruct.define_operation(op("empty_ctx"), &[]).unwrap();
+        let bool_ty = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        for name in &["true", "false"] {
+            let tm = pstruct.define_operation(op(name), &[empty]).unwrap();
+            pstruct.define_operation(op("tm_ty"), &[tm]).unwrap();
+        }
+        pstruct.compute_fixpoint().unwrap();
+        assert_eq!(pstruct.conflicts(), Ok(vec![]));
+
+        // Proof irrelevance for bool identifies true and false.
+        let is_prop = Predicate::by_name("is_prop").unwrap();
+        pstruct.define_predicate(is_prop, &[bool_ty]).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+        let conflicts = pstruct.conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].name, "a = true(G) && a = false(G) |= falsity");
+        assert!(pstruct.are_equal(conflicts[0].witness, empty).unwrap());
+    }
+
+    #[test]
+    fn limits() {
+        let mut pstruct = Structure::cwf().unwrap();
+        let empty = pstruct.define_operation(op("empty_ctx"), &[]).unwrap();
+        let bool_ty = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        let ctx = pstruct.define_operation(op("ty_ctx"), &[bool_ty]).unwrap();
+
+        pstruct.set_limits(Limits { max_rounds: Some(1), ..Limits::default() }).unwrap();
+        match pstruct.compute_fixpoint() {
+            Err(Error::LimitExceeded(msg)) => assert_eq!(msg, "no fixpoint after 1 rounds"),
+            result => panic!("expected the round limit to be exceeded, got {:?}", result),
+        }
+        assert_eq!(pstruct.closure_stats().unwrap().rounds, 1);
+
+        let carrier_size = pstruct.carrier_size();
+        let limits = Limits { max_carrier_size: Some(carrier_size - 1), ..Limits::default() };
+        pstruct.set_limits(limits).unwrap();
+        assert!(matches!(pstruct.compute_fixpoint(), Err(Error::LimitExceeded(_))));
+
+        pstruct.set_limits(Limits::default()).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+        assert!(pstruct.are_equal(ctx, empty).unwrap());
+
+        // A timeout beyond the range of the clock is no limit.
+        let mut pstruct = Structure::cwf().unwrap();
+        let empty = pstruct.define_operation(op("empty_ctx"), &[]).unwrap();
+        let bool_ty = pstruct.define_operation(op("bool"), &[empty]).unwrap();
+        let ctx = pstruct.define_operation(op("ty_ctx"), &[bool_ty]).unwrap();
+        let limits = Limits { timeout: Some(Duration::MAX), ..Limits::default() };
+        pstruct.set_limits(limits).unwrap();
+        pstruct.compute_fixpoint().unwrap();
+        assert!(pstruct.are_equal(ctx, empty).unwrap());
+    }
+}
@@ -0,0 +1,120 @@
+// Prints cwf syntax in the surface syntax of qt. Variables are printed by
+// name, and substitutions are carried out while printing as far as they can
+// be: weakenings are dropped and a substitution by an extension replaces its
+// variable.
+use std::collections::HashMap;
+use super::cwf::*;
+
+pub struct Printer<'a> {
+    syntax: &'a Syntax,
+    // names of variables, by the type of the comprehension they come from
+    names: HashMap<TyId, String>,
+}
+
+fn arg(s: String) -> String {
+    if s.contains(' ') {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(syntax: &'a Syntax) -> Self {
+        Printer { syntax, names: HashMap::new() }
+    }
+
+    // Names the variable of the comprehension of ty.
+    pub fn name(&mut self, ty: TyId, name: &str) {
+        self.names.insert(ty, name.to_owned());
+    }
+
+    pub fn ty(&self, ty: TyId) -> String {
+        self.ty_under(ty, &mut vec![])
+    }
+
+    pub fn tm(&self, tm: TmId) -> String {
+        self.tm_under(tm, &mut vec![])
+    }
+
+    // Prints ty with the morphisms on the stack applied to it, the last one
+    // first.
+    fn ty_under(&self, ty: TyId, stack: &mut Vec<MorphId>) -> String {
+        match self.syntax.ty(ty) {
+            Ty::Subst(f, ty) => {
+                stack.push(f);
+                let s = self.ty_under(ty, stack);
+                stack.pop();
+                s
+            },
+            Ty::Bool(_) => "bool".to_owned(),
+            Ty::Eq(a, b) => format!("{} = {}", self.tm_under(a, stack), self.tm_under(b, stack)),
+        }
+    }
+
+    fn tm_under(&self, tm: TmId, stack: &mut Vec<MorphId>) -> String {
+        match self.syntax.tm(tm) {
+            Tm::Subst(f, tm) => {
+                stack.push(f);
+                let s = self.tm_under(tm, stack);
+                stack.pop();
+                s
+            },
+            Tm::Var(ty) => self.var_under(ty, stack.clone()),
+            Tm::Refl(a) => format!("refl {}", arg(self.tm_under(a, stack))),
+            Tm::True(_) => "true".to_owned(),
+            Tm::False(_) => "false".to_owned(),
+            Tm::ElimBool(_, _, true_case, false_case) => {
+                // The elimination lives in a context extended by bool and is
+                // usually substituted by an extension that gives the value
+                // to eliminate.
+                let mut inner = stack.clone();
+                let val = loop {
+                    match inner.pop().map(|f| self.syntax.morph(f)) {
+                        Some(Morph::Identity(_)) => (),
+                        Some(Morph::Composition(g, f)) => {
+                            inner.push(g);
+                            inner.push(f);
+                        },
+                        Some(Morph::Extension(g, _, val)) => {
+                            let val = self.tm_under(val, &mut inner.clone());
+                            inner.push(g);
+                            break val
+                        },
+                        _ => break "_".to_owned(),
+                    }
+                };
+                format!("elim {} | => {} | => {} end",
+                        val,
+                        self.tm_under(true_case, &mut inner.clone()),
+                        self.tm_under(false_case, &mut inner))
+            },
+        }
+    }
+
+    fn var_under(&self, ty: TyId, mut stack: Vec<MorphId>) -> String {
+        // The number of weakenings applied to the variable so far that have
+        // not been cancelled by an extension.
+        let mut weakenings = 0;
+        while let Some(f) = stack.pop() {
+            match self.syntax.morph(f) {
+                Morph::Identity(_) => (),
+                Morph::Weakening(_) => weakenings += 1,
+                Morph::Composition(g, f) => {
+                    stack.push(g);
+                    stack.push(f);
+                },
+                // <g, A, a> cancels the last weakening, or replaces the
+                // variable by a.
+                Morph::Extension(g, _, a) =>
+                    if weakenings > 0 {
+                        weakenings -= 1;
+                        stack.push(g);
+                    } else {
+                        return self.tm_under(a, &mut stack)
+                    },
+            }
+        }
+        self.names.get(&ty).cloned().unwrap_or_else(|| "_".to_owned())
+    }
+}
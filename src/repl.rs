@@ -0,0 +1,213 @@
+// An interactive session around a single type checker. Defs are checked as
+// they are entered and can be used by everything entered after them.
+use std::io::{self, BufRead, Write};
+use super::cwf::TyId;
+use super::lang;
+use super::model::{Equality, Judgement, Model};
+use super::type_checker::TypeChecker;
+
+const HELP: &str = "\
+def ... .           check a def and make it available to later input
+:type <expr>        infer the type of expr
+:eq <expr> <expr>   decide whether two terms are judgementally equal
+:prop <type>        decide whether all terms of a type are equal
+:contractible <type>
+                    decide whether a type is a proposition with a term
+:decidable <type>   decide whether a type has decidable equality
+:ctx                list the defs
+:undo               forget the last def
+:dump               print the cwf syntax of the defs
+:help               print this help";
+
+pub struct Repl<M: Model> {
+    tc: TypeChecker<M>,
+    // the lines of a def that has not been terminated by a "." yet
+    pending: String,
+}
+
+impl<M: Model> Repl<M> {
+    pub fn new(model: M) -> Self {
+        Repl { tc: TypeChecker::new(model), pending: String::new() }
+    }
+
+    // Handles a line of input and returns what to print. Returns None while
+    // a def is incomplete.
+    pub fn line(&mut self, line: &str) -> Option<String> {
+        let trimmed = line.trim();
+        if self.pending.is_empty() {
+            if trimmed.is_empty() {
+                return None
+            }
+            if trimmed.starts_with(':') {
+                let (command, arg) = match trimmed.find(char::is_whitespace) {
+                    Some(i) => (&trimmed[.. i], trimmed[i ..].trim()),
+                    None => (trimmed, ""),
+                };
+                return Some(self.command(command, arg))
+            }
+        }
+
+        self.pending += line;
+        self.pending += "\n";
+        if !trimmed.ends_with('.') {
+            return None
+        }
+        let src = std::mem::take(&mut self.pending);
+        Some(self.defs(&src))
+    }
+
+    fn defs(&mut self, src: &str) -> String {
+        let unit = match lang::parse_unit(src) {
+            Ok(unit) => unit,
+            Err(diagnostics) =>
+                return diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"),
+        };
+        let mut out = vec![];
+        for def in unit {
+            out.push(match (self.tc.check_def(&def), def.name) {
+                (Ok(_), Some(name)) => format!("Defined {}", name),
+                (Ok(_), None) => "Ok".to_owned(),
+                (Err(err), _) => format!("Err:\n{}", err),
+            });
+        }
+        out.join("\n")
+    }
+
+    fn command(&mut self, command: &str, arg: &str) -> String {
+        match command {
+            ":type" => match lang::parse_expr(arg) {
+                Ok(expr) => self.tc.infer_ty(&expr).unwrap_or_else(|err| format!("Err:\n{}", err)),
+                Err(diagnostic) => diagnostic.to_string(),
+            },
+            ":eq" => match lang::parse_expr_pair(arg) {
+                Ok((a, b)) => match self.tc.judgementally_eq(&a, &b) {
+                    Ok(Equality::Equal) => "equal".to_owned(),
+                    Ok(Equality::Distinct) => "distinct".to_owned(),
+                    Ok(Equality::Unknown) => "not known to be equal".to_owned(),
+                    Err(err) => format!("Err:\n{}", err),
+                },
+                Err(diagnostic) => diagnostic.to_string(),
+            },
+            ":prop" => self.holds(Judgement::Prop, arg, "a proposition"),
+            ":contractible" => self.holds(Judgement::Contractible, arg, "contractible"),
+            ":decidable" => self.holds(Judgement::Decidable, arg, "decidable"),
+            ":ctx" => {
+                let signatures = self.tc.signatures();
+                if signatures.is_empty() {
+                    "No defs".to_owned()
+                } else {
+                    signatures.join("\n")
+                }
+            },
+            ":undo" => match self.tc.undo_def() {
+                Some(name) => {
+                    self.tc.collect_garbage();
+                    format!("Forgot {}", name)
+                },
+                None => "Nothing to undo".to_owned(),
+            },
+            ":dump" => self.tc.dump().trim_end().to_owned(),
+            ":help" => HELP.to_owned(),
+            _ => format!("Unknown command {}, see :help", command),
+        }
+    }
+
+    fn holds(&mut self, judgement: fn(TyId) -> Judgement, arg: &str, what: &str) -> String {
+        match lang::parse_expr(arg) {
+            Ok(ty) => match self.tc.holds(judgement, &ty) {
+                Ok(true) => what.to_owned(),
+                Ok(false) => format!("not known to be {}", what),
+                Err(err) => format!("Err:\n{}", err),
+            },
+            Err(diagnostic) => diagnostic.to_string(),
+        }
+    }
+}
+
+pub fn run<M: Model>(model: M) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut repl = Repl::new(model);
+    loop {
+        write!(stdout, "{}", if repl.pending.is_empty() { "qt> " } else { "... " })?;
+        stdout.flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(())
+        }
+        if let Some(out) = repl.line(&line) {
+            writeln!(stdout, "{}", out)?;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "phl"))]
+mod tests {
+    use crate::cwf_model::Cwf;
+    use super::Repl;
+
+    fn session(lines: &[&str]) -> Vec<String> {
+        let mut repl = Repl::new(Cwf::new());
+        lines.iter().filter_map(|line| repl.line(line)).collect()
+    }
+
+    #[test]
+    fn defs_and_commands() {
+        let out = session(&[
+            "def negb (b : bool) : bool :=",
+            "  elim b | => false | => true end.",
+            ":type negb true",
+            ":eq (negb true) false",
+            ":eq (negb true) true",
+            "def sym {a b : bool} (p : a = b) : b = a := refl b.",
+            ":ctx",
+            ":undo",
+            ":ctx",
+            ":type sym",
+            ":frobnicate",
+        ]);
+        assert_eq!(out, vec![
+            "Defined negb",
+            "bool",
+            "equal",
+            "distinct",
+            "Defined sym",
+            "negb (b : bool) : bool\nsym {a : bool} {b : bool} (p : a = b) : b = a",
+            "Forgot sym",
+            "negb (b : bool) : bool",
+            "Err:\nunknown definition sym",
+            "Unknown command :frobnicate, see :help",
+        ]);
+    }
+
+    #[test]
+    fn judgements() {
+        let out = session(&[
+            ":prop true = false",
+            ":prop bool",
+            ":contractible true = true",
+            ":decidable bool",
+        ]);
+        assert_eq!(out, vec![
+            "a proposition",
+            "not known to be a proposition",
+            "not known to be contractible",
+            "decidable",
+        ]);
+    }
+
+    #[test]
+    fn errors() {
+        let out = session(&[
+            "def f : bool := refl true.",
+            "def g : bool :=",
+            "  true true).",
+            ":type ?x",
+            ":eq true",
+        ]);
+        assert!(out[0].starts_with("Err:\nexpected:"), "{}", out[0]);
+        assert_eq!(out[1], "2:12: unexpected `)`, expected `.`");
+        assert_eq!(out[2], "Err:\nCannot infer the type of ?x");
+        assert!(out[3].starts_with("1:5: unexpected end of input"), "{}", out[3]);
+    }
+}
@@ -0,0 +1,452 @@
+// A model that records every call made to another model, so that the
+// interaction between the checker and a model can be inspected and replayed
+// against a different model outside of the checker.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+use super::model::{Equality, GaveUp, Judgement, Model};
+use super::cwf::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Call {
+    CtxEq(CtxId, CtxId),
+    MorphEq(MorphId, MorphId),
+    TyEq(TyId, TyId),
+    TmEq(TmId, TmId),
+    Holds(Judgement),
+    IsInconsistent(CtxId),
+    EmptyCtx,
+    Comprehension(TyId),
+    Weakening(TyId),
+    Var(TyId),
+    IdMorph(CtxId),
+    Compose(MorphId, MorphId),
+    Extension(MorphId, TyId, TmId),
+    SubstTy(MorphId, TyId),
+    SubstTm(MorphId, TmId),
+    EqTy(TmId, TmId),
+    Refl(TmId),
+    BoolTy(CtxId),
+    TrueTm(CtxId),
+    FalseTm(CtxId),
+    ElimBool(CtxId, TyId, TmId, TmId),
+    Checkpoint,
+    Rollback,
+    Commit,
+}
+
+impl Call {
+    // The name of the called Model method.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Call::CtxEq(..) => "ctx_eq",
+            Call::MorphEq(..) => "morph_eq",
+            Call::TyEq(..) => "ty_eq",
+            Call::TmEq(..) => "tm_eq",
+            Call::Holds(..) => "holds",
+            Call::IsInconsistent(..) => "is_inconsistent",
+            Call::EmptyCtx => "empty_ctx",
+            Call::Comprehension(..) => "comprehension",
+            Call::Weakening(..) => "weakening",
+            Call::Var(..) => "var",
+            Call::IdMorph(..) => "id_morph",
+            Call::Compose(..) => "compose",
+            Call::Extension(..) => "extension",
+            Call::SubstTy(..) => "subst_ty",
+            Call::SubstTm(..) => "subst_tm",
+            Call::EqTy(..) => "eq_ty",
+            Call::Refl(..) => "refl",
+            Call::BoolTy(..) => "bool_ty",
+            Call::TrueTm(..) => "true_tm",
+            Call::FalseTm(..) => "false_tm",
+            Call::ElimBool(..) => "elim_bool",
+            Call::Checkpoint => "checkpoint",
+            Call::Rollback => "rollback",
+            Call::Commit => "commit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ret {
+    Unit,
+    Bool(bool),
+    Equality(Equality),
+    // a query the model gave up on
+    GaveUp,
+    Ctx(CtxId),
+    Morph(MorphId),
+    Ty(TyId),
+    Tm(TmId),
+}
+
+impl Ret {
+    fn answer(answer: Result<bool, GaveUp>) -> Ret {
+        match answer {
+            Ok(answer) => Ret::Bool(answer),
+            Err(_) => Ret::GaveUp,
+        }
+    }
+
+    fn equality(equality: Result<Equality, GaveUp>) -> Ret {
+        match equality {
+            Ok(equality) => Ret::Equality(equality),
+            Err(_) => Ret::GaveUp,
+        }
+    }
+
+    fn is_answer(self) -> bool {
+        matches!(self, Ret::Bool(_) | Ret::Equality(_) | Ret::GaveUp)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub call: Call,
+    pub ret: Ret,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:?} -> {:?} ({:?})", self.call, self.ret, self.elapsed)
+    }
+}
+
+pub struct TracingModel<M: Model> {
+    inner: M,
+    trace: Vec<Event>,
+    // print every event to stderr as it happens
+    log: bool,
+}
+
+impl<M: Model> TracingModel<M> {
+    pub fn new(inner: M) -> Self {
+        TracingModel { inner, trace: vec![], log: false }
+    }
+
+    pub fn logging(inner: M) -> Self {
+        TracingModel { log: true, ..TracingModel::new(inner) }
+    }
+
+    pub fn trace(&self) -> &[Event] {
+        &self.trace
+    }
+
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    // Number of calls and total time spent per method.
+    pub fn summary(&self) -> BTreeMap<&'static str, (usize, Duration)> {
+        let mut summary = BTreeMap::new();
+        for event in &self.trace {
+            let entry = summary.entry(event.call.name()).or_insert((0, Duration::default()));
+            entry.0 += 1;
+            entry.1 += event.elapsed;
+        }
+        summary
+    }
+
+    fn record<T, F, R>(&mut self, call: Call, f: F, ret: R) -> T
+        where T: Clone, F: FnOnce(&mut M) -> T, R: FnOnce(T) -> Ret
+    {
+        let start = Instant::now();
+        let result = f(&mut self.inner);
+        let event = Event { call, ret: ret(result.clone()), elapsed: start.elapsed() };
+        if self.log {
+            eprintln!("{}", event);
+        }
+        self.trace.push(event);
+        result
+    }
+}
+
+impl<M: Model> Model for TracingModel<M> {
+    fn syntax(&self) -> &Syntax {
+        self.inner.syntax()
+    }
+
+    fn ctx_eq(&mut self, l: CtxId, r: CtxId) -> Result<Equality, GaveUp> {
+        self.record(Call::CtxEq(l, r), |m| m.ctx_eq(l, r), Ret::equality)
+    }
+    fn morph_eq(&mut self, l: MorphId, r: MorphId) -> Result<Equality, GaveUp> {
+        self.record(Call::MorphEq(l, r), |m| m.morph_eq(l, r), Ret::equality)
+    }
+    fn ty_eq(&mut self, l: TyId, r: TyId) -> Result<Equality, GaveUp> {
+        self.record(Call::TyEq(l, r), |m| m.ty_eq(l, r), Ret::equality)
+    }
+    fn tm_eq(&mut self, l: TmId, r: TmId) -> Result<Equality, GaveUp> {
+        self.record(Call::TmEq(l, r), |m| m.tm_eq(l, r), Ret::equality)
+    }
+
+    fn holds(&mut self, judgement: Judgement) -> Result<bool, GaveUp> {
+        self.record(Call::Holds(judgement), |m| m.holds(judgement), Ret::answer)
+    }
+    fn is_inconsistent(&mut self, ctx: CtxId) -> Result<bool, GaveUp> {
+        self.record(Call::IsInconsistent(ctx), |m| m.is_inconsistent(ctx), Ret::answer)
+    }
+
+    fn empty_ctx(&mut self) -> CtxId {
+        self.record(Call::EmptyCtx, |m| m.empty_ctx(), Ret::Ctx)
+    }
+    fn comprehension(&mut self, ty: TyId) -> CtxId {
+        self.record(Call::Comprehension(ty), |m| m.comprehension(ty), Ret::Ctx)
+    }
+    fn weakening(&mut self, ty: TyId) -> MorphId {
+        self.record(Call::Weakening(ty), |m| m.weakening(ty), Ret::Morph)
+    }
+    fn var(&mut self, ty: TyId) -> TmId {
+        self.record(Call::Var(ty), |m| m.var(ty), Ret::Tm)
+    }
+
+    fn id_morph(&mut self, ctx: CtxId) -> MorphId {
+        self.record(Call::IdMorph(ctx), |m| m.id_morph(ctx), Ret::Morph)
+    }
+    fn compose(&mut self, g: MorphId, f: MorphId) -> MorphId {
+        self.record(Call::Compose(g, f), |m| m.compose(g, f), Ret::Morph)
+    }
+    fn extension(&mut self, morph: MorphId, ty: TyId, tm: TmId) -> MorphId {
+        self.record(Call::Extension(morph, ty, tm), |m| m.extension(morph, ty, tm), Ret::Morph)
+    }
+
+    fn subst_ty(&mut self, f: MorphId, ty: TyId) -> TyId {
+        self.record(Call::SubstTy(f, ty), |m| m.subst_ty(f, ty), Ret::Ty)
+    }
+    fn subst_tm(&mut self, f: MorphId, tm: TmId) -> TmId {
+        self.record(Call::SubstTm(f, tm), |m| m.subst_tm(f, tm), Ret::Tm)
+    }
+
+    fn eq_ty(&mut self, l: TmId, r: TmId) -> TyId {
+        self.record(Call::EqTy(l, r), |m| m.eq_ty(l, r), Ret::Ty)
+    }
+    fn refl(&mut self, tm: TmId) -> TmId {
+        self.record(Call::Refl(tm), |m| m.refl(tm), Ret::Tm)
+    }
+
+    fn bool_ty(&mut self, ctx: CtxId) -> TyId {
+        self.record(Call::BoolTy(ctx), |m| m.bool_ty(ctx), Ret::Ty)
+    }
+    fn true_tm(&mut self, ctx: CtxId) -> TmId {
+        self.record(Call::TrueTm(ctx), |m| m.true_tm(ctx), Ret::Tm)
+    }
+    fn false_tm(&mut self, ctx: CtxId) -> TmId {
+        self.record(Call::FalseTm(ctx), |m| m.false_tm(ctx), Ret::Tm)
+    }
+    fn elim_bool(&mut self, base_ctx: CtxId, into: TyId, true_case: TmId, false_case: TmId) -> TmId {
+        self.record(
+            Call::ElimBool(base_ctx, into, true_case, false_case),
+            |m| m.elim_bool(base_ctx, into, true_case, false_case),
+            Ret::Tm)
+    }
+
+    fn checkpoint(&mut self) {
+        self.record(Call::Checkpoint, |m| m.checkpoint(), |_| Ret::Unit)
+    }
+    fn rollback(&mut self) {
+        self.record(Call::Rollback, |m| m.rollback(), |_| Ret::Unit)
+    }
+    fn commit(&mut self) {
+        self.record(Call::Commit, |m| m.commit(), |_| Ret::Unit)
+    }
+
+    // Not recorded: the handles in the trace refer to the syntax before the
+    // collection, so a trace can only be replayed up to the first one.
+    fn collect_garbage(&mut self, roots: &Roots) -> Remap {
+        self.inner.collect_garbage(roots)
+    }
+}
+
+// A query whose answer during replay differs from the recorded one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub index: usize,
+    pub call: Call,
+    pub recorded: Ret,
+    pub replayed: Ret,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "call {}: {:?} was {:?} but is now {:?}",
+               self.index, self.call, self.recorded, self.replayed)
+    }
+}
+
+// Maps handles of the recorded model to handles of the replaying model.
+struct Handles<I>(HashMap<I, I>);
+
+impl<I: Copy + Eq + Hash + fmt::Debug> Handles<I> {
+    fn get(&self, id: I) -> I {
+        match self.0.get(&id) {
+            Some(&id) => id,
+            None => panic!("{:?} is used before it is created in the trace", id),
+        }
+    }
+
+    fn insert(&mut self, recorded: I, replayed: I) {
+        self.0.insert(recorded, replayed);
+    }
+}
+
+struct Replay {
+    ctxs: Handles<CtxId>,
+    morphs: Handles<MorphId>,
+    tys: Handles<TyId>,
+    tms: Handles<TmId>,
+}
+
+impl Replay {
+    fn judgement(&self, judgement: Judgement) -> Judgement {
+        match judgement {
+            Judgement::Prop(ty) => Judgement::Prop(self.tys.get(ty)),
+            Judgement::Contractible(ty) => Judgement::Contractible(self.tys.get(ty)),
+            Judgement::Decidable(ty) => Judgement::Decidable(self.tys.get(ty)),
+        }
+    }
+}
+
+// Performs the calls of trace against model and returns the queries that
+// were answered differently than when the trace was recorded.
+pub fn replay<M: Model>(trace: &[Event], model: &mut M) -> Vec<Divergence> {
+    let mut r = Replay {
+        ctxs: Handles(HashMap::new()),
+        morphs: Handles(HashMap::new()),
+        tys: Handles(HashMap::new()),
+        tms: Handles(HashMap::new()),
+    };
+    let mut divergences = vec![];
+
+    for (index, event) in trace.iter().enumerate() {
+        let ret = match event.call {
+            Call::CtxEq(a, b) => Ret::equality(model.ctx_eq(r.ctxs.get(a), r.ctxs.get(b))),
+            Call::MorphEq(a, b) => Ret::equality(model.morph_eq(r.morphs.get(a), r.morphs.get(b))),
+            Call::TyEq(a, b) => Ret::equality(model.ty_eq(r.tys.get(a), r.tys.get(b))),
+            Call::TmEq(a, b) => Ret::equality(model.tm_eq(r.tms.get(a), r.tms.get(b))),
+            Call::Holds(j) => Ret::answer(model.holds(r.judgement(j))),
+            Call::IsInconsistent(ctx) => Ret::answer(model.is_inconsistent(r.ctxs.get(ctx))),
+            Call::EmptyCtx => Ret::Ctx(model.empty_ctx()),
+            Call::Comprehension(ty) => Ret::Ctx(model.comprehension(r.tys.get(ty))),
+            Call::Weakening(ty) => Ret::Morph(model.weakening(r.tys.get(ty))),
+            Call::Var(ty) => Ret::Tm(model.var(r.tys.get(ty))),
+            Call::IdMorph(ctx) => Ret::Morph(model.id_morph(r.ctxs.get(ctx))),
+            Call::Compose(g, f) => Ret::Morph(model.compose(r.morphs.get(g), r.morphs.get(f))),
+            Call::Extension(f, ty, tm) =>
+                Ret::Morph(model.extension(r.morphs.get(f), r.tys.get(ty), r.tms.get(tm))),
+            Call::SubstTy(f, ty) => Ret::Ty(model.subst_ty(r.morphs.get(f), r.tys.get(ty))),
+            Call::SubstTm(f, tm) => Ret::Tm(model.subst_tm(r.morphs.get(f), r.tms.get(tm))),
+            Call::EqTy(a, b) => Ret::Ty(model.eq_ty(r.tms.get(a), r.tms.get(b))),
+            Call::Refl(tm) => Ret::Tm(model.refl(r.tms.get(tm))),
+            Call::BoolTy(ctx) => Ret::Ty(model.bool_ty(r.ctxs.get(ctx))),
+            Call::TrueTm(ctx) => Ret::Tm(model.true_tm(r.ctxs.get(ctx))),
+            Call::FalseTm(ctx) => Ret::Tm(model.false_tm(r.ctxs.get(ctx))),
+            Call::ElimBool(ctx, into, t, f) => Ret::Tm(model.elim_bool(
+                r.ctxs.get(ctx), r.tys.get(into), r.tms.get(t), r.tms.get(f))),
+            Call::Checkpoint => {
+                model.checkpoint();
+                Ret::Unit
+            },
+            Call::Rollback => {
+                model.rollback();
+                Ret::Unit
+            },
+            Call::Commit => {
+                model.commit();
+                Ret::Unit
+            },
+        };
+
+        match (event.ret, ret) {
+            (Ret::Bool(_), Ret::Bool(_)) | (Ret::Equality(_), Ret::Equality(_)) => {
+                if event.ret != ret {
+                    divergences.push(Divergence {
+                        index,
+                        call: event.call,
+                        recorded: event.ret,
+                        replayed: ret,
+                    });
+                }
+            },
+            // A query that either model gave up on does not diverge.
+            (Ret::GaveUp, other) | (other, Ret::GaveUp) if other.is_answer() => (),
+            (Ret::Ctx(recorded), Ret::Ctx(replayed)) => r.ctxs.insert(recorded, replayed),
+            (Ret::Morph(recorded), Ret::Morph(replayed)) => r.morphs.insert(recorded, replayed),
+            (Ret::Ty(recorded), Ret::Ty(replayed)) => r.tys.insert(recorded, replayed),
+            (Ret::Tm(recorded), Ret::Tm(replayed)) => r.tms.insert(recorded, replayed),
+            (Ret::Unit, Ret::Unit) => (),
+            (recorded, replayed) =>
+                panic!("call {} returned {:?} but {:?} was recorded", index, replayed, recorded),
+        }
+    }
+    divergences
+}
+
+#[cfg(all(test, feature = "phl"))]
+mod tests {
+    use crate::cwf_model::Cwf;
+    use crate::lang;
+    use crate::model::Model;
+    use crate::nbe_model::NbeModel;
+    use crate::type_checker::TypeChecker;
+    use super::*;
+
+    fn trace_def(code: &str) -> Vec<Event> {
+        let def = lang::parse_def(code).unwrap();
+        let mut tc = TypeChecker::new(TracingModel::new(Cwf::new()));
+        tc.check_def(&def).unwrap();
+        tc.model().trace().to_vec()
+    }
+
+    #[test]
+    fn records_calls() {
+        let trace = trace_def("def negb (b : bool) : bool :=
+            elim b into (_ : bool) : bool | => false | => true end.");
+        assert_eq!(trace[0].call, Call::EmptyCtx);
+        let mut model = TracingModel::new(Cwf::new());
+        let empty = model.empty_ctx();
+        let bool_ty = model.bool_ty(empty);
+        assert_eq!(model.ty_eq(bool_ty, bool_ty), Ok(Equality::Equal));
+        assert_eq!(model.trace()[1], Event {
+            call: Call::BoolTy(empty),
+            ret: Ret::Ty(bool_ty),
+            elapsed: model.trace()[1].elapsed,
+        });
+        assert_eq!(model.summary()["ty_eq"].0, 1);
+        assert!(trace.iter().any(|e| e.call.name() == "elim_bool"));
+    }
+
+    #[test]
+    fn replay_against_other_models() {
+        let trace = trace_def("def trans (a b c : bool) (p : a = b) (q : b = c) : a = c :=
+            refl a.");
+        assert_eq!(replay(&trace, &mut Cwf::new()), vec![]);
+        assert_eq!(replay(&trace, &mut NbeModel::new()), vec![]);
+
+        // Replaying into a traced model reproduces the trace.
+        let mut model = TracingModel::new(NbeModel::new());
+        replay(&trace, &mut model);
+        let calls: Vec<Call> = model.trace().iter().map(|e| e.call).collect();
+        let expected: Vec<Call> = trace.iter().map(|e| e.call).collect();
+        assert_eq!(calls.len(), expected.len());
+        assert_eq!(calls.iter().map(Call::name).collect::<Vec<_>>(),
+                   expected.iter().map(Call::name).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn replay_reports_divergence() {
+        let mut trace = trace_def("def id (b : bool) : bool := b.");
+        let (index, event) = trace.iter_mut()
+            .enumerate()
+            .find(|(_, e)| e.call.name() == "ty_eq")
+            .unwrap();
+        event.ret = Ret::Equality(Equality::Distinct);
+        let call = event.call;
+        assert_eq!(replay(&trace, &mut Cwf::new()), vec![Divergence {
+            index,
+            call,
+            recorded: Ret::Equality(Equality::Distinct),
+            replayed: Ret::Equality(Equality::Equal),
+        }]);
+    }
+}
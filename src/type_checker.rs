@@ -1,20 +1,53 @@
-use scopeguard::{guard, ScopeGuard};
-use super::model::Model;
+use std::collections::HashMap;
+use std::mem;
+use scopeguard::guard;
+use super::model::{Equality, Judgement, Model};
 use super::cwf::*;
 use super::lang::ast::*;
+use super::pretty::Printer;
 
 pub struct TypeChecker<T: Model> {
     model: T,
     ctxs : Vec<CtxInfo>,
+    globals: HashMap<String, Global>,
+    // names of the globals in the order they were defined
+    global_names: Vec<String>,
+    // reports of the holes in the def being checked
+    holes: Vec<String>,
+    // the innermost expression that failed to check
+    error_span: Option<Span>,
+    // the types of the checked expressions, if they are recorded
+    annotations: Option<Vec<(Span, String)>>,
 }
 
 struct CtxInfo {
-    syntax: Ctx,
+    syntax: CtxId,
     // morphism from previous (if any) context to current
-    weakening: Option<Morph>,
-    defs: Vec<(String, Tm, Ty)>,
+    weakening: Option<MorphId>,
+    defs: Vec<(String, TmId, TyId)>,
 }
 
+// A checked def. The type of each parameter lives in the context extended by
+// the parameters before it, ret_ty and body live in the context extended by
+// all parameters.
+#[derive(Clone)]
+struct Global {
+    params: Vec<Param>,
+    ret_ty: TyId,
+    body: TmId,
+}
+
+#[derive(Clone)]
+struct Param {
+    name: DefId,
+    binding: Binding,
+    ty: TyId,
+}
+
+// Arguments of an application of a global, with their types, as far as they
+// are known.
+type Solution = Vec<Option<(TmId, TyId)>>;
+
 impl<TModel: Model> TypeChecker<TModel> {
     pub fn new(mut model: TModel) -> TypeChecker<TModel> {
         let empty = model.empty_ctx();
@@ -25,34 +58,84 @@ impl<TModel: Model> TypeChecker<TModel> {
                 weakening: None,
                 defs: vec![]
             }],
+            globals: HashMap::new(),
+            global_names: vec![],
+            holes: vec![],
+            error_span: None,
+            annotations: None,
         }
     }
 
-    // Saves the current number of context extensions and definitions
-    // in the current context and returns a scopeguard that will restore
-    // to this state when it is dropped. The scope guard takes ownership
-    // of the TC.
-    fn save_ctx<'a>(&'a mut self) ->
-            ScopeGuard<&mut TypeChecker<TModel>, impl FnOnce(&'a mut TypeChecker<TModel>)>
+    pub fn model(&self) -> &TModel {
+        &self.model
+    }
+
+    // Makes the checker record the type of every term it checks, printed.
+    pub fn record_types(&mut self) {
+        self.annotations = Some(vec![]);
+    }
+
+    pub fn annotations(&self) -> &[(Span, String)] {
+        self.annotations.as_ref().map_or(&[], |annotations| &annotations[..])
+    }
+
+    // The span of the innermost expression that caused the last def to be
+    // rejected, if the error is due to an expression.
+    pub fn error_span(&self) -> Option<Span> {
+        self.error_span
+    }
+
+    fn annotate(&mut self, span: Span, ty: TyId) {
+        if self.annotations.is_none() {
+            return
+        }
+        let ty = self.printer().ty(ty);
+        if let Some(annotations) = &mut self.annotations {
+            annotations.push((span, ty));
+        }
+    }
+
+    fn spanned<T>(&mut self, span: Span, result: Result<T, String>) -> Result<T, String> {
+        if result.is_err() && self.error_span.is_none() {
+            self.error_span = Some(span);
+        }
+        result
+    }
+
+    // Runs f and restores the current number of context extensions and
+    // definitions in the current context afterwards, also if f panics. If f
+    // fails, the model is rolled back to where it was before f as well, so
+    // that failed checks leave nothing behind in it.
+    fn save_ctx<T, F>(&mut self, f: F) -> Result<T, String>
+        where F: FnOnce(&mut Self) -> Result<T, String>
     {
         let depth = self.ctxs.len();
         assert!(depth > 0); // always have empty context
         let num_defs = self.ctxs.last().unwrap().defs.len();
-        guard(self, move |s| {
-            s.ctxs.truncate(depth);
-            s.ctxs.last_mut().unwrap().defs.truncate(num_defs)
-        })
+        self.model.checkpoint();
+        let result = {
+            let mut s = guard(&mut *self, move |s| {
+                s.ctxs.truncate(depth);
+                s.ctxs.last_mut().unwrap().defs.truncate(num_defs)
+            });
+            f(&mut s)
+        };
+        match result {
+            Ok(_) => self.model.commit(),
+            Err(_) => self.model.rollback(),
+        }
+        result
     }
 
-    fn extend(&mut self, ext: &CtxExt) -> Result<Ty, String> {
+    fn extend(&mut self, ext: &CtxExt) -> Result<TyId, String> {
         let ty = self.check_ty(&ext.1)?;
-        let new_ctx = self.model.comprehension(&ty);
-        let weakening = self.model.weakening(&ty);
+        let new_ctx = self.model.comprehension(ty);
+        let weakening = self.model.weakening(ty);
         let mut defs = vec![];
 
         if let Some(ref name) = ext.0 {
-            let var_ty = Self::subst_ty(&mut self.model, &weakening, &ty);
-            defs.push((name.clone(), self.model.var(&ty), var_ty))
+            let var_ty = Self::subst_ty(&mut self.model, weakening, ty);
+            defs.push((name.clone(), self.model.var(ty), var_ty))
         }
         
         let new_ctx_info = CtxInfo {
@@ -65,31 +148,191 @@ impl<TModel: Model> TypeChecker<TModel> {
         Ok(ty)
     }
 
-    pub fn check_def(&mut self, def: &Def) -> Result<Tm, String> {
-        let mut s = self.save_ctx();
-        for ext in def.ctx.iter() {
-            s.extend(ext)?;
+    // Checks def and, if it is named, makes it available to the defs checked
+    // after it. A def with holes that could not be inferred is rejected with
+    // a report of the expected type and the context of each hole.
+    pub fn check_def(&mut self, def: &Def) -> Result<TmId, String> {
+        self.holes.clear();
+        self.error_span = None;
+        if let Some(ref name) = def.name {
+            if self.globals.contains_key(name) {
+                return Err(format!("{} is already defined", name))
+            }
         }
-        let ret_ty = s.check_ty(&def.ret_ty)?;
-        s.check_tm_ty(&def.body, &ret_ty)
+
+        let global = self.save_ctx(|s| {
+            let mut params = vec![];
+            for ext in def.ctx.iter() {
+                let ty = s.extend(ext)?;
+                params.push(Param { name: ext.0.clone(), binding: ext.2, ty });
+            }
+            let ret_ty = s.check_ty(&def.ret_ty)?;
+            let body = s.check_tm_ty(&def.body, ret_ty)?;
+            s.check_no_holes()?;
+            Ok(Global { params, ret_ty, body })
+        })?;
+
+        let body = global.body;
+        if let Some(ref name) = def.name {
+            self.globals.insert(name.clone(), global);
+            self.global_names.push(name.clone());
+        }
+        Ok(body)
+    }
+
+    // Infers the type of expr in the empty context and returns it printed.
+    pub fn infer_ty(&mut self, expr: &Expr) -> Result<String, String> {
+        self.query(|s| {
+            let (_, ty) = s.check_tm(expr)?;
+            s.check_no_holes()?;
+            Ok(s.printer().ty(ty))
+        })
+    }
+
+    // Decides whether a and b, which live in the empty context, are
+    // judgementally equal.
+    pub fn judgementally_eq(&mut self, a: &Expr, b: &Expr) -> Result<Equality, String> {
+        self.query(|s| {
+            let (a, a_ty) = s.check_tm(a)?;
+            let b = s.check_tm_ty(b, a_ty)?;
+            s.check_no_holes()?;
+            s.tm_eq(a, b)
+        })
+    }
+
+    // Decides whether judgement, e.g. Judgement::Prop, holds of the type
+    // expr in the empty context.
+    pub fn holds(&mut self, judgement: fn(TyId) -> Judgement, expr: &Expr) -> Result<bool, String> {
+        self.query(|s| {
+            let ty = s.check_ty(expr)?;
+            s.check_no_holes()?;
+            Ok(s.model.holds(judgement(ty))?)
+        })
+    }
+
+    // Runs f and rolls the model back afterwards, since nothing that f adds
+    // to it is referred to once the answer has been computed.
+    fn query<T, F>(&mut self, f: F) -> Result<T, String>
+        where F: FnOnce(&mut Self) -> Result<T, String>
+    {
+        self.holes.clear();
+        self.model.checkpoint();
+        let result = f(self);
+        self.model.rollback();
+        result
+    }
+
+    fn check_no_holes(&mut self) -> Result<(), String> {
+        if self.holes.is_empty() {
+            Ok(())
+        } else {
+            let holes = mem::take(&mut self.holes);
+            Err(format!("Unsolved holes:\n{}", holes.join("\n")))
+        }
+    }
+
+    // The signatures of the globals, in the order they were defined.
+    pub fn signatures(&self) -> Vec<String> {
+        self.global_names.iter().map(|name| {
+            let global = &self.globals[name];
+            let mut printer = self.printer();
+            let mut signature = name.clone();
+            for param in &global.params {
+                let param_name = param.name.as_ref().map_or("_", |name| name.as_str());
+                let ty = printer.ty(param.ty);
+                signature += &match param.binding {
+                    Binding::Explicit => format!(" ({} : {})", param_name, ty),
+                    Binding::Implicit => format!(" {{{} : {}}}", param_name, ty),
+                };
+                printer.name(param.ty, param_name);
+            }
+            signature + &format!(" : {}", printer.ty(global.ret_ty))
+        }).collect()
+    }
+
+    // Removes the global defined last and returns its name. The syntax it
+    // added to the model stays until the next collect_garbage, but can no
+    // longer be referred to.
+    pub fn undo_def(&mut self) -> Option<String> {
+        let name = self.global_names.pop()?;
+        self.globals.remove(&name);
+        Some(name)
+    }
+
+    // Lets the model forget the syntax that neither the globals nor the
+    // current context refer to.
+    pub fn collect_garbage(&mut self) {
+        let mut roots = Roots::default();
+        for ctx in &self.ctxs {
+            roots.ctxs.push(ctx.syntax);
+            roots.morphs.extend(ctx.weakening);
+            for &(_, tm, ty) in &ctx.defs {
+                roots.tms.push(tm);
+                roots.tys.push(ty);
+            }
+        }
+        for global in self.globals.values() {
+            roots.tys.extend(global.params.iter().map(|param| param.ty));
+            roots.tys.push(global.ret_ty);
+            roots.tms.push(global.body);
+        }
+
+        let remap = self.model.collect_garbage(&roots);
+        for ctx in &mut self.ctxs {
+            ctx.syntax = remap.ctx(ctx.syntax);
+            ctx.weakening = ctx.weakening.map(|weakening| remap.morph(weakening));
+            for (_, tm, ty) in &mut ctx.defs {
+                *tm = remap.tm(*tm);
+                *ty = remap.ty(*ty);
+            }
+        }
+        for global in self.globals.values_mut() {
+            for param in &mut global.params {
+                param.ty = remap.ty(param.ty);
+            }
+            global.ret_ty = remap.ty(global.ret_ty);
+            global.body = remap.tm(global.body);
+        }
+    }
+
+    // The cwf syntax of the globals.
+    pub fn dump(&self) -> String {
+        let syntax = self.model.syntax();
+        let mut dump = String::new();
+        for name in &self.global_names {
+            let global = &self.globals[name];
+            dump += &format!("{}\n", name);
+            for param in &global.params {
+                dump += &format!("  param {:?}\n", syntax.show(param.ty));
+            }
+            dump += &format!("  type {:?}\n  body {:?}\n",
+                             syntax.show(global.ret_ty), syntax.show(global.body));
+        }
+        dump
     }
 
     fn check_let<T, F>(
         &mut self, check_body: F,
-        name: &DefId, ty: &Expr, val: &Expr, body: &Expr) -> Result<T, String>
+        name: &DefId, ty: &Option<Box<Expr>>, val: &Expr, body: &Expr) -> Result<T, String>
             where F : FnOnce(&mut Self, &Expr) -> Result<T, String>
     {
-        let mut s = self.save_ctx();
-        let ty = s.check_ty(ty)?;
-        let val = s.check_tm_ty(val, &ty)?;
-        if let Some(name) = name {
-            s.ctxs.last_mut().unwrap().defs.push((name.clone(), val, ty));
-        };
-        check_body(&mut s, body)
+        self.save_ctx(|s| {
+            let (val, ty) = match ty {
+                Some(ty) => {
+                    let ty = s.check_ty(ty)?;
+                    (s.check_tm_ty(val, ty)?, ty)
+                },
+                None => s.check_tm(val)?,
+            };
+            if let Some(name) = name {
+                s.ctxs.last_mut().unwrap().defs.push((name.clone(), val, ty));
+            };
+            check_body(s, body)
+        })
     }
 
-    pub fn check_ty(&mut self, expr: &Expr) -> Result<Ty, String> {
-        let cur_ctx_syn = &self.ctxs.last().unwrap().syntax;
+    pub fn check_ty(&mut self, expr: &Expr) -> Result<TyId, String> {
+        let cur_ctx_syn = self.ctxs.last().unwrap().syntax;
         match expr {
             Expr::App(id, v) =>
                 match (id.as_str(), &v[..]) {
@@ -97,150 +340,276 @@ impl<TModel: Model> TypeChecker<TModel> {
                     ("eq", [a, b]) => self.check_eq(a, b),
                     (s, v) => Err(format!("Unexpected {} with {} args", s, v.len()))
                 },
-            Expr::Let { name, ty, val, body } =>
-                self.check_let(|s, body| s.check_ty(body), name, &*ty, &*val, &*body),
+            Expr::Let { name, ty, val, body, .. } =>
+                self.check_let(|s, body| s.check_ty(body), name, ty, &*val, &*body),
+            Expr::Spanned(span, expr) => {
+                let result = self.check_ty(expr);
+                self.spanned(*span, result)
+            },
             _ => Err(format!("Unhandled type {:?}", expr))
         }
     }
 
-    pub fn check_tm(&mut self, expr: &Expr) -> Result<(Tm, Ty), String> {
+    // Infers the type of expr.
+    pub fn check_tm(&mut self, expr: &Expr) -> Result<(TmId, TyId), String> {
         match expr {
             Expr::App(id, v) =>
                 match (id.as_str(), &v[..]) {
                     ("refl", [a]) => self.refl(&*a),
                     ("true", []) => Ok(self.true_tm()),
                     ("false", []) => Ok(self.false_tm()),
+                    (name, args) if self.is_global_app(name, args) =>
+                        self.apply_global(name, args, None),
                     (v, []) => self.access_var(v),
                     (s, v) => Err(format!("Unexpected {} with {} args", s, v.len()))
                 },
-            Expr::Let { name, ty, val, body } =>
-                self.check_let(|s, body| s.check_tm(body), name, &*ty, &*val, &*body),
+            Expr::Hole(None) => Err("Cannot infer _ here".to_owned()),
+            Expr::Hole(Some(name)) => Err(format!("Cannot infer the type of ?{}", name)),
+            Expr::Let { name, ty, val, body, .. } =>
+                self.check_let(|s, body| s.check_tm(body), name, ty, &*val, &*body),
             Expr::Elim { val, into_ctx, into_ty, cases } =>
-                self.check_elim(&*val, into_ctx, &*into_ty, cases),
+                self.check_elim(&*val, into_ctx, into_ty, cases, None),
+            Expr::Spanned(span, expr) => {
+                let result = self.check_tm(expr);
+                if let Ok((_, ty)) = result {
+                    self.annotate(*span, ty);
+                }
+                self.spanned(*span, result)
+            },
         }
     }
 
-    fn refl(&mut self, expr: &Expr) -> Result<(Tm, Ty), String> {
+    fn refl(&mut self, expr: &Expr) -> Result<(TmId, TyId), String> {
         let (tm, _) = self.check_tm(expr)?;
-        let eq_ty = self.model.eq_ty(&tm, &tm);
-        let refl_tm = self.model.refl(&tm);
+        let eq_ty = self.model.eq_ty(tm, tm);
+        let refl_tm = self.model.refl(tm);
         Ok((refl_tm, eq_ty))
     }
 
-    fn true_tm(&mut self) -> (Tm, Ty) {
-        let cur_ctx_syn = &self.ctxs.last().unwrap().syntax;
+    // Checks refl expr against an expected equality a = b: expr is checked
+    // against the type of a, and may be _ to stand for a.
+    fn check_refl(&mut self, expr: &Expr, expected_ty: TyId) -> Result<(TmId, TyId), String> {
+        let tm = match (self.model.syntax().ty(expected_ty), expr.unspanned()) {
+            (Ty::Eq(a, _), Expr::Hole(_)) => a,
+            (Ty::Eq(a, _), _) => {
+                let a_ty = Self::tm_ty(&mut self.model, a);
+                self.check_tm_ty(expr, a_ty)?
+            },
+            _ => return self.refl(expr),
+        };
+        let eq_ty = self.model.eq_ty(tm, tm);
+        let refl_tm = self.model.refl(tm);
+        Ok((refl_tm, eq_ty))
+    }
+
+    fn true_tm(&mut self) -> (TmId, TyId) {
+        let cur_ctx_syn = self.ctxs.last().unwrap().syntax;
         let bool_ty = self.model.bool_ty(cur_ctx_syn);
         let tm = self.model.true_tm(cur_ctx_syn);
         (tm, bool_ty)
     }
 
-    fn false_tm(&mut self) -> (Tm, Ty) {
-        let cur_ctx_syn = &self.ctxs.last().unwrap().syntax;
+    fn false_tm(&mut self) -> (TmId, TyId) {
+        let cur_ctx_syn = self.ctxs.last().unwrap().syntax;
         let bool_ty = self.model.bool_ty(cur_ctx_syn);
         let tm = self.model.false_tm(cur_ctx_syn);
         (tm, bool_ty)
     }
 
+    // Compares types of the current context. If the model can not decide
+    // their equality but knows the context to be inconsistent, they are
+    // equal ex falso. Distinct answers are kept: bool and equality types
+    // differ even in an inconsistent context.
+    fn ty_eq(&mut self, l: TyId, r: TyId) -> Result<Equality, String> {
+        let equality = self.model.ty_eq(l, r)?;
+        self.ex_falso(equality)
+    }
+
+    // Terms the model can not compare are also equal if their type is a
+    // proposition.
+    fn tm_eq(&mut self, l: TmId, r: TmId) -> Result<Equality, String> {
+        let equality = self.model.tm_eq(l, r)?;
+        if equality == Equality::Unknown {
+            let ty = Self::tm_ty(&mut self.model, l);
+            if self.model.holds(Judgement::Prop(ty))? {
+                return Ok(Equality::Equal)
+            }
+        }
+        self.ex_falso(equality)
+    }
+
+    fn ex_falso(&mut self, equality: Equality) -> Result<Equality, String> {
+        let ctx = self.ctxs.last().unwrap().syntax;
+        if equality == Equality::Unknown && self.model.is_inconsistent(ctx)? {
+            Ok(Equality::Equal)
+        } else {
+            Ok(equality)
+        }
+    }
+
     // Given G |- a : A, construct the morphism <1(G), A, a> : G.A -> G
     // substituting the last A for a in any term in G.A.
-    fn bar_tm(model: &mut TModel, ctx: &Ctx, ty: &Ty, tm: &Tm) -> Morph {
+    fn bar_tm(model: &mut TModel, ctx: CtxId, ty: TyId, tm: TmId) -> MorphId {
         let id = model.id_morph(ctx);
-        model.extension(&id, ty, tm)
+        model.extension(id, ty, tm)
     }
 
+    // Without an into clause, the motive is the expected type or, if there is
+    // none, the type of the first case; in both cases it does not depend on
+    // the eliminated value.
     fn check_elim(
         &mut self,
-        val: &Expr, into_ctx: &Vec<CtxExt>, into_ty: &Expr,
-        cases: &Vec<ElimCase>) -> Result<(Tm, Ty), String>
+        val: &Expr, into_ctx: &Vec<CtxExt>, into_ty: &Option<Box<Expr>>,
+        cases: &Vec<ElimCase>, expected_ty: Option<TyId>) -> Result<(TmId, TyId), String>
     {
         let (val_tm, val_ty) = self.check_tm(val)?;
-        let bool_ty = self.model.bool_ty(&self.ctxs.last().unwrap().syntax);
+        let bool_ty = self.model.bool_ty(self.ctxs.last().unwrap().syntax);
 
+        let equality = self.ty_eq(val_ty, bool_ty)?;
         let (elim_tm, elim_ty) =
-            if self.model.ty_eq(&val_ty, &bool_ty) {
-                self.elim_bool(into_ctx, into_ty, cases)?
+            if equality == Equality::Equal {
+                let (motive, true_case) = match (into_ty, expected_ty) {
+                    (Some(into_ty), _) => (self.bool_motive(into_ctx, into_ty)?, None),
+                    (None, Some(expected_ty)) => (self.constant_motive(expected_ty), None),
+                    (None, None) => {
+                        let case = match cases.first() {
+                            Some(case) => &case.1,
+                            None => return Err("Invalid bool elimination".to_owned()),
+                        };
+                        let (case_tm, case_ty) = self.check_tm(case)?;
+                        (self.constant_motive(case_ty), Some(case_tm))
+                    },
+                };
+                self.elim_bool(motive, cases, true_case)?
             } else {
-                return Err(format!("Cannot eliminate {:?} of type {:?}", val, val_ty))
+                return Err(not_equal(equality, format!("Cannot eliminate {:?} of type {:?}",
+                                                       val, self.model.syntax().show(val_ty))))
             };
         
         // Substitute bar(val_tm) into elimination term and type, which live
         // live in an extended context.
-        let cur_ctx_syn = &self.ctxs.last().unwrap().syntax;
-        let bar = Self::bar_tm(&mut self.model, cur_ctx_syn, &val_ty, &val_tm);
-        let tm = self.model.subst_tm(&bar, &elim_tm);
-        let ty = self.model.subst_ty(&bar, &elim_ty);
+        let cur_ctx_syn = self.ctxs.last().unwrap().syntax;
+        let bar = Self::bar_tm(&mut self.model, cur_ctx_syn, val_ty, val_tm);
+        let tm = Self::subst_tm(&mut self.model, bar, elim_tm);
+        let ty = Self::subst_ty(&mut self.model, bar, elim_ty);
         Ok((tm, ty))
     }
 
+    // Checks the motive of a bool elimination, which lives in the current
+    // context extended by bool.
+    fn bool_motive(&mut self, into_ctx: &Vec<CtxExt>, into_ty: &Expr) -> Result<TyId, String> {
+        if into_ctx.len() != 1 {
+            return Err("Invalid bool elimination".to_owned())
+        }
+
+        let bool_ty = self.model.bool_ty(self.ctxs.last().unwrap().syntax);
+        self.save_ctx(|s| {
+            let ext_ty = s.extend(&into_ctx[0])?;
+            let equality = s.ty_eq(ext_ty, bool_ty)?;
+            if equality != Equality::Equal {
+                return Err(not_equal(
+                    equality, "Invalid extension for into-type: expected bool".to_owned()));
+            }
+            s.check_ty(into_ty)
+        })
+    }
+
+    // The motive that is ty for both cases, i.e. ty weakened by bool.
+    fn constant_motive(&mut self, ty: TyId) -> TyId {
+        let bool_ty = self.model.bool_ty(self.ctxs.last().unwrap().syntax);
+        let weakening = self.model.weakening(bool_ty);
+        Self::subst_ty(&mut self.model, weakening, ty)
+    }
+
+    // true_case is the term of the first case if it was already checked to
+    // have the type of into_ty at true.
     fn elim_bool(
         &mut self,
-        into_ctx: &Vec<CtxExt>, into_ty: &Expr,
-        cases: &Vec<ElimCase>) -> Result<(Tm, Ty), String>
+        into_ty: TyId, cases: &Vec<ElimCase>,
+        true_case: Option<TmId>) -> Result<(TmId, TyId), String>
     {
-        if into_ctx.len() != 1 || cases.len() != 2 ||
-           cases[0].0.len() != 0 || cases[1].0.len() != 0
-        {
+        if cases.len() != 2 || cases[0].0.len() != 0 || cases[1].0.len() != 0 {
             return Err("Invalid bool elimination".to_owned())
         }
 
-        let cur_ctx_syn = self.ctxs.last().unwrap().syntax.clone();
-        let bool_ty = self.model.bool_ty(&cur_ctx_syn);
-        let into_ty = {
-            let mut s = self.save_ctx();
-            let ext_ty = s.extend(&into_ctx[0])?;
-            if !s.model.ty_eq(&ext_ty, &bool_ty) {
-                return Err("Invalid extension for into-type: expected bool".to_owned());
-            }
-            
-            s.check_ty(into_ty)?
-        };
+        let cur_ctx_syn = self.ctxs.last().unwrap().syntax;
+        let bool_ty = self.model.bool_ty(cur_ctx_syn);
 
-        let true_tm = self.model.true_tm(&cur_ctx_syn);
-        let true_bar = Self::bar_tm(&mut self.model, &cur_ctx_syn, &bool_ty, &true_tm);
-        let expected_ty_true_case = Self::subst_ty(&mut self.model, &true_bar, &into_ty);
+        let true_tm = self.model.true_tm(cur_ctx_syn);
+        let true_bar = Self::bar_tm(&mut self.model, cur_ctx_syn, bool_ty, true_tm);
+        let expected_ty_true_case = Self::subst_ty(&mut self.model, true_bar, into_ty);
 
-        let false_tm = self.model.false_tm(&cur_ctx_syn);
-        let false_bar = Self::bar_tm(&mut self.model, &cur_ctx_syn, &bool_ty, &false_tm);
-        let expected_ty_false_case = Self::subst_ty(&mut self.model, &false_bar, &into_ty);
+        let false_tm = self.model.false_tm(cur_ctx_syn);
+        let false_bar = Self::bar_tm(&mut self.model, cur_ctx_syn, bool_ty, false_tm);
+        let expected_ty_false_case = Self::subst_ty(&mut self.model, false_bar, into_ty);
 
-        let true_case_tm = self.check_tm_ty(&cases[0].1, &expected_ty_true_case)?;
-        let false_case_tm = self.check_tm_ty(&cases[1].1, &expected_ty_false_case)?;
+        let true_case_tm = match true_case {
+            Some(tm) => tm,
+            None => self.check_tm_ty(&cases[0].1, expected_ty_true_case)?,
+        };
+        let false_case_tm = self.check_tm_ty(&cases[1].1, expected_ty_false_case)?;
 
-        let tm = self.model.elim_bool(&cur_ctx_syn, &into_ty, &true_case_tm, &false_case_tm);
+        let tm = self.model.elim_bool(cur_ctx_syn, into_ty, true_case_tm, false_case_tm);
         // Define substitutions by true and false
-        Self::subst_tm(&mut self.model, &true_bar, &tm);
-        Self::subst_tm(&mut self.model, &false_bar, &tm);
+        Self::subst_tm(&mut self.model, true_bar, tm);
+        Self::subst_tm(&mut self.model, false_bar, tm);
         Ok((tm, into_ty))
     }
 
-    fn check_tm_ty(&mut self, expr: &Expr, expected_ty: &Ty) -> Result<Tm, String> {
-        let (tm, ty) = self.check_tm(expr)?;
-        if self.model.ty_eq(&ty, expected_ty) {
+    // Checks expr against expected_ty. The expected type is pushed into the
+    // forms that can use it, the type of other terms is inferred and then
+    // compared.
+    fn check_tm_ty(&mut self, expr: &Expr, expected_ty: TyId) -> Result<TmId, String> {
+        let (tm, ty) = match expr {
+            Expr::Spanned(span, expr) => {
+                let result = self.check_tm_ty(expr, expected_ty);
+                if result.is_ok() {
+                    self.annotate(*span, expected_ty);
+                }
+                return self.spanned(*span, result)
+            },
+            Expr::Hole(name) => self.hole(name, expected_ty),
+            Expr::App(name, args) if name == "refl" && args.len() == 1 =>
+                self.check_refl(&args[0], expected_ty)?,
+            // The expected type may determine implicit arguments.
+            Expr::App(name, args) if self.is_global_app(name, args) =>
+                self.apply_global(name, args, Some(expected_ty))?,
+            Expr::Let { name, ty, val, body, .. } =>
+                return self.check_let(
+                    |s, body| s.check_tm_ty(body, expected_ty), name, ty, &*val, &*body),
+            Expr::Elim { val, into_ctx, into_ty, cases } =>
+                self.check_elim(&*val, into_ctx, into_ty, cases, Some(expected_ty))?,
+            _ => self.check_tm(expr)?,
+        };
+        let equality = self.ty_eq(ty, expected_ty)?;
+        if equality == Equality::Equal {
             Ok(tm)
         } else {
-            Err(format!("expected:\n{:?}\ngot:\n{:?}", expected_ty, ty))
+            let syntax = self.model.syntax();
+            Err(not_equal(equality, format!("expected:\n{:?}\ngot:\n{:?}",
+                                            syntax.show(expected_ty), syntax.show(ty))))
         }
     }
 
-    fn access_var(&mut self, name: &str) -> Result<(Tm, Ty), String> {
+    fn access_var(&mut self, name: &str) -> Result<(TmId, TyId), String> {
         let mut ctx_index = self.ctxs.len();
         for ctx in self.ctxs.iter().rev() {
             ctx_index -= 1;
-            for (ref ctx_var_name, ref tm, ref ty) in ctx.defs.iter().rev() {
+            for &(ref ctx_var_name, tm, ty) in ctx.defs.iter().rev() {
                 if ctx_var_name != name {
                     continue
                 }
 
-                let mut tm = tm.clone();
-                let mut ty = ty.clone();
+                let mut tm = tm;
+                let mut ty = ty;
                 // Found term, inject it into current context.
                 for ctx in &self.ctxs[ctx_index+1..] {
                     let weakening = match ctx.weakening {
-                        Some(ref w) => w,
+                        Some(w) => w,
                         None => panic!("expected weakening to be available")
                     };
-                    tm = Self::subst_tm(&mut self.model, &weakening, &tm);
-                    ty = Self::subst_ty(&mut self.model, &weakening, &ty);
+                    tm = Self::subst_tm(&mut self.model, weakening, tm);
+                    ty = Self::subst_ty(&mut self.model, weakening, ty);
                 }
 
                 return Ok((tm, ty))
@@ -250,105 +619,352 @@ impl<TModel: Model> TypeChecker<TModel> {
         Err(format!("unknown definition {}", name))
     }
 
-    fn check_eq(&mut self, a: &Expr, b: &Expr) -> Result<Ty, String> {
+    // A printer that knows the names of the variables in scope.
+    fn printer(&self) -> Printer<'_> {
+        let syntax = self.model.syntax();
+        let mut printer = Printer::new(syntax);
+        for ctx in &self.ctxs {
+            for &(ref name, tm, _) in &ctx.defs {
+                if let Tm::Var(ty) = syntax.tm(tm) {
+                    printer.name(ty, name);
+                }
+            }
+        }
+        printer
+    }
+
+    // Records a hole of type ty, which is reported at the end of check_def,
+    // and returns a placeholder term for it so that checking can continue.
+    fn hole(&mut self, name: &Option<Id>, ty: TyId) -> (TmId, TyId) {
+        let report = {
+            let syntax = self.model.syntax();
+            let printer = self.printer();
+            let name = name.as_ref().map_or("_".to_owned(), |name| format!("?{}", name));
+            let mut report = format!("{} : {}", name, printer.ty(ty));
+            for ctx in &self.ctxs {
+                for &(ref name, tm, ty) in &ctx.defs {
+                    report += &match syntax.tm(tm) {
+                        Tm::Var(_) => format!("\n  {} : {}", name, printer.ty(ty)),
+                        _ => format!("\n  {} : {} := {}", name, printer.ty(ty), printer.tm(tm)),
+                    };
+                }
+            }
+            report
+        };
+        self.holes.push(report);
+        (Self::placeholder(&mut self.model, ty), ty)
+    }
+
+    // Returns some term for a hole of type ty. It need not have type ty,
+    // since a def with holes is rejected anyway.
+    fn placeholder(model: &mut TModel, ty: TyId) -> TmId {
+        match model.syntax().ty(ty) {
+            Ty::Subst(f, ty) => {
+                let tm = Self::placeholder(model, ty);
+                Self::subst_tm(model, f, tm)
+            },
+            Ty::Bool(ctx) => model.true_tm(ctx),
+            Ty::Eq(a, _) => model.refl(a),
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.ctxs.iter().any(|ctx| ctx.defs.iter().any(|def| def.0 == name))
+    }
+
+    // Local definitions shadow globals.
+    fn is_global_app(&self, name: &str, args: &[Expr]) -> bool {
+        self.globals.contains_key(name) && !(args.is_empty() && self.is_local(name))
+    }
+
+    // The morphism from the empty context to the current context, along
+    // which the globals are moved into the current context.
+    fn terminal_morph(&mut self) -> MorphId {
+        let mut morph = self.model.id_morph(self.ctxs[0].syntax);
+        for ctx in &self.ctxs[1..] {
+            let weakening = ctx.weakening.expect("expected weakening to be available");
+            morph = Self::comp_morphs(&mut self.model, weakening, morph);
+        }
+        morph
+    }
+
+    // Applies a global to the explicit args by substituting the arguments
+    // for its parameters. Implicit arguments and _ holes are solved by
+    // matching the types of the parameters against the types of the
+    // explicit arguments, and the type of the global against the expected
+    // type if there is one.
+    fn apply_global(
+        &mut self, name: &str, args: &[Expr],
+        expected_ty: Option<TyId>) -> Result<(TmId, TyId), String>
+    {
+        let global = self.globals[name].clone();
+        let explicit = global.params.iter().filter(|p| p.binding == Binding::Explicit).count();
+        if args.len() != explicit {
+            return Err(format!("{} expects {} explicit arguments but got {}",
+                               name, explicit, args.len()))
+        }
+
+        let mut solution: Solution = vec![None; global.params.len()];
+        let mut given = vec![None; global.params.len()];
+        let mut args = args.iter();
+        for (i, param) in global.params.iter().enumerate() {
+            if param.binding == Binding::Implicit {
+                continue
+            }
+            given[i] = args.next().map(Expr::unspanned);
+            match given[i].unwrap() {
+                Expr::Hole(_) => (),
+                arg => {
+                    let (tm, ty) = self.check_tm(arg)?;
+                    solution[i] = Some((tm, ty));
+                    self.match_ty(&global.params, param.ty, ty, &mut solution)?;
+                }
+            }
+        }
+        if let Some(expected_ty) = expected_ty {
+            self.match_ty(&global.params, global.ret_ty, expected_ty, &mut solution)?;
+        }
+
+        let mut morph = self.terminal_morph();
+        for (i, param) in global.params.iter().enumerate() {
+            let param_name = param.name.clone().unwrap_or_else(|| format!("#{}", i + 1));
+            let expected_ty = Self::subst_ty(&mut self.model, morph, param.ty);
+            let (tm, ty) = match (solution[i], given[i]) {
+                (Some(arg), _) => arg,
+                (None, Some(Expr::Hole(hole))) => self.hole(hole, expected_ty),
+                (None, _) => return Err(format!("Cannot infer argument {} of {}", param_name, name)),
+            };
+            let equality = self.ty_eq(ty, expected_ty)?;
+            if equality != Equality::Equal {
+                let syntax = self.model.syntax();
+                return Err(not_equal(equality, format!("argument {} of {}: expected:\n{:?}\ngot:\n{:?}",
+                                                       param_name, name,
+                                                       syntax.show(expected_ty), syntax.show(ty))))
+            }
+            morph = self.model.extension(morph, param.ty, tm);
+        }
+
+        let tm = Self::subst_tm(&mut self.model, morph, global.body);
+        let ty = Self::subst_ty(&mut self.model, morph, global.ret_ty);
+        Ok((tm, ty))
+    }
+
+    // Matches pattern, which lives in the context of params, against ty,
+    // which lives in the current context, and records the arguments that
+    // are determined by this in solution. This only solves for variables that
+    // occur in the pattern in positions where ty has the same shape; the
+    // arguments are checked against the parameter types afterwards.
+    fn match_ty(
+        &mut self, params: &[Param],
+        pattern: TyId, ty: TyId, solution: &mut Solution) -> Result<(), String>
+    {
+        let syntax = self.model.syntax();
+        match (syntax.ty(pattern), syntax.ty(ty)) {
+            (Ty::Eq(pattern_a, pattern_b), Ty::Eq(a, b)) => {
+                self.match_tm(params, pattern_a, a, solution)?;
+                self.match_tm(params, pattern_b, b, solution)
+            },
+            _ => Ok(())
+        }
+    }
+
+    fn match_tm(
+        &mut self, params: &[Param],
+        pattern: TmId, tm: TmId, solution: &mut Solution) -> Result<(), String>
+    {
+        if let Some(i) = Self::param_index(self.model.syntax(), params, pattern) {
+            return match solution[i] {
+                None => {
+                    let ty = Self::tm_ty(&mut self.model, tm);
+                    solution[i] = Some((tm, ty));
+                    Ok(())
+                },
+                Some((solved, _)) => match self.tm_eq(solved, tm)? {
+                    Equality::Equal => Ok(()),
+                    equality => {
+                        let syntax = self.model.syntax();
+                        Err(not_equal(equality, format!("Cannot unify\n{:?}\nwith\n{:?}",
+                                                        syntax.show(solved), syntax.show(tm))))
+                    },
+                },
+            }
+        }
+
+        let syntax = self.model.syntax();
+        match (syntax.tm(pattern), syntax.tm(tm)) {
+            (Tm::Refl(pattern_a), Tm::Refl(a)) => self.match_tm(params, pattern_a, a, solution),
+            _ => Ok(())
+        }
+    }
+
+    // Returns i if tm is the variable of params[i], possibly weakened.
+    fn param_index(syntax: &Syntax, params: &[Param], tm: TmId) -> Option<usize> {
+        match syntax.tm(tm) {
+            Tm::Subst(f, tm) if Self::is_weakening(syntax, f) =>
+                Self::param_index(syntax, params, tm),
+            Tm::Var(ty) => params.iter().position(|param| param.ty == ty),
+            _ => None
+        }
+    }
+
+    fn is_weakening(syntax: &Syntax, morph: MorphId) -> bool {
+        match syntax.morph(morph) {
+            Morph::Weakening(_) => true,
+            Morph::Composition(g, f) => Self::is_weakening(syntax, g) && Self::is_weakening(syntax, f),
+            _ => false
+        }
+    }
+
+    // The type of a term, read off its syntax.
+    fn tm_ty(model: &mut TModel, tm: TmId) -> TyId {
+        match model.syntax().tm(tm) {
+            Tm::Subst(f, tm) => {
+                let ty = Self::tm_ty(model, tm);
+                Self::subst_ty(model, f, ty)
+            },
+            Tm::Var(ty) => {
+                let weakening = model.weakening(ty);
+                Self::subst_ty(model, weakening, ty)
+            },
+            Tm::Refl(a) => model.eq_ty(a, a),
+            Tm::True(ctx) | Tm::False(ctx) => model.bool_ty(ctx),
+            // The elimination lives in the context extended by bool.
+            Tm::ElimBool(_, into_ty, _, _) => into_ty,
+        }
+    }
+
+    fn check_eq(&mut self, a: &Expr, b: &Expr) -> Result<TyId, String> {
         let (tma, tya) = self.check_tm(a)?;
-        let tmb = self.check_tm_ty(b, &tya)?;
-        Ok(self.model.eq_ty(&tma, &tmb))
+        let tmb = self.check_tm_ty(b, tya)?;
+        Ok(self.model.eq_ty(tma, tmb))
     }
 
-    fn subst_ty(model: &mut TModel, g: &Morph, ty: &Ty) -> Ty {
+    fn subst_ty(model: &mut TModel, g: MorphId, ty: TyId) -> TyId {
         model.subst_ty(g, ty);
 
-        match ty {
+        match model.syntax().ty(ty) {
             Ty::Subst(f, s) => {
                 // g (f s) = (g . f) s
-                let gf = Self::comp_morphs(model, g, &*f);
-                Self::subst_ty(model, &gf, &*s)
+                let gf = Self::comp_morphs(model, g, f);
+                Self::subst_ty(model, gf, s)
             },
             Ty::Bool(_) => {
                 let codomain = Self::morph_codomain(model, g);
-                model.bool_ty(&codomain)
+                model.bool_ty(codomain)
             },
             Ty::Eq(a, b) => {
-                let ga = Self::subst_tm(model, g, &*a);
-                let gb = Self::subst_tm(model, g, &*b);
+                let ga = Self::subst_tm(model, g, a);
+                let gb = Self::subst_tm(model, g, b);
 
-                model.eq_ty(&ga, &gb)
+                model.eq_ty(ga, gb)
             },
         }
     }
 
-    fn subst_tm(model: &mut TModel, g: &Morph, tm: &Tm) -> Tm {
+    fn subst_tm(model: &mut TModel, g: MorphId, tm: TmId) -> TmId {
         let gtm = model.subst_tm(g, tm);
-        match tm {
+        match model.syntax().tm(tm) {
             Tm::Subst(f, tm) => {
                 // g (f tm) = (g . f) tm
-                let gf = Self::comp_morphs(model, g, &*f);
-                Self::subst_tm(model, &gf, &*tm)
+                let gf = Self::comp_morphs(model, g, f);
+                Self::subst_tm(model, gf, tm)
             },
             Tm::Refl(a) => {
-                let ga = Self::subst_tm(model, g, &*a);
-                model.refl(&ga)
+                let ga = Self::subst_tm(model, g, a);
+                model.refl(ga)
             },
             Tm::True(_) => {
                 let codomain = Self::morph_codomain(model, g);
-                model.true_tm(&codomain)
+                model.true_tm(codomain)
             },
             Tm::False(_) => {
                 let codomain = Self::morph_codomain(model, g);
-                model.false_tm(&codomain)
+                model.false_tm(codomain)
             },
             Tm::ElimBool(ctx, into_ty, true_case, false_case) => {
                 let ctx_bool = model.bool_ty(ctx);
-                let w = model.weakening(&ctx_bool);
+                let w = model.weakening(ctx_bool);
                 // w : ctx -> ctx.bool (where into_ty, true_case and false_case) live
-                let gw = Self::comp_morphs(model, g, &w);
-                Self::subst_ty(model, &gw, &*into_ty);
-                Self::subst_tm(model, &gw, &*true_case);
-                Self::subst_tm(model, &gw, &*false_case);
+                let gw = Self::comp_morphs(model, g, w);
+                Self::subst_ty(model, gw, into_ty);
+                Self::subst_tm(model, gw, true_case);
+                Self::subst_tm(model, gw, false_case);
                 gtm
             },
             _ => gtm
         }
     }
 
-    fn comp_morphs(model: &mut TModel, g: &Morph, f: &Morph) -> Morph {
+    fn comp_morphs(model: &mut TModel, g: MorphId, f: MorphId) -> MorphId {
         let gf = model.compose(g, f);
-        match f {
-            Morph::Identity(_) => g.clone(),
+        match model.syntax().morph(f) {
+            Morph::Identity(_) => g,
             Morph::Composition(f, e) => {
                 // g . (f . e) = (g . f) . e
-                let gf = model.compose(g, &*f);
-                Self::comp_morphs(model, &gf, e)
+                let gf = model.compose(g, f);
+                Self::comp_morphs(model, gf, e)
             },
             Morph::Extension(f, s, tm) => {
                 // g . <f, s, tm> = <g . f, s, gtm>
                 let gf = Self::comp_morphs(model, g, f);
                 let gtm = model.subst_tm(g, tm);
-                model.extension(&gf, &*s, &gtm)
+                model.extension(gf, s, gtm)
             }
             _ => gf
         }
     }
 
-    fn morph_codomain(model: &mut TModel, morph: &Morph) -> Ctx {
-        match morph {
-            Morph::Identity(ctx) => (**ctx).clone(),
-            Morph::Weakening(ty) => model.comprehension(&*ty),
+    fn morph_codomain(model: &mut TModel, morph: MorphId) -> CtxId {
+        match model.syntax().morph(morph) {
+            Morph::Identity(ctx) => ctx,
+            Morph::Weakening(ty) => model.comprehension(ty),
             Morph::Composition(g, _) => Self::morph_codomain(model, g),
             Morph::Extension(f, _, _) => Self::morph_codomain(model, f),
         }
     }
 }
 
-#[cfg(test)]
+// Turns the message of a failed equality check into an error. Distinct
+// objects are a definite type error, whereas an unknown equality only means
+// that the model was unable to decide it.
+fn not_equal(equality: Equality, msg: String) -> String {
+    match equality {
+        Equality::Unknown => format!("{}\nThe model could not decide whether these are equal", msg),
+        _ => msg,
+    }
+}
+
+#[cfg(all(test, feature = "phl"))]
 mod tests {
     use crate::cwf_model;
-    use crate::lang::parser::DefParser;
+    use crate::def_gen::DefGen;
+    use crate::nbe_model;
+    use crate::lang;
+    use crate::model::Equality;
     fn verify_def(code: &str) {
-        let p = DefParser::new().parse(code).unwrap();
+        let p = lang::parse_def(code).unwrap();
         let model = cwf_model::Cwf::new();
         super::TypeChecker::new(model).check_def(&p).unwrap();
+        let model = nbe_model::NbeModel::new();
+        super::TypeChecker::new(model).check_def(&p).unwrap();
+    }
+
+    fn check_unit<M: crate::model::Model>(model: M, code: &str) -> Result<(), String> {
+        let mut tc = super::TypeChecker::new(model);
+        for def in lang::parse_unit(code).unwrap() {
+            tc.check_def(&def)?;
+        }
+        Ok(())
+    }
+
+    fn verify_unit(code: &str) {
+        check_unit(cwf_model::Cwf::new(), code).unwrap();
+        check_unit(nbe_model::NbeModel::new(), code).unwrap();
+    }
+
+    fn reject_unit(code: &str) -> String {
+        assert!(check_unit(nbe_model::NbeModel::new(), code).is_err());
+        check_unit(cwf_model::Cwf::new(), code).unwrap_err()
     }
 
     #[test]
@@ -393,4 +1009,201 @@ def eta (a : bool) : a = elim a into (_ : bool) : bool | => true | => false end
     | => refl false
     end.")
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn generated_defs() {
+        for seed in 0..200 {
+            let mut gen = DefGen::new(seed);
+            let def = gen.def();
+            let bad_def = gen.mutate(&def);
+
+            let mut tc = super::TypeChecker::new(cwf_model::Cwf::new());
+            if let Err(err) = tc.check_def(&def) {
+                panic!("seed {}: {:?} was rejected:\n{}", seed, def, err);
+            }
+            let mut tc = super::TypeChecker::new(nbe_model::NbeModel::new());
+            if let Err(err) = tc.check_def(&def) {
+                panic!("seed {}: {:?} was rejected by nbe:\n{}", seed, def, err);
+            }
+
+            let mut tc = super::TypeChecker::new(cwf_model::Cwf::new());
+            assert!(tc.check_def(&bad_def).is_err(), "seed {}: {:?} was accepted", seed, bad_def);
+            let mut tc = super::TypeChecker::new(nbe_model::NbeModel::new());
+            assert!(tc.check_def(&bad_def).is_err(),
+                    "seed {}: {:?} was accepted by nbe", seed, bad_def);
+        }
+    }
+
+    #[test]
+    fn globals() {
+        verify_unit("
+def negb (b : bool) : bool :=
+    elim b into (_ : bool) : bool | => false | => true end.
+def negb_true : negb true = false := refl false.
+def twice (b : bool) : negb (negb b) = negb (negb b) := refl (negb (negb b)).");
+        let err = reject_unit("def f : bool := true. def f : bool := false.");
+        assert_eq!(err, "f is already defined");
+        let err = reject_unit("def f (b : bool) : bool := b. def g : bool := f.");
+        assert_eq!(err, "f expects 1 explicit arguments but got 0");
+    }
+
+    #[test]
+    fn implicit_arguments() {
+        verify_unit("
+def sym {a b : bool} (p : a = b) : b = a := refl b.
+def trans {a b c : bool} (p : a = b) (q : b = c) : a = c := refl a.
+def use (x y z : bool) (p : x = y) (q : z = y) : x = z := trans p (sym q).");
+        let err = reject_unit("
+def trans {a b c : bool} (p : a = b) (q : b = c) : a = c := refl a.
+def bad (x y z : bool) (p : x = y) (q : z = z) : x = z := trans p q.");
+        assert!(err.starts_with("Cannot unify"), "{}", err);
+        let err = reject_unit("
+def id (a : bool) : bool := a.
+def bad (x : bool) (p : x = x) : bool := id p.");
+        assert!(err.starts_with("argument a of id"), "{}", err);
+        let err = reject_unit("
+def const {a : bool} : bool := true.
+def bad : bool := const.");
+        assert_eq!(err, "Cannot infer argument a of const");
+    }
+
+    #[test]
+    fn holes() {
+        verify_unit("
+def refl' (a : bool) : a = a := refl a.
+def t : true = true := refl' _.
+def f (x : bool) : x = x := refl' x.");
+        let err = reject_unit("
+def id (a : bool) : bool := a.
+def t : bool := id _.");
+        assert_eq!(err, "Unsolved holes:\n_ : bool");
+    }
+
+    #[test]
+    fn goals() {
+        let err = reject_unit("
+def negb (b : bool) : bool := elim b | => false | => true end.
+def f (x y : bool) (p : x = negb y) : negb y = x :=
+    let z := negb x in
+    elim x into (b : bool) : b = b | => ?t | => refl false end.");
+        assert_eq!(err, "\
+Unsolved holes:
+?t : true = true
+  x : bool
+  y : bool
+  p : x = elim y | => false | => true end
+  z : bool := elim x | => false | => true end");
+
+        let err = reject_unit("
+def sym {a b : bool} (p : a = b) : b = a := refl b.
+def g (x : bool) : x = true := sym ?p.");
+        assert_eq!(err, "Unsolved holes:\n?p : true = x\n  x : bool");
+        let err = reject_unit("def h : bool := let y := ?v in y.");
+        assert_eq!(err, "Cannot infer the type of ?v");
+    }
+
+    #[test]
+    fn definite_and_unknown_errors() {
+        let err = reject_unit("def f : bool := refl true.");
+        assert_eq!(err, "expected:\nBool(Empty)\ngot:\nEq(True(Empty), True(Empty))");
+        let err = reject_unit("def f (x : bool) (p : x = true) : x = false := p.");
+        assert!(err.ends_with("The model could not decide whether these are equal"), "{}", err);
+    }
+
+    #[test]
+    fn ex_falso() {
+        verify_unit("
+def absurd (x : bool) (p : true = false) : x = true := refl x.
+def later (p : true = false) (y : bool) : y = elim y | => false | => true end := refl y.");
+        // Types of different constructors are distinct even then.
+        let err = reject_unit("def f (p : true = false) : bool := refl true.");
+        assert!(err.starts_with("expected:"), "{}", err);
+    }
+
+    #[test]
+    fn bidirectional() {
+        verify_unit("
+def negb (b : bool) : bool := elim b | => false | => true end.
+def inferred_let (b : bool) : bool := let x := negb b in x.
+def inferred_motive (b : bool) : bool := let x := elim b | => true | => false end in x.
+def refl_hole (b : bool) : b = b := refl _.
+def checked_let (b : bool) : negb b = negb b := let p := refl (negb b) in p.
+def motive_from_let (a b : bool) (p : a = b) : b = a :=
+    let q : b = a := elim a | => refl _ | => refl _ end in q.");
+        // Without into, the motive cannot depend on the eliminated value.
+        let err = reject_unit("
+def eta (a : bool) : a = elim a | => true | => false end :=
+    elim a | => refl true | => refl false end.");
+        assert!(err.starts_with("expected:"), "{}", err);
+        let err = reject_unit("def f : true = true := refl true. def g : bool := refl _.");
+        assert_eq!(err, "Cannot infer _ here");
+    }
+
+    #[test]
+    fn failures_are_rolled_back() {
+        use crate::model::Model;
+        let mut tc = super::TypeChecker::new(cwf_model::Cwf::new());
+        let defs = lang::parse_unit("
+def negb (b : bool) : bool := elim b | => false | => true end.
+def bad (x : bool) (p : x = true) : x = false := refl (negb x).
+def holey (x : bool) : negb x = x := _.
+def twice (b : bool) : bool := negb (negb b).").unwrap();
+        tc.check_def(&defs[0]).unwrap();
+        let mark = tc.model().syntax().mark();
+        assert!(tc.check_def(&defs[1]).is_err());
+        assert!(tc.check_def(&defs[2]).is_err());
+        assert_eq!(tc.infer_ty(&lang::parse_expr("negb (negb true)").unwrap()),
+                   Ok("bool".to_owned()));
+        let (a, b) = lang::parse_expr_pair("(negb true) false").unwrap();
+        assert_eq!(tc.judgementally_eq(&a, &b), Ok(Equality::Equal));
+        assert_eq!(tc.model().syntax().mark(), mark);
+        tc.check_def(&defs[3]).unwrap();
+    }
+
+    #[test]
+    fn collect_garbage() {
+        use crate::lockstep_model::Lockstep;
+        use crate::model::Model;
+        let model = Lockstep::new(cwf_model::Cwf::new(), nbe_model::NbeModel::new());
+        let mut tc = super::TypeChecker::new(model);
+        let defs = lang::parse_unit("
+def negb (b : bool) : bool := elim b | => false | => true end.
+def twice (b : bool) : bool := negb (negb b).
+def thrice (b : bool) : bool := negb (twice b).
+def same : negb (negb true) = true := refl true.").unwrap();
+        tc.check_def(&defs[0]).unwrap();
+        tc.check_def(&defs[1]).unwrap();
+        tc.collect_garbage();
+        let mark = tc.model().syntax().mark();
+        tc.check_def(&defs[2]).unwrap();
+        assert!(tc.model().syntax().mark() != mark);
+        tc.undo_def();
+        tc.collect_garbage();
+        assert_eq!(tc.model().syntax().mark(), mark);
+
+        tc.check_def(&defs[2]).unwrap();
+        tc.check_def(&defs[3]).unwrap();
+        tc.collect_garbage();
+        let (a, b) = lang::parse_expr_pair("(negb (negb false)) false").unwrap();
+        assert_eq!(tc.judgementally_eq(&a, &b), Ok(Equality::Equal));
+        assert_eq!(tc.infer_ty(&lang::parse_expr("twice (thrice true)").unwrap()),
+                   Ok("bool".to_owned()));
+        assert!(tc.model().disagreements().is_empty());
+    }
+
+    #[test]
+    fn closure_limits() {
+        use crate::phl::Limits;
+        let def = lang::parse_def("def trans (a b c : bool) (p : a = b) (q : b = c) : a = c :=
+            refl a.").unwrap();
+        let mut model = cwf_model::Cwf::new();
+        model.set_limits(Limits { max_rounds: Some(1), ..Limits::default() });
+        let mut tc = super::TypeChecker::new(model);
+        assert_eq!(tc.check_def(&def).unwrap_err(),
+                   "the model gave up: no fixpoint after 1 rounds");
+
+        let mut model = cwf_model::Cwf::new();
+        model.set_limits(Limits { max_rounds: Some(100), ..Limits::default() });
+        super::TypeChecker::new(model).check_def(&def).unwrap();
+    }
+}